@@ -0,0 +1,53 @@
+//! Times a search of a corpus of many small documents with several `--chunk-size` settings.
+//!
+//! Run it with `cargo run --release --example chunk_size [DOCUMENTS]`; the corpus, 2000
+//! one-paragraph documents by default, is written to a temporary directory and removed after.
+
+use std::num::NonZeroUsize;
+use std::time::Instant;
+
+use docread::{process_files, SearchOptions, Searcher};
+use docx_rs::{Docx, Paragraph, Run};
+use regex::Regex;
+
+fn main() -> anyhow::Result<()> {
+    let documents: usize = match std::env::args().nth(1) {
+        Some(n) => n.parse()?,
+        None => 2000,
+    };
+    let dir = tempfile::tempdir()?;
+    for i in 0..documents {
+        let file = std::fs::File::create(dir.path().join(format!("doc{}.docx", i)))?;
+        Docx::new()
+            .add_paragraph(
+                Paragraph::new().add_run(Run::new().add_text(format!("Document number {}", i))),
+            )
+            .build()
+            .pack(file)?;
+    }
+    // nothing matches, so that the time is spent finding, reading and parsing documents
+    let searcher = Searcher::from(Regex::new("no such text")?);
+    let base_dir = dir.path().to_str().unwrap();
+    for chunk_size in [None, Some(4), Some(16), Some(64), Some(256)] {
+        let mut opts = SearchOptions::default();
+        opts.chunk_size = chunk_size.and_then(NonZeroUsize::new);
+        // the best of a few runs, to smooth out the noise of a busy machine
+        let best = (0..3)
+            .map(|_| {
+                let started = Instant::now();
+                process_files(base_dir, &searcher, &opts).map(|_| started.elapsed())
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .into_iter()
+            .min()
+            .unwrap();
+        let label = chunk_size.map_or("unset".to_string(), |n| n.to_string());
+        println!(
+            "--chunk-size {:>5}: {:>8.1} ms for {} documents",
+            label,
+            best.as_secs_f64() * 1000.0,
+            documents
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,97 @@
+//! Times the search of a document's worth of runs for an alternation of plain words, with the regex
+//! engine and with the Aho-Corasick automaton that `build_searcher` can use instead, for several
+//! numbers of words. `LITERALS_THRESHOLD` in src/matcher.rs is set from what this shows.
+//!
+//! Run it with `cargo run --release --example literals`.
+
+use std::time::{Duration, Instant};
+
+use aho_corasick::{AhoCorasick, AhoCorasickKind, MatchKind};
+use regex::Regex;
+
+/// Returns `count` pseudo-random six-letter lowercase words, the same ones on every run.
+fn words(count: usize, seed: &mut u64) -> Vec<String> {
+    (0..count)
+        .map(|_| {
+            (0..6)
+                .map(|_| {
+                    // a 64-bit linear congruential generator, good enough for test data
+                    *seed = seed
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    (b'a' + ((*seed >> 33) % 26) as u8) as char
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Returns the best of a few timings of `search`, to smooth out the noise of a busy machine.
+fn best_of(mut search: impl FnMut() -> usize) -> (Duration, usize) {
+    (0..5)
+        .map(|_| {
+            let started = Instant::now();
+            let found = search();
+            (started.elapsed(), found)
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() -> anyhow::Result<()> {
+    let mut seed = 1;
+    // about 2.8 MB of text drawn from a vocabulary of 20000 words, some of which are searched for
+    let vocabulary = words(20_000, &mut seed);
+    let mut pick = |count: usize| -> Vec<String> {
+        words(count, &mut seed)
+            .iter()
+            .map(|word| {
+                let index = word
+                    .bytes()
+                    .fold(0usize, |n, b| n * 26 + (b - b'a') as usize);
+                vocabulary[index % vocabulary.len()].clone()
+            })
+            .collect()
+    };
+    let text = pick(400_000);
+    // runs of 12 words, about the length of a sentence
+    let runs: Vec<String> = text.chunks(12).map(|run| run.join(" ")).collect();
+    for count in [10, 50, 100, 200, 500, 1000, 2000, 3000, 5000, 10000] {
+        let literals = pick(count);
+        let started = Instant::now();
+        let regex = Regex::new(&literals.join("|"))?;
+        let regex_build = started.elapsed();
+        let started = Instant::now();
+        let automaton = AhoCorasick::builder()
+            .match_kind(MatchKind::LeftmostFirst)
+            .kind(Some(AhoCorasickKind::DFA))
+            .build(&literals)?;
+        let automaton_build = started.elapsed();
+        // searched a run at a time, as documents are
+        let (regex_time, regex_found) = best_of(|| {
+            runs.iter()
+                .filter(|run| regex.is_match(run))
+                .map(|run| regex.find_iter(run).count())
+                .sum()
+        });
+        let (automaton_time, automaton_found) = best_of(|| {
+            runs.iter()
+                .filter(|run| automaton.is_match(run.as_str()))
+                .map(|run| automaton.find_iter(run.as_str()).count())
+                .sum()
+        });
+        assert_eq!(regex_found, automaton_found);
+        let ms = |time: Duration| time.as_secs_f64() * 1000.0;
+        println!(
+            "{:>5} literals: regex {:>6.1} ms + {:>5.1} ms to build, automaton {:>6.1} ms + {:>5.1} \
+             ms to build ({} matches)",
+            count,
+            ms(regex_time),
+            ms(regex_build),
+            ms(automaton_time),
+            ms(automaton_build),
+            regex_found
+        );
+    }
+    Ok(())
+}
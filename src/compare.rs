@@ -0,0 +1,270 @@
+use colored::Colorize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use crate::matcher::Searcher;
+use crate::reader::{search_documents, SearchOptions, SearchResult};
+
+/// How the matches of one document changed from `--dir` to the directory it is compared with.
+#[derive(Debug, Default, PartialEq)]
+pub struct FileDelta {
+    /// Path of the document relative to the directory it was found in, with archive entries
+    /// under their archive
+    pub path: PathBuf,
+    /// Number of matches in the document under `--dir`, or `None` if it is not there
+    pub before: Option<usize>,
+    /// Number of matches in the document under the other directory, or `None` if it is not there
+    pub after: Option<usize>,
+    /// The text of the matched runs found only under the other directory
+    pub added: Vec<String>,
+    /// The text of the matched runs found only under `--dir`
+    pub removed: Vec<String>,
+}
+
+impl FileDelta {
+    /// Returns true if the document is in both directories with the same matched runs.
+    pub fn is_unchanged(&self) -> bool {
+        self.before.is_some()
+            && self.after.is_some()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+    }
+}
+
+/// The matched runs of a document, and the number of matches in them.
+type Matched = (Vec<String>, usize);
+
+/// Returns the matched runs of each document searched in `results`, keyed by its path relative
+/// to `base_dir`, and the set of those paths whose document could not be searched, whose
+/// errors are printed to standard error.
+fn matched_by_path(
+    results: Vec<SearchResult>,
+    base_dir: &str,
+    searcher: &Searcher,
+) -> (BTreeMap<PathBuf, Matched>, BTreeSet<PathBuf>) {
+    let mut matched = BTreeMap::new();
+    let mut errored = BTreeSet::new();
+    for result in results {
+        let path = result
+            .source_path
+            .strip_prefix(Path::new(base_dir))
+            .map(Path::to_path_buf)
+            .unwrap_or(result.source_path);
+        match result.maybe_result {
+            Ok(parsed) => {
+                let nmatches = parsed
+                    .runs
+                    .iter()
+                    .map(|run| searcher.find_spans(&run.text).len())
+                    .sum();
+                let texts = parsed.runs.into_iter().map(|run| run.text).collect();
+                matched.insert(path, (texts, nmatches));
+            }
+            Err(e) => {
+                eprintln!("{:?}\n", e);
+                errored.insert(path);
+            }
+        }
+    }
+    (matched, errored)
+}
+
+/// Returns the texts of `from` that are not matched, one for one, by an equal text of `to`.
+fn missing_from(from: &[String], to: &[String]) -> Vec<String> {
+    let mut available: BTreeMap<&str, usize> = BTreeMap::new();
+    for text in to {
+        *available.entry(text).or_default() += 1;
+    }
+    from.iter()
+        .filter(|text| match available.get_mut(text.as_str()) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                false
+            }
+            _ => true,
+        })
+        .cloned()
+        .collect()
+}
+
+/// Pairs the documents matched under `before` and `after`, as returned by `matched_by_path`,
+/// by their relative path, and returns how the matches of each changed, in path order.
+fn pair_documents(
+    mut before: BTreeMap<PathBuf, Matched>,
+    mut after: BTreeMap<PathBuf, Matched>,
+) -> Vec<FileDelta> {
+    let paths: BTreeSet<PathBuf> = before.keys().chain(after.keys()).cloned().collect();
+    paths
+        .into_iter()
+        .map(|path| {
+            let (old_runs, before) = before.remove(&path).unzip();
+            let (new_runs, after) = after.remove(&path).unzip();
+            let (old_runs, new_runs) = (old_runs.unwrap_or_default(), new_runs.unwrap_or_default());
+            FileDelta {
+                added: missing_from(&new_runs, &old_runs),
+                removed: missing_from(&old_runs, &new_runs),
+                path,
+                before,
+                after,
+            }
+        })
+        .collect()
+}
+
+/// Searches the documents under both `base_dir` and `other_dir` with `searcher`, as
+/// `process_files` does, pairs them by their path relative to the directory they were found
+/// in, and returns how the matches of each document changed from `base_dir` to `other_dir`,
+/// in path order. A run counts as added or removed if its text is matched in only one of the
+/// two copies of a document, as many times as it is matched more often in that copy. A
+/// document that could not be searched in either directory is left out, and its error is
+/// printed to standard error.
+///
+/// # Errors
+///
+/// Will return an error if the files under either directory could not be listed, or, if
+/// `opts.fail_fast` is set, if a document could not be searched.
+pub fn compare_dirs(
+    base_dir: &str,
+    other_dir: &str,
+    searcher: &Searcher,
+    opts: &SearchOptions,
+) -> anyhow::Result<Vec<FileDelta>> {
+    let (before, mut errored) = matched_by_path(
+        search_documents(base_dir, searcher, opts)?,
+        base_dir,
+        searcher,
+    );
+    let (after, other_errored) = matched_by_path(
+        search_documents(other_dir, searcher, opts)?,
+        other_dir,
+        searcher,
+    );
+    errored.extend(other_errored);
+    let mut deltas = pair_documents(before, after);
+    deltas.retain(|delta| !errored.contains(&delta.path));
+    Ok(deltas)
+}
+
+/// Formats a match count for `format_delta`, or `-` for a document that is not there.
+fn format_count(count: Option<usize>) -> String {
+    count.map_or_else(|| "-".to_string(), |count| count.to_string())
+}
+
+/// Formats `delta` for display: the document's path and its match counts before and after,
+/// followed by the text of each added run, marked `+`, and of each removed run, marked `-`,
+/// and then by `separator`, if any, as `format_result` ends the output of a file.
+pub(crate) fn format_delta(delta: &FileDelta, separator: Option<&str>) -> String {
+    let mut out = format!(
+        "Compared file--> {}: {} -> {} matches (+{} -{} runs)\n\n",
+        delta.path.display().to_string().bright_red().on_black(),
+        format_count(delta.before),
+        format_count(delta.after),
+        delta.added.len(),
+        delta.removed.len()
+    );
+    for text in &delta.added {
+        out.push_str(&format!("  {} {}\n", "+".green().bold(), text));
+    }
+    for text in &delta.removed {
+        out.push_str(&format!("  {} {}\n", "-".red().bold(), text));
+    }
+    if !delta.added.is_empty() || !delta.removed.is_empty() {
+        out.push('\n');
+    }
+    if let Some(separator) = separator {
+        out.push_str(&format!("{}\n\n", separator));
+    }
+    out
+}
+
+/// Prints the documents of `deltas` whose matches changed (see `format_delta`), followed by a
+/// count of the documents compared and of those that changed. Each document's output ends with
+/// `separator`, if any.
+pub fn print_deltas(deltas: &[FileDelta], separator: Option<&str>) {
+    let changed: Vec<&FileDelta> = deltas.iter().filter(|d| !d.is_unchanged()).collect();
+    for delta in &changed {
+        print!("{}", format_delta(delta, separator));
+    }
+    println!(
+        "Compared {} documents: {} changed, {} unchanged\n",
+        deltas.len(),
+        changed.len(),
+        deltas.len() - changed.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    #[test]
+    fn test_pair_documents() {
+        let matched = |runs: &[&str], nmatches| -> Matched {
+            (runs.iter().map(|text| text.to_string()).collect(), nmatches)
+        };
+        let before = BTreeMap::from([
+            (PathBuf::from("a.docx"), matched(&["x", "y", "y"], 3)),
+            (PathBuf::from("gone.docx"), matched(&["x"], 1)),
+            (PathBuf::from("same.docx"), matched(&["x"], 1)),
+        ]);
+        let after = BTreeMap::from([
+            (PathBuf::from("a.docx"), matched(&["y", "z", "x"], 3)),
+            (PathBuf::from("new.docx"), matched(&[], 0)),
+            (PathBuf::from("same.docx"), matched(&["x"], 1)),
+        ]);
+        let deltas = pair_documents(before, after);
+        let paths: Vec<&Path> = deltas.iter().map(|d| d.path.as_path()).collect();
+        assert_eq!(
+            paths,
+            ["a.docx", "gone.docx", "new.docx", "same.docx"].map(Path::new)
+        );
+        assert_eq!(deltas[0].added, ["z"]);
+        assert_eq!(deltas[0].removed, ["y"]);
+        assert_eq!((deltas[1].before, deltas[1].after), (Some(1), None));
+        assert_eq!(deltas[1].removed, ["x"]);
+        assert!(!deltas[2].is_unchanged());
+        assert!(deltas[3].is_unchanged());
+    }
+
+    #[test]
+    fn test_compare_dirs() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let other = dir.path().join("other");
+        std::fs::create_dir_all(other.join("sub"))?;
+        std::fs::copy("resources/testdoc.docx", other.join("sub/testdoc.docx"))?;
+        std::fs::copy("resources/TestArchive.zip", other.join("TestArchive.zip"))?;
+        let searcher = Searcher::from(Regex::new("plain")?);
+        let deltas = compare_dirs(
+            "resources",
+            other.to_str().unwrap(),
+            &searcher,
+            &SearchOptions::default(),
+        )?;
+        let summary: Vec<(PathBuf, Option<usize>, Option<usize>)> = deltas
+            .iter()
+            .map(|d| (d.path.clone(), d.before, d.after))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (PathBuf::from("BookNotes.docx"), Some(0), None),
+                (
+                    PathBuf::from("TestArchive.zip/BookNotes.docx"),
+                    Some(0),
+                    Some(0)
+                ),
+                (
+                    PathBuf::from("TestArchive.zip/testdoc.docx"),
+                    Some(1),
+                    Some(1)
+                ),
+                (PathBuf::from("sub/testdoc.docx"), None, Some(1)),
+                (PathBuf::from("testdoc.docx"), Some(1), None),
+            ]
+        );
+        assert!(deltas[2].is_unchanged());
+        assert_eq!(deltas[3].added, ["This is a plain paragraph."]);
+        Ok(())
+    }
+}
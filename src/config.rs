@@ -0,0 +1,346 @@
+use anyhow::Context;
+use clap::{ArgAction, Command};
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// The name of the config file looked for in the current directory, then the home directory.
+pub(crate) const CONFIG_FILE: &str = "docread.toml";
+
+/// A value in the config file.
+#[derive(Debug, Clone, PartialEq)]
+enum ConfigValue {
+    Bool(bool),
+    /// A string, or a number kept as it was written
+    Text(String),
+    Array(Vec<ConfigValue>),
+}
+
+/// Returns the config file to use when none is named: `docread.toml` in the current directory
+/// if there is one, or else in the home directory, or `None` if there is neither.
+pub fn find_config() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+    [Some(PathBuf::from(".")), home.map(PathBuf::from)]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join(CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+/// Reads the config file at `path` and turns its settings into command line arguments for
+/// `command`, to be put in front of the real ones. Each `key = value` line sets the long option
+/// of the same name, with `_` and `-` alike: `true` turns a flag on (`false` leaves it off), an
+/// array gives an option several values, and any other value is passed as it is. Settings for
+/// the options that `given` says are on the command line are left out, and so are those for
+/// options that conflict either way with one on the command line, so that the command line
+/// overrides the config file.
+///
+/// # Errors
+///
+/// Will return an error if the file cannot be read, is not in the subset of TOML that
+/// `parse_config` accepts, or sets an option that does not exist or is given the wrong kind of
+/// value.
+pub fn config_args(
+    command: &Command,
+    path: &Path,
+    given: impl Fn(&str) -> bool,
+) -> anyhow::Result<Vec<OsString>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+    let settings =
+        parse_config(&text).with_context(|| format!("Invalid config file: {}", path.display()))?;
+    let mut args = Vec::new();
+    for (key, value) in settings {
+        let name = key.replace('_', "-");
+        let invalid = |expected: &str| {
+            anyhow::anyhow!(
+                "Invalid config file: {}: '{}' expects {}",
+                path.display(),
+                key,
+                expected
+            )
+        };
+        let Some(arg) = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(name.as_str()))
+            .filter(|arg| !matches!(arg.get_id().as_str(), "config" | "no_config"))
+        else {
+            anyhow::bail!(
+                "Invalid config file: {}: unknown option '{}'",
+                path.display(),
+                key
+            );
+        };
+        let conflicts_with_given = command
+            .get_arg_conflicts_with(arg)
+            .iter()
+            .any(|other| given(other.get_id().as_str()))
+            || command.get_arguments().any(|other| {
+                given(other.get_id().as_str())
+                    && command
+                        .get_arg_conflicts_with(other)
+                        .iter()
+                        .any(|conflict| conflict.get_id() == arg.get_id())
+            });
+        if given(arg.get_id().as_str()) || conflicts_with_given {
+            continue;
+        }
+        let flag = OsString::from(format!("--{}", name));
+        match (arg.get_action(), value) {
+            (ArgAction::SetTrue, ConfigValue::Bool(true)) => args.push(flag),
+            (ArgAction::SetTrue, ConfigValue::Bool(false)) => {}
+            (ArgAction::SetTrue, _) => return Err(invalid("true or false")),
+            (ArgAction::Help | ArgAction::Version, _) => {
+                anyhow::bail!(
+                    "Invalid config file: {}: unknown option '{}'",
+                    path.display(),
+                    key
+                )
+            }
+            (_, ConfigValue::Bool(_)) => return Err(invalid("a value")),
+            (_, ConfigValue::Text(text)) => args.extend([flag, text.into()]),
+            (_, ConfigValue::Array(items)) => {
+                let mut texts = Vec::new();
+                for item in items {
+                    match item {
+                        ConfigValue::Text(text) => texts.push(text),
+                        _ => return Err(invalid("an array of strings or numbers")),
+                    }
+                }
+                args.push(flag);
+                match arg.get_value_delimiter() {
+                    Some(delimiter) => args.push(texts.join(&delimiter.to_string()).into()),
+                    None => args.extend(texts.into_iter().map(OsString::from)),
+                }
+            }
+        }
+    }
+    Ok(args)
+}
+
+/// Parses the settings of a config file, in order. Only the subset of TOML that options need is
+/// accepted: one `key = value` per line, where the value is a string (in double quotes with
+/// backslash escapes, or in single quotes taken literally), a number, `true` or `false`, or an
+/// array of those on the same line. Blank lines and `#` comments are skipped; tables are not
+/// allowed, and neither is setting a key twice.
+fn parse_config(text: &str) -> anyhow::Result<Vec<(String, ConfigValue)>> {
+    let mut settings = Vec::new();
+    let mut keys = HashSet::new();
+    for (number, line) in text.lines().enumerate() {
+        let line_error = |message: &str| anyhow::anyhow!("line {}: {}", number + 1, message);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            return Err(line_error("tables are not supported"));
+        }
+        let (key, rest) = line
+            .split_once('=')
+            .ok_or_else(|| line_error("expected key = value"))?;
+        let key = key.trim();
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(line_error("expected a bare key"));
+        }
+        let (value, rest) = parse_value(rest.trim_start()).map_err(|e| line_error(&e))?;
+        let rest = rest.trim_start();
+        if !rest.is_empty() && !rest.starts_with('#') {
+            return Err(line_error("unexpected text after the value"));
+        }
+        if !keys.insert(key.replace('_', "-")) {
+            return Err(line_error(&format!("'{}' is set twice", key)));
+        }
+        settings.push((key.to_string(), value));
+    }
+    Ok(settings)
+}
+
+/// Parses the value at the start of `s`, returning it with the rest of `s`.
+fn parse_value(s: &str) -> Result<(ConfigValue, &str), String> {
+    if let Some(rest) = s.strip_prefix('"') {
+        let mut text = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((ConfigValue::Text(text), &rest[i + 1..])),
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('u') => {
+                            let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or("invalid \\u escape")?
+                        }
+                        _ => return Err("invalid escape in string".to_string()),
+                    };
+                    text.push(escaped);
+                }
+                c => text.push(c),
+            }
+        }
+        return Err("unterminated string".to_string());
+    }
+    if let Some(rest) = s.strip_prefix('\'') {
+        let end = rest.find('\'').ok_or("unterminated string")?;
+        return Ok((ConfigValue::Text(rest[..end].to_string()), &rest[end + 1..]));
+    }
+    if let Some(mut rest) = s.strip_prefix('[') {
+        let mut items = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((ConfigValue::Array(items), after));
+            }
+            let (item, after) = parse_value(rest)?;
+            items.push(item);
+            rest = after.trim_start();
+            match rest.strip_prefix(',') {
+                Some(after) => rest = after,
+                None if rest.starts_with(']') => {}
+                None => return Err("expected , or ] in array".to_string()),
+            }
+        }
+    }
+    let end = s
+        .find(|c: char| c.is_whitespace() || c == ',' || c == ']' || c == '#')
+        .unwrap_or(s.len());
+    let (word, rest) = s.split_at(end);
+    match word {
+        "true" => Ok((ConfigValue::Bool(true), rest)),
+        "false" => Ok((ConfigValue::Bool(false), rest)),
+        _ if word.replace('_', "").parse::<f64>().is_ok() => {
+            Ok((ConfigValue::Text(word.replace('_', "")), rest))
+        }
+        "" => Err("expected a value".to_string()),
+        _ => Err(format!("invalid value '{}'", word)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Arg;
+
+    fn text(s: &str) -> ConfigValue {
+        ConfigValue::Text(s.to_string())
+    }
+
+    #[test]
+    fn test_parse_config() -> anyhow::Result<()> {
+        let settings = parse_config(
+            "# defaults\n\
+             context = 100\n\
+             context_unit = \"bytes\"  # or chars\n\
+             show-heading = true\n\
+             \n\
+             entity = ['email', \"phone\\t\",]\n",
+        )?;
+        assert_eq!(
+            settings,
+            vec![
+                ("context".to_string(), text("100")),
+                ("context_unit".to_string(), text("bytes")),
+                ("show-heading".to_string(), ConfigValue::Bool(true)),
+                (
+                    "entity".to_string(),
+                    ConfigValue::Array(vec![text("email"), text("phone\t")])
+                ),
+            ]
+        );
+        assert!(parse_config("[search]\n").is_err());
+        assert!(parse_config("context = 1\ncontext = 2\n").is_err());
+        assert!(parse_config("context = \"100\n").is_err());
+        assert!(parse_config("context = bytes\n").is_err());
+        assert!(parse_config("context = 1 2\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_args() -> anyhow::Result<()> {
+        let command = Command::new("docread")
+            .arg(Arg::new("context").long("context").short('c'))
+            .arg(
+                Arg::new("show_heading")
+                    .long("show-heading")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(Arg::new("quiet").long("quiet").action(ArgAction::SetTrue))
+            .arg(Arg::new("entity").long("entity").value_delimiter(','))
+            .arg(Arg::new("between").long("between").num_args(2));
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(CONFIG_FILE);
+        std::fs::write(
+            &path,
+            "context = 100\nshow_heading = true\nquiet = false\n\
+             entity = [\"email\", \"phone\"]\nbetween = [\"From:\", \"To:\"]\n",
+        )?;
+        let args = config_args(&command, &path, |_| false)?;
+        let expected = [
+            "--context",
+            "100",
+            "--show-heading",
+            "--entity",
+            "email,phone",
+            "--between",
+            "From:",
+            "To:",
+        ];
+        assert_eq!(args, expected.map(OsString::from));
+
+        // options on the command line win
+        let args = config_args(&command, &path, |id| id == "context")?;
+        assert_eq!(args[0], "--show-heading");
+
+        // and so do those that conflict with options in the config file, either way
+        let conflicting = Command::new("docread")
+            .arg(Arg::new("context").long("context"))
+            .arg(
+                Arg::new("no_context")
+                    .long("no-context")
+                    .action(ArgAction::SetTrue)
+                    .conflicts_with("context"),
+            )
+            .arg(
+                Arg::new("summary")
+                    .long("summary")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("no_summary")
+                    .long("no-summary")
+                    .action(ArgAction::SetTrue)
+                    .conflicts_with("summary"),
+            )
+            .arg(Arg::new("align").long("align"))
+            .arg(Arg::new("center").long("center").conflicts_with("align"));
+        std::fs::write(&path, "context = 30\nsummary = true\nalign = 40\n")?;
+        let args = config_args(&conflicting, &path, |id| id == "no_context")?;
+        assert_eq!(args, ["--summary", "--align", "40"].map(OsString::from));
+        let args = config_args(&conflicting, &path, |id| id == "no_summary")?;
+        assert_eq!(
+            args,
+            ["--context", "30", "--align", "40"].map(OsString::from)
+        );
+        let args = config_args(&conflicting, &path, |id| id == "center")?;
+        assert_eq!(args, ["--context", "30", "--summary"].map(OsString::from));
+        std::fs::write(&path, "no_context = true\ncenter = 60\n")?;
+        let args = config_args(&conflicting, &path, |id| matches!(id, "context" | "align"))?;
+        assert!(args.is_empty());
+
+        std::fs::write(&path, "colour = true\n")?;
+        assert!(config_args(&command, &path, |_| false).is_err());
+        std::fs::write(&path, "show_heading = 1\n")?;
+        assert!(config_args(&command, &path, |_| false).is_err());
+        Ok(())
+    }
+}
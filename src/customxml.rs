@@ -0,0 +1,37 @@
+use crate::rawxml::{part_texts, xtract_xml_values};
+
+/// The folder of a .docx package that holds its custom XML data parts.
+const CUSTOM_XML_DIR: &str = "customXml/";
+
+/// Returns the number of the custom XML data part called `name`, such as 3 for
+/// `customXml/item3.xml`, or `None` if `name` is not one. The `itemPropsN.xml` parts that
+/// describe them, and their relationships, are not data parts.
+fn item_number(name: &str) -> Option<u32> {
+    name.strip_prefix(CUSTOM_XML_DIR)?
+        .strip_prefix("item")?
+        .strip_suffix(".xml")?
+        .parse()
+        .ok()
+}
+
+/// Returns the name and data values (see `xtract_xml_values`) of each custom XML data part in
+/// the .docx package held in `buffer`, in the order of their numbers. Parts that cannot be read
+/// are skipped.
+pub(crate) fn custom_xml_texts(buffer: &[u8]) -> Vec<(String, Vec<String>)> {
+    part_texts(buffer, item_number, |_, bytes| {
+        Some(xtract_xml_values(&String::from_utf8_lossy(bytes)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_item_number() {
+        assert_eq!(item_number("customXml/item2.xml"), Some(2));
+        assert_eq!(item_number("customXml/itemProps2.xml"), None);
+        assert_eq!(item_number("customXml/_rels/item2.xml.rels"), None);
+        assert_eq!(item_number("word/item2.xml"), None);
+    }
+}
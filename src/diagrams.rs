@@ -0,0 +1,98 @@
+use crate::rawxml::{part_texts, xtract_matching_elements};
+
+/// What kind of graphic a package part holds the data of, as found by `graphic_kind`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GraphicKind {
+    /// The data of a SmartArt diagram, such as `word/diagrams/data1.xml`
+    SmartArt,
+    /// A chart, such as `word/charts/chart1.xml`
+    Chart,
+}
+
+/// Returns the kind of graphic whose data the package part called `name` holds, or `None` if
+/// it is not the data part of a SmartArt diagram or a chart. The layout, style and color parts
+/// of a diagram, and the style and color parts of a chart, hold no text of the document.
+fn graphic_kind(name: &str) -> Option<GraphicKind> {
+    let (folder, file_name) = name.rsplit_once('/')?;
+    let number = |prefix: &str| {
+        file_name
+            .strip_prefix(prefix)?
+            .strip_suffix(".xml")
+            .filter(|digits| digits.chars().all(|c| c.is_ascii_digit()))
+    };
+    if folder.ends_with("diagrams") && number("data").is_some() {
+        Some(GraphicKind::SmartArt)
+    } else if folder.ends_with("charts") && number("chart").is_some() {
+        Some(GraphicKind::Chart)
+    } else {
+        None
+    }
+}
+
+/// Returns the label of the graphic part called `name`, such as "smartart word/diagrams/data1.xml"
+/// or "chart word/charts/chart1.xml", as matches in it are shown, or `None` if it is neither.
+pub(crate) fn graphic_label(name: &str) -> Option<String> {
+    let kind = match graphic_kind(name)? {
+        GraphicKind::SmartArt => "smartart",
+        GraphicKind::Chart => "chart",
+    };
+    Some(format!("{} {}", kind, name))
+}
+
+/// Returns the texts of the graphic part `xml` of the given kind, in document order: the
+/// `<a:t>` text of a SmartArt diagram's nodes, and of a chart's title and axis titles along
+/// with the `<c:v>` values it caches, such as its series names, categories and figures.
+fn graphic_texts(xml: &str, kind: GraphicKind) -> Vec<String> {
+    xtract_matching_elements(xml, |name| {
+        name == "a:t" || (kind == GraphicKind::Chart && name == "c:v")
+    })
+}
+
+/// Returns the name and texts (see `graphic_texts`) of each SmartArt diagram and chart of the
+/// .docx package held in `buffer`, in name order. Parts that cannot be read are skipped, and a
+/// malformed part yields only the texts found before the point where it goes wrong.
+pub(crate) fn diagram_texts(buffer: &[u8]) -> Vec<(String, Vec<String>)> {
+    part_texts(
+        buffer,
+        |name| graphic_kind(name).map(|_| ()),
+        |name, bytes| {
+            let kind = graphic_kind(name)?;
+            Some(graphic_texts(&String::from_utf8_lossy(bytes), kind))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_graphic_kind() {
+        assert_eq!(
+            graphic_kind("word/diagrams/data1.xml"),
+            Some(GraphicKind::SmartArt)
+        );
+        assert_eq!(
+            graphic_kind("word/charts/chart12.xml"),
+            Some(GraphicKind::Chart)
+        );
+        assert_eq!(graphic_kind("word/diagrams/layout1.xml"), None);
+        assert_eq!(graphic_kind("word/charts/colors1.xml"), None);
+        assert_eq!(graphic_kind("word/charts/_rels/chart1.xml.rels"), None);
+        assert_eq!(graphic_kind("word/document.xml"), None);
+    }
+
+    #[test]
+    fn test_graphic_texts() {
+        let chart = r#"<c:chartSpace><c:chart><c:title><c:tx><c:rich><a:p><a:r><a:t>Q4 Revenue</a:t></a:r></a:p></c:rich></c:tx></c:title><c:ser><c:tx><c:strRef><c:strCache><c:pt idx="0"><c:v>EMEA</c:v></c:pt></c:strCache></c:strRef></c:tx></c:ser></c:chart></c:chartSpace>"#;
+        assert_eq!(
+            graphic_texts(chart, GraphicKind::Chart),
+            ["Q4 Revenue", "EMEA"]
+        );
+        assert_eq!(graphic_texts(chart, GraphicKind::SmartArt), ["Q4 Revenue"]);
+        // a part cut off in the middle gives what comes before the break
+        let malformed =
+            "<dgm:dataModel><dgm:pt><dgm:t><a:p><a:r><a:t>Plan</a:t></a:r><a:r><a:t>Bui";
+        assert_eq!(graphic_texts(malformed, GraphicKind::SmartArt), ["Plan"]);
+    }
+}
@@ -0,0 +1,157 @@
+use anyhow::Context;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// A word list that `--unknown-words` checks the words of each run against. Words are kept
+/// case-folded, so that a word is known whatever its case in the document or in the list.
+#[derive(Debug, Clone, Default)]
+pub struct Dictionary {
+    words: HashSet<String>,
+}
+
+impl Dictionary {
+    /// Reads the dictionary at `path`: one word per line, as in `/usr/share/dict/words`. Only
+    /// the first whitespace-separated field of a line is taken, and only up to a `/`, so that
+    /// Hunspell `.dic` files, whose words carry `/FLAGS` and whose first line is the number of
+    /// words, can be used as they are; a first line of nothing but digits, blank lines and
+    /// lines starting with `#` are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the file cannot be read.
+    pub fn load(path: &Path) -> anyhow::Result<Dictionary> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read dictionary: {}", path.display()))?;
+        let mut lines = text.lines().peekable();
+        // the word count that starts a .dic file
+        lines.next_if(|line| {
+            let line = line.trim();
+            !line.is_empty() && line.bytes().all(|b| b.is_ascii_digit())
+        });
+        let words = lines
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .filter_map(|line| line.split_whitespace().next())
+            .filter_map(|field| field.split('/').next())
+            .filter(|word| !word.is_empty());
+        Ok(Dictionary::from_words(words))
+    }
+
+    /// Returns a dictionary of `words`.
+    pub fn from_words<'a>(words: impl IntoIterator<Item = &'a str>) -> Dictionary {
+        Dictionary {
+            words: words.into_iter().map(fold).collect(),
+        }
+    }
+
+    /// Returns the number of distinct words in the dictionary.
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Returns true if the dictionary has no words.
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Returns true if `word` is in the dictionary, whatever its case, or if it is a possessive
+    /// (`dog's`) of a word that is.
+    pub fn contains(&self, word: &str) -> bool {
+        let word = fold(word);
+        self.words.contains(&word)
+            || word
+                .strip_suffix("'s")
+                .is_some_and(|stem| self.words.contains(stem))
+    }
+
+    /// Returns the byte spans of the words of `text` that are not in the dictionary, in order
+    /// (see `word_spans`).
+    pub fn unknown_word_spans(&self, text: &str) -> Vec<(usize, usize)> {
+        word_spans(text)
+            .into_iter()
+            .filter(|&(start, end)| !self.contains(&text[start..end]))
+            .collect()
+    }
+}
+
+/// Returns `word` case-folded, with typographic apostrophes made plain.
+fn fold(word: &str) -> String {
+    word.to_lowercase().replace('\u{2019}', "'")
+}
+
+/// Returns true if `c` can be inside a word: an apostrophe, as in "don't", or a hyphen, as in
+/// "well-known".
+fn is_joiner(c: char) -> bool {
+    matches!(c, '\'' | '\u{2019}' | '-')
+}
+
+/// Returns the byte spans of the words of `text`, in order. A word is a run of letters, which
+/// may be joined by single apostrophes or hyphens; the punctuation around it is left out.
+/// Runs of characters that hold digits, such as "3rd" or "A4", are not words.
+pub(crate) fn word_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if !c.is_alphanumeric() {
+            continue;
+        }
+        let mut end = start + c.len_utf8();
+        let mut has_digit = c.is_numeric();
+        while let Some(&(i, c)) = chars.peek() {
+            if c.is_alphanumeric() {
+                has_digit |= c.is_numeric();
+                end = i + c.len_utf8();
+                chars.next();
+            } else if is_joiner(c)
+                && text[i + c.len_utf8()..]
+                    .chars()
+                    .next()
+                    .is_some_and(char::is_alphanumeric)
+            {
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        if !has_digit {
+            spans.push((start, end));
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_spans() {
+        let text = "\"Well-known,\" she said—don’t test 3rd-party A4 paper.";
+        let words: Vec<&str> = word_spans(text)
+            .into_iter()
+            .map(|(start, end)| &text[start..end])
+            .collect();
+        assert_eq!(
+            words,
+            ["Well-known", "she", "said", "don’t", "test", "paper"]
+        );
+    }
+
+    #[test]
+    fn test_unknown_word_spans() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("words.dic");
+        std::fs::write(&path, "4\n# a comment\nthe\ncat/S\nsat\n\ndon't\n")?;
+        let dictionary = Dictionary::load(&path)?;
+        // the word count on the first line is not a word
+        assert_eq!(dictionary.len(), 4);
+        assert!(!dictionary.contains("4"));
+        let text = "The cat’s hat: Don’t sat on teh mat.";
+        let unknown: Vec<&str> = dictionary
+            .unknown_word_spans(text)
+            .into_iter()
+            .map(|(start, end)| &text[start..end])
+            .collect();
+        assert_eq!(unknown, ["hat", "on", "teh", "mat"]);
+        Ok(())
+    }
+}
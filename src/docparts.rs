@@ -0,0 +1,52 @@
+use std::path::Path;
+
+use crate::rawxml::{part_texts, xtract_raw_text, DOCUMENT_PART};
+
+/// Returns true if the package part called `name` is a WordprocessingML document body other
+/// than the main one, such as the glossary of building blocks in `word/glossary/document.xml`
+/// or a further body such as `word/document2.xml`: a part named `document.xml`, or
+/// `documentN.xml`, outside the `_rels` folders that describe the parts.
+fn is_other_document_part(name: &str) -> bool {
+    let path = Path::new(name);
+    let Some(stem) = path
+        .file_name()
+        .and_then(|file_name| file_name.to_str()?.strip_suffix(".xml"))
+        .and_then(|stem| stem.strip_prefix("document"))
+    else {
+        return false;
+    };
+    name != DOCUMENT_PART
+        && stem.chars().all(|c| c.is_ascii_digit())
+        && !path
+            .components()
+            .any(|component| component.as_os_str() == "_rels")
+}
+
+/// Returns the name and `<w:t>` texts (see `xtract_raw_text`) of each document body of the .docx
+/// package held in `buffer` other than `word/document.xml`, in name order. Parts that cannot be
+/// read are skipped.
+pub(crate) fn other_document_texts(buffer: &[u8]) -> Vec<(String, Vec<String>)> {
+    part_texts(
+        buffer,
+        |name| is_other_document_part(name).then_some(()),
+        |_, bytes| Some(xtract_raw_text(&String::from_utf8_lossy(bytes))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_other_document_part() {
+        assert!(is_other_document_part("word/glossary/document.xml"));
+        assert!(is_other_document_part("word/document2.xml"));
+        assert!(!is_other_document_part("word/document.xml"));
+        assert!(!is_other_document_part("word/_rels/document2.xml"));
+        assert!(!is_other_document_part(
+            "word/glossary/_rels/document.xml.rels"
+        ));
+        assert!(!is_other_document_part("word/documentation.xml"));
+        assert!(!is_other_document_part("word/styles.xml"));
+    }
+}
@@ -0,0 +1,122 @@
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+
+use crate::rawxml::{part_texts, xtract_element_text};
+
+/// The folder of a .docx package that holds embedded objects.
+const EMBEDDINGS_DIR: &str = "word/embeddings/";
+
+/// The part of a spreadsheet package that holds the text of its cells.
+const SHARED_STRINGS_PART: &str = "xl/sharedStrings.xml";
+
+/// The folder of a spreadsheet package that holds its worksheets.
+const WORKSHEETS_DIR: &str = "xl/worksheets/";
+
+/// Reads the part called `name` out of `archive`, or returns `None` if it is missing or cannot
+/// be read.
+fn read_part(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Option<Vec<u8>> {
+    let mut part = archive.by_name(name).ok()?;
+    let mut bytes = Vec::new();
+    part.read_to_end(&mut bytes).ok()?;
+    Some(bytes)
+}
+
+/// Returns the text of the cells of the .xlsx spreadsheet held in `buffer`: its shared strings,
+/// followed by the inline strings of each worksheet. Returns `None` if `buffer` is not a
+/// spreadsheet package.
+fn xlsx_text(buffer: &[u8]) -> Option<Vec<String>> {
+    let mut archive = ZipArchive::new(Cursor::new(buffer)).ok()?;
+    let mut sheet_names: Vec<String> = archive
+        .file_names()
+        .filter(|name| name.starts_with(WORKSHEETS_DIR) && name.ends_with(".xml"))
+        .map(str::to_string)
+        .collect();
+    sheet_names.sort();
+    let shared_strings = read_part(&mut archive, SHARED_STRINGS_PART);
+    if shared_strings.is_none() && sheet_names.is_empty() {
+        return None;
+    }
+    let mut texts = Vec::new();
+    for xml in shared_strings.into_iter().chain(
+        sheet_names
+            .iter()
+            .filter_map(|name| read_part(&mut archive, name)),
+    ) {
+        texts.extend(xtract_element_text(&String::from_utf8_lossy(&xml), "t"));
+    }
+    Some(texts)
+}
+
+/// Returns the name and cell text of each embedded .xlsx spreadsheet in the .docx package held
+/// in `buffer`, in name order. Embedded objects that are not OOXML spreadsheets (such as
+/// `oleObject*.bin` compound files), or that cannot be read, are skipped.
+pub(crate) fn embedded_xlsx_texts(buffer: &[u8]) -> Vec<(String, Vec<String>)> {
+    part_texts(
+        buffer,
+        |name| {
+            (name.starts_with(EMBEDDINGS_DIR) && name.to_ascii_lowercase().ends_with(".xlsx"))
+                .then_some(())
+        },
+        |_, bytes| xlsx_text(bytes),
+    )
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    /// Builds a zip package containing the given parts.
+    pub(crate) fn make_zip(parts: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in parts {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    /// Builds a minimal spreadsheet package with one shared string and one inline string.
+    pub(crate) fn make_xlsx() -> Vec<u8> {
+        make_zip(&[
+            (
+                SHARED_STRINGS_PART,
+                b"<sst><si><t>Revenue &amp; costs</t></si></sst>",
+            ),
+            (
+                "xl/worksheets/sheet1.xml",
+                b"<worksheet><sheetData><row><c t=\"inlineStr\"><is><t>Q3 total</t></is></c><c><v>42</v></c></row></sheetData><tableParts/></worksheet>",
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_xlsx_text() {
+        assert_eq!(
+            xlsx_text(&make_xlsx()),
+            Some(vec!["Revenue & costs".to_string(), "Q3 total".to_string()])
+        );
+        assert_eq!(xlsx_text(b"not a zip"), None);
+        assert_eq!(xlsx_text(&make_zip(&[("other.xml", b"<t>x</t>")])), None);
+    }
+
+    #[test]
+    fn test_embedded_xlsx_texts() {
+        let xlsx = make_xlsx();
+        let docx = make_zip(&[
+            ("word/document.xml", b"<w:document/>"),
+            ("word/embeddings/Microsoft_Excel_Worksheet.xlsx", &xlsx),
+            ("word/embeddings/oleObject1.bin", b"\xd0\xcf\x11\xe0"),
+            ("word/embeddings/broken.xlsx", b"not a zip"),
+        ]);
+        let embedded = embedded_xlsx_texts(&docx);
+        assert_eq!(embedded.len(), 1);
+        assert_eq!(
+            embedded[0].0,
+            "word/embeddings/Microsoft_Excel_Worksheet.xlsx"
+        );
+        assert_eq!(embedded[0].1, vec!["Revenue & costs", "Q3 total"]);
+    }
+}
@@ -0,0 +1,174 @@
+use clap::ValueEnum;
+
+/// A kind of structured data, such as an e-mail address or a phone number, that can be searched
+/// for with `--entity` instead of writing a regex for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Entity {
+    /// E-mail addresses
+    Email,
+    /// North American numbers written with separators, and international numbers starting with +
+    Phone,
+    /// US social security numbers written as AAA-GG-SSSS
+    Ssn,
+    /// Visa, Mastercard, American Express and Discover card numbers
+    Creditcard,
+    /// IPv4 addresses
+    Ip,
+}
+
+const EMAIL: &str = r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}\b";
+
+const PHONE: &str = r"(?:\+1[ .-]?)?(?:\([2-9]\d{2}\) ?|\b[2-9]\d{2}[ .-])\d{3}[ .-]\d{4}\b|\+[2-9]\d{0,2}(?:[ .-]?\d{2,4}){2,5}\b";
+
+/// Area 001-899 but not 666, group 01-99, serial 0001-9999
+const SSN: &str = r"\b(?:00[1-9]|0[1-9]\d|[1-578]\d{2}|6[0-57-9]\d|66[0-57-9])-(?:0[1-9]|[1-9]\d)-(?:000[1-9]|00[1-9]\d|0[1-9]\d{2}|[1-9]\d{3})\b";
+
+/// 13 to 19 digits with a known issuer prefix, optionally grouped by spaces or dashes
+const CREDITCARD: &str = r"\b(?:4\d|5[1-5]|2[2-7]|3[47]|6[05])(?:[ -]?\d){11,17}\b";
+
+const IP: &str =
+    r"\b(?:(?:25[0-5]|2[0-4]\d|1\d\d|[1-9]?\d)\.){3}(?:25[0-5]|2[0-4]\d|1\d\d|[1-9]?\d)\b";
+
+impl Entity {
+    /// Returns the regex that finds this kind of entity.
+    pub fn pattern(&self) -> &'static str {
+        match self {
+            Entity::Email => EMAIL,
+            Entity::Phone => PHONE,
+            Entity::Ssn => SSN,
+            Entity::Creditcard => CREDITCARD,
+            Entity::Ip => IP,
+        }
+    }
+}
+
+/// Returns a single regex that matches `pattern` or any of `entities`, each wrapped in a
+/// non-capturing group. An empty `pattern` is left out, and `pattern` is returned unchanged if
+/// there are no entities.
+pub(crate) fn combine(pattern: &str, entities: &[Entity]) -> String {
+    if entities.is_empty() {
+        return pattern.to_string();
+    }
+    (!pattern.is_empty())
+        .then_some(pattern)
+        .into_iter()
+        .chain(entities.iter().map(|entity| entity.pattern()))
+        .map(|part| format!("(?:{})", part))
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+/// Returns true if the digits of `number` (other characters are ignored) pass the Luhn checksum
+/// that card numbers carry in their last digit.
+pub(crate) fn luhn_valid(number: &str) -> bool {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    if digits.len() < 2 {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &digit)| match (i % 2, digit * 2) {
+            (0, _) => digit,
+            (_, doubled) if doubled > 9 => doubled - 9,
+            (_, doubled) => doubled,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    /// Returns the matches of `entity` in `text`.
+    fn find(entity: Entity, text: &str) -> Vec<&str> {
+        Regex::new(entity.pattern())
+            .unwrap()
+            .find_iter(text)
+            .map(|m| m.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_email() {
+        assert_eq!(
+            find(
+                Entity::Email,
+                "Write to jane.doe+docs@example.co.uk or to @nobody."
+            ),
+            vec!["jane.doe+docs@example.co.uk"]
+        );
+        assert!(find(Entity::Email, "user@localhost").is_empty());
+    }
+
+    #[test]
+    fn test_phone() {
+        assert_eq!(
+            find(
+                Entity::Phone,
+                "Call (212) 555-0147, 212.555.0148, +1 212-555-0149 or +44 20 7946 0958."
+            ),
+            vec![
+                "(212) 555-0147",
+                "212.555.0148",
+                "+1 212-555-0149",
+                "+44 20 7946 0958"
+            ]
+        );
+        assert!(find(Entity::Phone, "Order 2125550147, page 12-34").is_empty());
+    }
+
+    #[test]
+    fn test_ssn() {
+        assert_eq!(
+            find(
+                Entity::Ssn,
+                "SSN 123-45-6789, not 666-12-3456, 000-12-3456 or 123-00-4567"
+            ),
+            vec!["123-45-6789"]
+        );
+    }
+
+    #[test]
+    fn test_creditcard() {
+        assert_eq!(
+            find(
+                Entity::Creditcard,
+                "Visa 4111 1111 1111 1111, Amex 3782-822463-10005, ref 1234567890123"
+            ),
+            vec!["4111 1111 1111 1111", "3782-822463-10005"]
+        );
+    }
+
+    #[test]
+    fn test_ip() {
+        assert_eq!(
+            find(
+                Entity::Ip,
+                "Hosts 192.168.0.1 and 10.0.0.255, not 256.1.1.1"
+            ),
+            vec!["192.168.0.1", "10.0.0.255"]
+        );
+    }
+
+    #[test]
+    fn test_combine() {
+        assert_eq!(combine("abc", &[]), "abc");
+        assert_eq!(combine("", &[Entity::Ip]), format!("(?:{})", IP));
+        assert_eq!(
+            combine("a|b", &[Entity::Ssn]),
+            format!("(?:a|b)|(?:{})", SSN)
+        );
+    }
+
+    #[test]
+    fn test_luhn_valid() {
+        assert!(luhn_valid("4111 1111 1111 1111"));
+        assert!(luhn_valid("3782-822463-10005"));
+        assert!(!luhn_valid("4111 1111 1111 1112"));
+        assert!(!luhn_valid("0"));
+    }
+}
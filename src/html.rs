@@ -0,0 +1,140 @@
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::matcher::Searcher;
+use crate::reader::{SearchOptions, SearchResult};
+
+/// Inline style sheet, so that the report is a single self-contained file.
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; line-height: 1.5; }
+mark { background: #ffe066; }
+section { border-top: 1px solid #ccc; margin-top: 1.5em; }
+li { margin-bottom: 0.5em; }
+.error { color: #b00020; }";
+
+/// Escapes the characters of `s` that are significant in HTML text and attribute values.
+pub(crate) fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Renders the search results as a self-contained HTML page: a table of contents linking to
+/// one section per file, with every match `<mark>`-highlighted in its context. Files without
+/// matches are left out unless `opts.unmatched_show` is set; files that could not be searched
+/// are listed with their error.
+pub(crate) fn render_report(
+    results: &[SearchResult],
+    searcher: &Searcher,
+    opts: &SearchOptions,
+) -> String {
+    let mut toc = String::new();
+    let mut sections = String::new();
+    for (index, result) in results.iter().enumerate() {
+        let anchor = format!("file-{}", index + 1);
+        let name = escape_html(&result.file_name);
+        match &result.maybe_result {
+            Ok(parsed) => {
+                if parsed.runs.is_empty() && !opts.unmatched_show {
+                    continue;
+                }
+                let mut items = String::new();
+                let mut nmatches = 0;
+                for run in &parsed.runs {
+                    for mtriple in run.segment(searcher, opts.context_for(&run.text)) {
+                        nmatches += 1;
+                        let _ = writeln!(
+                            items,
+                            "<li>{}<mark>{}</mark>{}</li>",
+                            escape_html(&mtriple.pre),
+                            escape_html(&mtriple.matched),
+                            escape_html(&mtriple.post)
+                        );
+                    }
+                }
+                let _ = writeln!(
+                    toc,
+                    "<li><a href=\"#{anchor}\">{name}</a> ({nmatches} matches)</li>"
+                );
+                let _ = writeln!(
+                    sections,
+                    "<section id=\"{anchor}\">\n<h2>{name}</h2>\n<ol>\n{items}</ol>\n</section>"
+                );
+            }
+            Err(e) => {
+                let _ = writeln!(
+                    toc,
+                    "<li><a href=\"#{anchor}\">{name}</a> <span class=\"error\">(error)</span></li>"
+                );
+                let _ = writeln!(
+                    sections,
+                    "<section id=\"{anchor}\">\n<h2>{name}</h2>\n<p class=\"error\">{}</p>\n</section>",
+                    escape_html(&format!("{:#}", e))
+                );
+            }
+        }
+    }
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>docread report</title>\n<style>\n{STYLE}\n</style>\n</head>\n<body>\n<h1>docread report</h1>\n<p>Pattern: <code>{}</code></p>\n<h2>Contents</h2>\n<ul>\n{toc}</ul>\n{sections}</body>\n</html>\n",
+        escape_html(&searcher.to_string())
+    )
+}
+
+/// Writes the HTML report for `results` to the file at `path`.
+///
+/// # Errors
+///
+/// Will return an error if the file cannot be written.
+pub(crate) fn write_report(
+    path: &Path,
+    results: &[SearchResult],
+    searcher: &Searcher,
+    opts: &SearchOptions,
+) -> anyhow::Result<()> {
+    std::fs::write(path, render_report(results, searcher, opts))
+        .map_err(|e| anyhow::anyhow!("Failed to write HTML report {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{ParsedDoc, Run};
+    use regex::Regex;
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(
+            escape_html(r#"<script>alert("x & y")</script>"#),
+            "&lt;script&gt;alert(&quot;x &amp; y&quot;)&lt;/script&gt;"
+        );
+    }
+
+    #[test]
+    fn test_render_report() {
+        let results = vec![SearchResult {
+            file_name: "a<b>.docx".to_string(),
+            archive_name: None,
+            source_path: "a<b>.docx".into(),
+            maybe_result: Ok(ParsedDoc {
+                runs: vec![Run::from("Say <hello> to Hello")],
+                ..Default::default()
+            }),
+            timed_out: false,
+            size: None,
+        }];
+        let re = Searcher::from(Regex::new("[Hh]ello").unwrap());
+        let html = render_report(&results, &re, &SearchOptions::default());
+        assert!(html.contains("<a href=\"#file-1\">a&lt;b&gt;.docx</a> (2 matches)"));
+        assert!(html.contains("<li>Say &lt;<mark>hello</mark>&gt; to </li>"));
+        assert!(html.contains("<mark>Hello</mark>"));
+        assert!(!html.contains("<hello>"));
+    }
+}
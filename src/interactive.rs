@@ -0,0 +1,145 @@
+use colored::Colorize;
+use std::io::{BufRead, Write};
+
+use crate::matcher::Searcher;
+use crate::reader::{format_result, SearchOptions, SearchResult};
+
+/// Writes a numbered list of the files in `matched`, with the number of matches in each.
+fn write_menu(output: &mut impl Write, matched: &[(&SearchResult, usize)]) -> std::io::Result<()> {
+    writeln!(output, "Files with matches:\n")?;
+    for (number, (result, nmatches)) in matched.iter().enumerate() {
+        let noun = if *nmatches == 1 { "match" } else { "matches" };
+        writeln!(
+            output,
+            "  {:>3}. {} ({} {})",
+            number + 1,
+            result.file_name.bright_red(),
+            nmatches,
+            noun
+        )?;
+    }
+    writeln!(output)
+}
+
+/// Lets the user browse `results` one file at a time. The files with matches are listed with
+/// their match counts; entering a file's number shows its matches as the normal output would,
+/// an empty line lists the files again, and `q` or the end of `input` quits. Errors are written
+/// to `output` with the rest, and files without matches are left out.
+///
+/// # Errors
+///
+/// Will return an error if reading `input` or writing `output` fails.
+pub(crate) fn browse(
+    results: &[SearchResult],
+    searcher: &Searcher,
+    opts: &SearchOptions,
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> anyhow::Result<()> {
+    let matched: Vec<(&SearchResult, usize)> = results
+        .iter()
+        .filter_map(|result| {
+            let parsed = result.maybe_result.as_ref().ok()?;
+            let nmatches: usize = parsed
+                .runs
+                .iter()
+                .map(|run| searcher.find_spans(&run.text).len())
+                .sum();
+            (nmatches > 0).then_some((result, nmatches))
+        })
+        .collect();
+    for result in results {
+        if let Err(e) = &result.maybe_result {
+            writeln!(output, "{:?}\n", e)?;
+        }
+    }
+    if matched.is_empty() {
+        writeln!(output, "No matches found\n")?;
+        return Ok(());
+    }
+    write_menu(&mut output, &matched)?;
+    let mut line = String::new();
+    loop {
+        write!(output, "File number (Enter to list, q to quit): ")?;
+        output.flush()?;
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(output)?;
+            return Ok(());
+        }
+        match line.trim() {
+            "q" | "quit" => return Ok(()),
+            "" => write_menu(&mut output, &matched)?,
+            choice => match choice.parse::<usize>() {
+                Ok(number) if (1..=matched.len()).contains(&number) => {
+                    let result = matched[number - 1].0;
+                    if let Ok(parsed) = &result.maybe_result {
+                        write!(
+                            output,
+                            "\n{}",
+                            format_result(result, parsed, searcher, opts)
+                        )?;
+                    }
+                }
+                _ => writeln!(
+                    output,
+                    "Please enter a number from 1 to {}, or q to quit",
+                    matched.len()
+                )?,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{ParsedDoc, Run};
+    use regex::Regex;
+
+    fn make_result(file_name: &str, text: &str) -> SearchResult {
+        SearchResult {
+            file_name: file_name.to_string(),
+            archive_name: None,
+            source_path: file_name.into(),
+            maybe_result: Ok(ParsedDoc {
+                runs: if text.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![Run::from(text)]
+                },
+                ..Default::default()
+            }),
+            timed_out: false,
+            size: None,
+        }
+    }
+
+    #[test]
+    fn test_browse() -> anyhow::Result<()> {
+        let results = vec![
+            make_result("a.docx", "one cat"),
+            make_result("b.docx", ""),
+            make_result("c.docx", "cat and cat"),
+        ];
+        let re = Searcher::from(Regex::new("cat").unwrap());
+        let mut output = Vec::new();
+        browse(
+            &results,
+            &re,
+            &SearchOptions::default(),
+            &b"2\n7\nq\n"[..],
+            &mut output,
+        )?;
+        let output = String::from_utf8(output)?;
+        assert!(output.contains("(1 match)"));
+        assert!(output.contains("(2 matches)"));
+        assert!(!output.contains("b.docx"));
+        // only the second file with matches is shown in full
+        let (_, shown) = output.split_once("Searched file-->").unwrap();
+        assert!(shown.contains("c.docx"));
+        assert!(!shown.contains("Searched file-->"));
+        assert!(output.contains("Please enter a number from 1 to 2"));
+        Ok(())
+    }
+}
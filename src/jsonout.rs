@@ -0,0 +1,281 @@
+use serde_json::{json, Value};
+use std::io::Write;
+
+use crate::matcher::Searcher;
+use crate::reader::{SearchOptions, SearchResult};
+
+/// The version of the structure of the `--json` output, given in every file object as
+/// `schema_version`. It goes up whenever a field is added, removed or changes meaning.
+pub(crate) const SCHEMA_VERSION: u64 = 7;
+
+/// Returns the JSON object describing one searched file: its names, the beginning of its text
+/// if `--peek` was given, any error, and each match with its number in the document and its
+/// run number (both counting from 1), heading, formatting, context and, when reported, the
+/// alternative of the pattern that matched and its `<w:t>` element and offset.
+pub(crate) fn result_to_json(
+    result: &SearchResult,
+    searcher: &Searcher,
+    opts: &SearchOptions,
+) -> Value {
+    let (matches, raw_fallback, peek, error) = match &result.maybe_result {
+        Ok(parsed) => {
+            let mut matches = Vec::new();
+            for (run_index, run) in parsed.runs.iter().enumerate() {
+                let positions = if opts.source_positions {
+                    run.source_positions(searcher)
+                } else {
+                    Vec::new()
+                };
+                let mtriples = run.segment(searcher, opts.context_for(&run.text));
+                for (match_index, mtriple) in mtriples.into_iter().enumerate() {
+                    let position = positions.get(match_index).copied().flatten();
+                    matches.push(json!({
+                        "index": matches.len() + 1,
+                        "run": run_index + 1,
+                        "heading": run.heading,
+                        "source": run.source.as_ref().map(|source| json!({
+                            "kind": source.kind(),
+                            "name": source.name(),
+                        })),
+                        "metadata": run.metadata,
+                        "text_box": run.text_box,
+                        "author": run.author,
+                        "comment": run.comment,
+                        "formatting": run.formatting,
+                        "para_before": run.para_before,
+                        "para_after": run.para_after,
+                        "preamble": mtriple.pre,
+                        "match": mtriple.matched,
+                        "postamble": mtriple.post,
+                        "alternative": mtriple.alternative,
+                        "element": position.map(|(element, _)| element),
+                        "offset": position.map(|(_, offset)| offset),
+                    }));
+                }
+            }
+            (matches, parsed.raw_fallback, parsed.peek.clone(), None)
+        }
+        Err(e) => (Vec::new(), false, None, Some(format!("{:#}", e))),
+    };
+    json!({
+        "schema_version": SCHEMA_VERSION,
+        "file": result.file_name,
+        "archive": result.archive_name,
+        "path": result.source_path.display().to_string(),
+        "timed_out": result.timed_out,
+        "raw_fallback": raw_fallback,
+        "peek": peek,
+        "error": error,
+        "matches": matches,
+    })
+}
+
+/// Returns the JSON Schema (draft 2020-12) of the `--json` output: an array of the file objects
+/// that `result_to_json` makes, for `--schema`.
+pub fn schema() -> Value {
+    let string = json!({"type": "string"});
+    let nullable_string = json!({"type": ["string", "null"]});
+    let boolean = json!({"type": "boolean"});
+    let strings = json!({"type": "array", "items": {"type": "string"}});
+    let count = json!({"type": "integer", "minimum": 1});
+    let nullable_offset = json!({"type": ["integer", "null"], "minimum": 0});
+    let object = |description: &str, properties: Value| {
+        let required: Vec<&String> = properties.as_object().unwrap().keys().collect();
+        json!({
+            "type": "object",
+            "description": description,
+            "properties": properties,
+            "required": required,
+        })
+    };
+    let matched = object(
+        "A match, with its context",
+        json!({
+            "index": count.clone(),
+            "run": count,
+            "heading": nullable_string.clone(),
+            "source": {
+                "oneOf": [
+                    {"type": "null"},
+                    object(
+                        "Where the match was found, if not in the main document body",
+                        json!({
+                            "kind": {
+                                "enum": [
+                                    "embedded",
+                                    "custom_xml",
+                                    "document_part",
+                                    "diagram",
+                                    "linked",
+                                ],
+                            },
+                            "name": string.clone(),
+                        }),
+                    ),
+                ],
+            },
+            "metadata": boolean.clone(),
+            "text_box": boolean.clone(),
+            "author": nullable_string.clone(),
+            "comment": boolean.clone(),
+            "formatting": {
+                "type": "array",
+                "items": {"enum": ["bold", "italic", "underline", "strike"]},
+            },
+            "para_before": strings.clone(),
+            "para_after": strings,
+            "preamble": string.clone(),
+            "match": string.clone(),
+            "postamble": string.clone(),
+            "alternative": nullable_string.clone(),
+            "element": nullable_offset.clone(),
+            "offset": nullable_offset,
+        }),
+    );
+    let file = object(
+        "A searched document, with its matches",
+        json!({
+            "schema_version": {"const": SCHEMA_VERSION},
+            "file": string.clone(),
+            "archive": nullable_string.clone(),
+            "path": string,
+            "timed_out": boolean.clone(),
+            "raw_fallback": boolean,
+            "peek": nullable_string.clone(),
+            "error": nullable_string,
+            "matches": {"type": "array", "items": matched},
+        }),
+    );
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "docread --json output",
+        "description": format!("Schema version {}", SCHEMA_VERSION),
+        "type": "array",
+        "items": file,
+    })
+}
+
+/// Writes `results` to `writer` as a JSON array with one object per file (see
+/// `result_to_json`), followed by a newline. Files without matches are left out unless
+/// `opts.unmatched_show` or `opts.peek` is set. The JSON is on a single line unless
+/// `opts.pretty` is set.
+///
+/// # Errors
+///
+/// Will return an error if writing fails.
+pub(crate) fn write_results(
+    mut writer: impl Write,
+    results: &[SearchResult],
+    searcher: &Searcher,
+    opts: &SearchOptions,
+) -> anyhow::Result<()> {
+    let values: Vec<Value> = results
+        .iter()
+        .filter(|result| match &result.maybe_result {
+            Ok(parsed) => !parsed.runs.is_empty() || opts.unmatched_show || opts.peek.is_some(),
+            Err(_) => true,
+        })
+        .map(|result| result_to_json(result, searcher, opts))
+        .collect();
+    if opts.pretty {
+        serde_json::to_writer_pretty(&mut writer, &values)?;
+    } else {
+        serde_json::to_writer(&mut writer, &values)?;
+    }
+    writeln!(writer)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{ParsedDoc, Run};
+    use regex::Regex;
+
+    fn make_results() -> Vec<SearchResult> {
+        vec![
+            SearchResult {
+                file_name: "a.docx".to_string(),
+                archive_name: None,
+                source_path: "a.docx".into(),
+                maybe_result: Ok(ParsedDoc {
+                    runs: vec![Run::from("Say hello to Hello")],
+                    ..Default::default()
+                }),
+                timed_out: false,
+                size: None,
+            },
+            SearchResult {
+                file_name: "b.docx".to_string(),
+                archive_name: None,
+                source_path: "b.docx".into(),
+                maybe_result: Ok(ParsedDoc::default()),
+                timed_out: false,
+                size: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_result_to_json() {
+        let re = Searcher::from(Regex::new("[Hh]ello").unwrap());
+        let value = result_to_json(&make_results()[0], &re, &SearchOptions::default());
+        assert_eq!(value["file"], "a.docx");
+        assert_eq!(value["error"], Value::Null);
+        assert_eq!(value["matches"].as_array().unwrap().len(), 2);
+        assert_eq!(value["matches"][1]["match"], "Hello");
+        assert_eq!(value["matches"][1]["preamble"], " to ");
+        assert_eq!(value["matches"][1]["author"], Value::Null);
+
+        let mut results = make_results();
+        if let Ok(parsed) = &mut results[0].maybe_result {
+            parsed.runs[0].author = Some("Ann".to_string());
+            parsed.runs[0].comment = true;
+        }
+        let value = result_to_json(&results[0], &re, &SearchOptions::default());
+        assert_eq!(value["matches"][0]["author"], "Ann");
+        assert_eq!(value["matches"][0]["comment"], true);
+    }
+
+    #[test]
+    fn test_schema_matches_output() {
+        let re = Searcher::from(Regex::new("[Hh]ello").unwrap());
+        let value = result_to_json(&make_results()[0], &re, &SearchOptions::default());
+        let keys = |value: &Value| {
+            let mut keys: Vec<String> = value.as_object().unwrap().keys().cloned().collect();
+            keys.sort();
+            keys
+        };
+        let schema = schema();
+        let file = &schema["items"];
+        assert_eq!(keys(&file["properties"]), keys(&value));
+        assert_eq!(
+            keys(&file["properties"]["matches"]["items"]["properties"]),
+            keys(&value["matches"][0])
+        );
+        assert_eq!(value["schema_version"], SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_write_results_pretty_and_compact() -> anyhow::Result<()> {
+        let re = Searcher::from(Regex::new("[Hh]ello").unwrap());
+        let results = make_results();
+        let mut compact = Vec::new();
+        write_results(&mut compact, &results, &re, &SearchOptions::default())?;
+        let opts = SearchOptions {
+            pretty: true,
+            ..Default::default()
+        };
+        let mut pretty = Vec::new();
+        write_results(&mut pretty, &results, &re, &opts)?;
+        let compact = String::from_utf8(compact)?;
+        let pretty = String::from_utf8(pretty)?;
+        assert_eq!(compact.lines().count(), 1);
+        assert!(pretty.lines().count() > 1);
+        let value: Value = serde_json::from_str(&compact)?;
+        assert_eq!(value, serde_json::from_str::<Value>(&pretty)?);
+        // the file without matches is left out
+        assert_eq!(value.as_array().unwrap().len(), 1);
+        Ok(())
+    }
+}
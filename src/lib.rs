@@ -0,0 +1,63 @@
+//! Find regular expression matches in .docx and zipped .docx files.
+//!
+//! The `docread` binary is a thin command line wrapper around `process_files`. Other programs
+//! can search documents the same way, with `process_files` or `search_documents`, or get at
+//! their text with `extract_all_text`. A `SearchOptions` is made with `Default::default()`
+//! and its fields then set one by one, so that options added later do not break callers.
+
+mod compare;
+mod config;
+mod customxml;
+mod diagrams;
+mod dictionary;
+mod docparts;
+mod embedded;
+mod entity;
+mod html;
+mod interactive;
+mod jsonout;
+mod linkeddocs;
+mod manifest;
+mod matcher;
+mod metadata;
+mod numbering;
+mod progress;
+mod rawxml;
+mod reader;
+mod selector;
+mod semaphore;
+mod since;
+mod streaming;
+mod summary;
+mod template;
+mod urlfile;
+mod watch;
+mod ziphandler;
+mod zstfile;
+
+pub use dictionary::Dictionary;
+pub use entity::Entity;
+pub use matcher::{
+    build_searcher, ContextSpec, ContextUnit, MatchTriple, Normalization, PatternOptions, Searcher,
+};
+pub use reader::{
+    extract_all_text, process_files, search_documents, FileLike, FileSize, IncludedExtension,
+    ParaRange, ParsedDoc, ReadIntoBuf, ReaderFactory, Run, RunSource, SearchOptions, SearchResult,
+    Sentence,
+};
+pub use since::Since;
+pub use summary::{ScanSummary, UniqueScope};
+pub use template::Template;
+
+/// What the `docread` binary needs beyond the library: reading its configuration file and
+/// the modes of the command line other than a plain search. Not meant for other programs, and
+/// may change at any time.
+#[doc(hidden)]
+pub mod cli {
+    pub use crate::compare::{compare_dirs, print_deltas, FileDelta};
+    pub use crate::config::{config_args, find_config};
+    pub use crate::jsonout::schema;
+    pub use crate::matcher::{explain, set_highlight_styles, HighlightStyle};
+    pub use crate::urlfile::is_url;
+    pub use crate::watch::watch;
+}
@@ -0,0 +1,234 @@
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+use crate::rawxml::{read_part, unescape_xml};
+
+/// The part of a .docx package that lists the relationships of the main document body.
+const DOCUMENT_RELS_PART: &str = "word/_rels/document.xml.rels";
+
+/// The kind of document that a relationship of the main document body links to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum LinkKind {
+    /// A subdocument of a master document (`<w:subDoc>`), usually a separate file
+    SubDocument,
+    /// Content imported into the document (`<w:altChunk>`), usually a part of the package
+    AltChunk,
+}
+
+/// A relationship of the main document body to another document.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Link {
+    pub(crate) kind: LinkKind,
+    /// Where the document is, as the relationship gives it
+    pub(crate) target: String,
+    /// True if the document is outside the package (`TargetMode="External"`)
+    pub(crate) external: bool,
+}
+
+/// Where the document a `Link` points at can be read from.
+#[derive(Debug, PartialEq)]
+pub(crate) enum LinkedSource {
+    /// The part of the package of the linking document with this name
+    Part(String),
+    /// The file at this path
+    File(PathBuf),
+}
+
+impl Link {
+    /// Returns how matches in the linked document are labelled, such as "subdocument
+    /// chapter1.docx" or "altchunk /word/afchunk.docx".
+    pub(crate) fn label(&self) -> String {
+        let kind = match self.kind {
+            LinkKind::SubDocument => "subdocument",
+            LinkKind::AltChunk => "altchunk",
+        };
+        format!("{} {}", kind, self.target)
+    }
+
+    /// Returns where the linked document can be read from: a part of the package for an
+    /// internal link, whose target is relative to `word/` unless it starts with `/`, or for an
+    /// external one a local file, whose target is a `file:` URL or a path relative to `dir`,
+    /// the folder of the linking document. Returns `None` for links to anything but a local file,
+    /// such as `http:` URLs, and for relative links when `dir` is not known.
+    pub(crate) fn resolve(&self, dir: Option<&Path>) -> Option<LinkedSource> {
+        if !self.external {
+            let name = match self.target.strip_prefix('/') {
+                Some(name) => name.to_string(),
+                None => format!("word/{}", self.target),
+            };
+            return Some(LinkedSource::Part(name));
+        }
+        let target = match self.target.strip_prefix("file://") {
+            // file:///C:/x.docx names the Windows path C:/x.docx
+            Some(path) => match path.strip_prefix('/') {
+                Some(rest) if rest.as_bytes().get(1) == Some(&b':') => rest,
+                _ => path,
+            },
+            None if self.target.contains("://") => return None,
+            None => &self.target,
+        };
+        let path = PathBuf::from(percent_decode(target));
+        if path.is_absolute() {
+            Some(LinkedSource::File(path))
+        } else {
+            Some(LinkedSource::File(dir?.join(path)))
+        }
+    }
+}
+
+impl LinkedSource {
+    /// Reads the linked document, out of the package held in `buffer` for a part, or returns
+    /// `None` if it cannot be read.
+    pub(crate) fn read(&self, buffer: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            LinkedSource::Part(name) => {
+                let mut archive = ZipArchive::new(Cursor::new(buffer)).ok()?;
+                let mut part = archive.by_name(name).ok()?;
+                let mut bytes = Vec::new();
+                part.read_to_end(&mut bytes).ok()?;
+                Some(bytes)
+            }
+            LinkedSource::File(path) => std::fs::read(path).ok(),
+        }
+    }
+}
+
+/// Decodes the `%XX` escapes of a URL path, such as `%20` for a space. Malformed escapes are
+/// left as they are.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| s.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Returns the decoded value of the attribute `name` of the start tag `tag`, if it has one.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    while let Some(eq) = rest.find('=') {
+        let found = rest[..eq].split_whitespace().last().unwrap_or_default();
+        let after = rest[eq + 1..].trim_start();
+        let quote = after.chars().next().filter(|&c| c == '"' || c == '\'')?;
+        let close = after[1..].find(quote)?;
+        if found == name {
+            return Some(unescape_xml(&after[1..close + 1]));
+        }
+        rest = &after[close + 2..];
+    }
+    None
+}
+
+/// Returns the links to subdocuments and imported content in the relationships XML `xml`, in
+/// the order they are listed. Relationships of other types, such as hyperlinks and images, are
+/// left out.
+fn parse_links(xml: &str) -> Vec<Link> {
+    xml.split('<')
+        .filter_map(|markup| markup.strip_prefix("Relationship"))
+        .filter(|tag| tag.starts_with(char::is_whitespace))
+        .filter_map(|tag| {
+            let rel_type = attribute(tag, "Type")?;
+            let kind = if rel_type.ends_with("/subDocument") {
+                LinkKind::SubDocument
+            } else if rel_type.ends_with("/aFChunk") {
+                LinkKind::AltChunk
+            } else {
+                return None;
+            };
+            Some(Link {
+                kind,
+                target: attribute(tag, "Target")?,
+                external: attribute(tag, "TargetMode").as_deref() == Some("External"),
+            })
+        })
+        .collect()
+}
+
+/// Returns the links from the main document body of the .docx package held in `buffer` to
+/// subdocuments and imported content (see `parse_links`), or none if its relationships cannot
+/// be read.
+pub(crate) fn links(buffer: &[u8]) -> Vec<Link> {
+    read_part(buffer, DOCUMENT_RELS_PART)
+        .map(|xml| parse_links(&xml))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_links() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/>
+<Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink" Target="https://example.com" TargetMode="External"/>
+<Relationship Id="rId3" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/subDocument" Target="file:///C:/Books/Chapter%201.docx" TargetMode="External"/>
+<Relationship Id="rId4" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/aFChunk" Target="/word/afchunk.docx"/>
+</Relationships>"#;
+        let links = parse_links(xml);
+        assert_eq!(
+            links,
+            vec![
+                Link {
+                    kind: LinkKind::SubDocument,
+                    target: "file:///C:/Books/Chapter%201.docx".to_string(),
+                    external: true,
+                },
+                Link {
+                    kind: LinkKind::AltChunk,
+                    target: "/word/afchunk.docx".to_string(),
+                    external: false,
+                },
+            ]
+        );
+        assert_eq!(links[1].label(), "altchunk /word/afchunk.docx");
+    }
+
+    #[test]
+    fn test_resolve() {
+        let link = |target: &str, external| Link {
+            kind: LinkKind::SubDocument,
+            target: target.to_string(),
+            external,
+        };
+        let dir = Path::new("/docs/book");
+        assert_eq!(
+            link("afchunk.docx", false).resolve(None),
+            Some(LinkedSource::Part("word/afchunk.docx".to_string()))
+        );
+        assert_eq!(
+            link("/word/afchunk.docx", false).resolve(None),
+            Some(LinkedSource::Part("word/afchunk.docx".to_string()))
+        );
+        assert_eq!(
+            link("Chapter%201.docx", true).resolve(Some(dir)),
+            Some(LinkedSource::File(dir.join("Chapter 1.docx")))
+        );
+        assert_eq!(
+            link("file:///docs/other.docx", true).resolve(None),
+            Some(LinkedSource::File(PathBuf::from("/docs/other.docx")))
+        );
+        assert_eq!(link("chapter1.docx", true).resolve(None), None);
+        assert_eq!(
+            link("https://example.com/a.docx", true).resolve(Some(dir)),
+            None
+        );
+    }
+}
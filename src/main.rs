@@ -1,11 +1,20 @@
-use clap::Parser;
-use regex::Regex;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, Parser};
+use colored::Colorize;
 
-mod matcher;
-mod reader;
-mod selector;
-mod ziphandler;
-use reader::process_files;
+use docread::cli::{
+    compare_dirs, config_args, explain, find_config, is_url, print_deltas, schema,
+    set_highlight_styles, watch, HighlightStyle,
+};
+use docread::{
+    build_searcher, process_files, ContextSpec, ContextUnit, Dictionary, Entity, IncludedExtension,
+    Normalization, ParaRange, PatternOptions, SearchOptions, Since, Template, UniqueScope,
+};
+use std::ffi::OsString;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -18,29 +27,553 @@ struct Args {
     #[arg(
         short,
         long,
+        required_unless_present_any = ["entity", "between", "schema", "unknown_words"],
         help = "Regular expression to search for, e.g. 'Hi|[Hh]ello'"
     )]
-    regex: String,
+    regex: Option<String>,
     #[arg(
         short,
         long,
         default_value = ".",
-        help = "top-level dir or file name to search for docx or zip files"
+        help = "top-level dir or file name to search for docx or zip files, or the http:// URL of a document (https is not supported)"
     )]
     dir: String,
     #[arg(
         short,
         long,
         default_value = "75",
-        help = "number of context chars to show before/after matches"
+        help = "context to show before/after matches: N chars, Nw words, Ns sentences or N% of the run"
     )]
     context: String,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = ContextUnit::Chars,
+        help = "unit of a bare-number --context: chars, or bytes (rounded down to whole chars)"
+    )]
+    context_unit: ContextUnit,
     #[arg(short, long, help = "show file names & match status only")]
     quiet: bool,
     #[arg(short, long, help = "show search summary")]
     summary: bool,
     #[arg(short, long, help = "show files with no matches")]
     unmatched_show: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "give up on any single file after N seconds"
+    )]
+    timeout_secs: Option<u64>,
+    #[arg(
+        short,
+        long,
+        help = "show the size of each searched file, and the bytes scanned per second"
+    )]
+    verbose: bool,
+    #[arg(long, help = "group results under the zip archive they came from")]
+    group_by_archive: bool,
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "reject regexes whose compiled form would exceed BYTES (also caps the DFA cache)"
+    )]
+    regex_size_limit: Option<usize>,
+    #[arg(
+        long,
+        help = "if a file cannot be parsed, search the text of its raw document.xml instead"
+    )]
+    raw_fallback: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "also write the results as a self-contained HTML report to FILE"
+    )]
+    html: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "stop searching each file at its first match and only report that it matches"
+    )]
+    first_match_only: bool,
+    #[arg(
+        long,
+        value_name = "DISTANCE",
+        conflicts_with = "regex_size_limit",
+        help = "treat the regex as a literal and match it approximately, within DISTANCE edits"
+    )]
+    fuzzy: Option<usize>,
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "write the full text of each searched document to a .txt file under DIR"
+    )]
+    extract_text: Option<PathBuf>,
+    #[arg(long, help = "show the heading that each match falls under")]
+    show_heading: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "write the search summary as JSON to FILE"
+    )]
+    stats_json: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "WIDTH",
+        help = "pad the context before each match to WIDTH columns so matches line up"
+    )]
+    align: Option<usize>,
+    #[arg(
+        long,
+        value_name = "WIDTH",
+        num_args = 0..=1,
+        conflicts_with = "align",
+        help = "center each match in a line of WIDTH columns (default: the width of the terminal, else COLUMNS, else 80)"
+    )]
+    center: Option<Option<usize>>,
+    #[arg(
+        long,
+        value_name = "START:END",
+        help = "only search paragraphs START to END (inclusive, counting from 1)"
+    )]
+    para_range: Option<ParaRange>,
+    #[arg(long, help = "report groups of documents whose text is identical")]
+    dedupe: bool,
+    #[arg(long, help = "ignore matches inside quotation marks or parentheses")]
+    exclude_quoted: bool,
+    #[arg(
+        long,
+        help = "search again whenever a file of a kind that is searched under the directory changes"
+    )]
+    watch: bool,
+    #[arg(
+        long,
+        help = "stop at the first file that cannot be searched and exit with an error"
+    )]
+    fail_fast: bool,
+    #[arg(
+        long,
+        help = "show the fraction of documents in each directory that matched"
+    )]
+    coverage: bool,
+    #[arg(long, help = "print the results as JSON instead of text")]
+    json: bool,
+    #[arg(long, requires = "json", help = "indent the --json output")]
+    pretty: bool,
+    #[arg(long, help = "flush the output after each file's results")]
+    line_buffered: bool,
+    #[arg(
+        long,
+        conflicts_with = "fuzzy",
+        help = "show which alternative of a 'a|b|c' regex each match came from"
+    )]
+    show_alternative: bool,
+    #[arg(
+        long,
+        help = "let the context of a match run on into the neighbouring runs of its paragraph"
+    )]
+    cross_run_context: bool,
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        help = "print each match as TEMPLATE, e.g. '{file}:{para}: {match}'"
+    )]
+    format: Option<Template>,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORM",
+        help = "bring the text and the regex to Unicode normalization form FORM before matching"
+    )]
+    unicode_normalize: Option<Normalization>,
+    #[arg(
+        long,
+        help = "remove soft hyphens, zero-width spaces and joiners, and BOMs from the text before matching"
+    )]
+    strip_invisibles: bool,
+    #[arg(
+        long,
+        help = "also search the cells of spreadsheets embedded in each document"
+    )]
+    embedded: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "show the first N characters of each document's text under its name"
+    )]
+    peek: Option<usize>,
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "skip documents larger than BYTES (uncompressed, for archive entries)"
+    )]
+    max_file_size: Option<u64>,
+    #[arg(
+        long,
+        help = "print the pattern that would be matched, and exit without searching"
+    )]
+    explain: bool,
+    #[arg(
+        long,
+        help = "also search each document's properties (application, template, revision...)"
+    )]
+    search_metadata: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "only search documents whose revision number is greater than N"
+    )]
+    filter_revisions: Option<u64>,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "in text with line breaks, show N lines before and after each match's line"
+    )]
+    context_lines: Option<usize>,
+    #[arg(
+        long,
+        conflicts_with_all = ["json", "format"],
+        help = "list the files with matches and pick which ones to view"
+    )]
+    interactive: bool,
+    #[arg(
+        long,
+        help = "match each table row as a whole, with its cells joined by ' | '"
+    )]
+    table_row_context: bool,
+    #[arg(
+        long,
+        conflicts_with = "summary",
+        help = "print only the results, without the footer that follows them"
+    )]
+    no_summary: bool,
+    #[arg(
+        long,
+        help = "show which <w:t> element of word/document.xml each match is in, and where"
+    )]
+    source_positions: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "read and parse at most N documents at once, to bound memory use"
+    )]
+    parallel_files: Option<NonZeroUsize>,
+    #[arg(
+        long,
+        help = "show whether each match's run is bold, italic, underlined or struck through"
+    )]
+    show_formatting: bool,
+    #[arg(
+        long,
+        value_name = "WHEN",
+        help = "only search documents modified since WHEN: a date (YYYY-MM-DD) or e.g. 7d"
+    )]
+    since: Option<Since>,
+    #[arg(
+        long,
+        value_name = "B",
+        default_value_t = 0,
+        help = "also show the B paragraphs before each matching paragraph"
+    )]
+    para_before: usize,
+    #[arg(
+        long,
+        value_name = "A",
+        default_value_t = 0,
+        help = "also show the A paragraphs after each matching paragraph"
+    )]
+    para_after: usize,
+    #[arg(
+        long,
+        help = "keep the empty matches of a regex that can match the empty string, such as 'a*'"
+    )]
+    allow_empty_match: bool,
+    #[arg(
+        long,
+        help = "skip zip and tar archives, and search only loose documents"
+    )]
+    no_archives: bool,
+    #[arg(
+        long,
+        alias = "archive-only",
+        conflicts_with = "no_archives",
+        help = "search only the documents inside zip and tar archives"
+    )]
+    archives_only: bool,
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        value_name = "KIND",
+        conflicts_with = "fuzzy",
+        help = "also search for built-in patterns: email, phone, ssn, creditcard or ip"
+    )]
+    entity: Vec<Entity>,
+    #[arg(
+        long,
+        requires = "entity",
+        help = "drop card numbers that fail the Luhn checksum"
+    )]
+    luhn: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "skip documents that the manifest FILE records as searched, and add each one searched to it"
+    )]
+    resume: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "match list items with their numbers or bullets (\"1.\", \"a)\", \"•\") in front"
+    )]
+    list_numbers: bool,
+    #[arg(
+        long,
+        help = "show the paragraphs with the most matches first, instead of in document order"
+    )]
+    rank_paragraphs: bool,
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["START", "END"],
+        conflicts_with_all = ["regex", "entity", "fuzzy", "show_alternative"],
+        help = "show the text between each match of regex START and the next match of regex END"
+    )]
+    between: Option<Vec<String>>,
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with = "tail",
+        help = "only show the first N files with results (the summary still counts all)"
+    )]
+    head: Option<usize>,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "only show the last N files with results (the summary still counts all)"
+    )]
+    tail: Option<usize>,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "hand documents to the worker threads in runs of at least N, not one at a time"
+    )]
+    chunk_size: Option<NonZeroUsize>,
+    #[arg(
+        long,
+        value_name = "DIR",
+        num_args = 0..=1,
+        hide = true,
+        help = "instead of searching, write each document's docx-rs JSON tree to stdout or DIR"
+    )]
+    dump_json_tree: Option<Option<PathBuf>>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "no_config",
+        help = "read default options from FILE instead of looking for docread.toml"
+    )]
+    config: Option<PathBuf>,
+    #[arg(long, help = "ignore any docread.toml config file")]
+    no_config: bool,
+    #[arg(
+        long,
+        help = "number each match RUN-MATCH, by its run and its place in it, instead of through the document"
+    )]
+    compound_index: bool,
+    #[arg(long, help = "print the JSON Schema of the --json output and exit")]
+    schema: bool,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "WHEN",
+        default_value_t = ColorChoice::Auto,
+        help = "when to color the output"
+    )]
+    color: ColorChoice,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "only report documents with at least N matches in all"
+    )]
+    min_matches: Option<usize>,
+    #[arg(
+        long,
+        help = "also search the values in each document's custom XML data parts"
+    )]
+    search_custom_xml: bool,
+    #[arg(
+        long,
+        help = "print the files in the order they were found, though they are searched in parallel"
+    )]
+    ordered: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["table_row_context", "list_numbers", "para_before", "para_after", "extract_text", "dedupe", "peek", "dump_json_tree"],
+        help = "stream each document's XML instead of loading its whole tree, to bound memory use"
+    )]
+    streaming: bool,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "SCOPE",
+        num_args = 0..=1,
+        default_missing_value = "corpus",
+        help = "count the distinct strings matched, across all documents (corpus) or in each (file)"
+    )]
+    count_unique: Option<UniqueScope>,
+    #[arg(
+        long,
+        requires = "count_unique",
+        help = "with --count-unique, also list the distinct strings matched, sorted"
+    )]
+    list_unique: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "unknown_words",
+        help = "the word list, one word per line, that --unknown-words checks against"
+    )]
+    dictionary: Option<PathBuf>,
+    #[arg(
+        long,
+        requires = "dictionary",
+        conflicts_with_all = ["regex", "entity", "fuzzy", "show_alternative", "between"],
+        help = "instead of --regex, find the words that are not in the --dictionary"
+    )]
+    unknown_words: bool,
+    #[arg(
+        long,
+        help = "show the sentence each match is in, numbered through the document"
+    )]
+    sentence_index: bool,
+    #[arg(
+        long,
+        value_name = "OTHER_DIR",
+        conflicts_with_all = [
+            "watch",
+            "json",
+            "html",
+            "interactive",
+            "resume",
+            "dump_json_tree",
+            "format",
+            "quiet",
+            "stats_json",
+            "count_unique",
+            "parallel_files",
+        ],
+        help = "search OTHER_DIR as well as --dir, and show how the matches of each document changed"
+    )]
+    compare: Option<String>,
+    #[arg(
+        long,
+        help = "show control characters in the text around matches as escapes such as \\x0c"
+    )]
+    escape_output: bool,
+    #[arg(
+        long,
+        help = "rejoin words hyphenated at a line break, such as \"inter-\" and \"national\", before matching"
+    )]
+    dehyphenate: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["fuzzy", "between", "unknown_words"],
+        help = "report only the paragraphs in which every alternative of the pattern matches"
+    )]
+    require_all_in_paragraph: bool,
+    #[arg(
+        long,
+        value_name = "K",
+        help = "stop the whole scan once K matches have been found"
+    )]
+    max_results: Option<usize>,
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["quiet", "first_match_only"],
+        help = "instead of each match, list the N paragraphs of each file with the most matches"
+    )]
+    count_per_paragraph: Option<usize>,
+    #[arg(
+        long,
+        value_name = "STR",
+        help = "write STR after the output of each file instead of ==="
+    )]
+    group_separator: Option<String>,
+    #[arg(
+        long,
+        conflicts_with = "group_separator",
+        help = "write no separator after the output of each file"
+    )]
+    no_group_separator: bool,
+    #[arg(
+        long,
+        help = "exit with an error if any document searched has no extractable text"
+    )]
+    fail_on_empty: bool,
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        value_name = "STYLE",
+        help = "also emphasize matches with bold, underline, reverse or background, or several of them"
+    )]
+    highlight_style: Vec<HighlightStyle>,
+    #[arg(
+        long,
+        help = "also search each document's other bodies, such as its glossary, besides the main one"
+    )]
+    all_document_parts: bool,
+    #[arg(
+        long,
+        value_name = "SECS",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "when the output is not a terminal, report progress to stderr every SECS seconds"
+    )]
+    progress_interval: Option<u64>,
+    #[arg(
+        long,
+        conflicts_with_all = ["context", "context_unit", "context_lines", "cross_run_context"],
+        help = "show only the matched text, with no context around it (like --context 0)"
+    )]
+    no_context: bool,
+    #[arg(
+        long,
+        help = "also search the text of each document's SmartArt diagrams and charts"
+    )]
+    search_diagrams: bool,
+    #[arg(
+        long,
+        help = "trim the white space from the outer ends of the context shown around each match"
+    )]
+    trim: bool,
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "report only matches in text inserted (as a tracked change) or commented by NAME"
+    )]
+    by_author: Option<String>,
+    #[arg(
+        long,
+        help = "also search the subdocuments and imported documents that each document links to, \
+                when they are in the package or are local files"
+    )]
+    follow_rels: bool,
+    #[arg(
+        long,
+        value_name = "EXT[=HANDLER]",
+        help = "also search the files with extension EXT, read as HANDLER (docx, zip, docx.zst \
+                or tar.zst); may be repeated"
+    )]
+    include_ext: Vec<IncludedExtension>,
+}
+
+/// When `--color` colors the output.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum ColorChoice {
+    /// When writing to a terminal, unless NO_COLOR is set (CLICOLOR and CLICOLOR_FORCE are
+    /// honored too)
+    Auto,
+    Always,
+    Never,
 }
 
 /// Search for the given regular expression in all .docx and zipped .docx files in the current directory,
@@ -48,11 +581,137 @@ struct Args {
 ///
 /// Command line arguments:
 /// - `--regex, -r`: Regular expression to search for, e.g. 'Hi|[Hh]ello'
-/// - `--dir, -d`: case dirctory to begin search (default: current directory)
-/// - `--context, -c`: number of context characters to show before/after matches (default: 75)
+/// - `--dir, -d`: case dirctory to begin search (default: current directory), or the `http://` URL
+///   of a single document when built with the `http` feature (`https://` is not supported)
+/// - `--context, -c`: context to show before/after matches: N characters, `Nw` words, `Ns`
+///   sentences or `N%` of the length of the run (default: 75)
+/// - `--context-unit chars|bytes`: unit of a bare-number `--context` (default: chars)
 /// - `--quiet, -q`: show file names & match status only
 /// - `--summary, -s`: show search summary
 /// - `--unmatched-show, -u`: show files with no matches
+/// - `--timeout-secs N`: give up on any single file after N seconds
+/// - `--verbose, -v`: show the size of each searched file, and the bytes scanned per second
+/// - `--group-by-archive`: group results under the zip archive they came from
+/// - `--regex-size-limit BYTES`: reject regexes whose compiled form would exceed BYTES
+/// - `--raw-fallback`: if a file cannot be parsed, search the text of its raw document.xml instead
+/// - `--html FILE`: also write the results as a self-contained HTML report to FILE
+/// - `--first-match-only`: stop searching each file at its first match
+/// - `--fuzzy DISTANCE`: treat the regex as a literal and match it within DISTANCE edits
+/// - `--extract-text DIR`: write the full text of each searched document to a .txt file under DIR
+/// - `--show-heading`: show the heading that each match falls under
+/// - `--stats-json FILE`: write the search summary as JSON to FILE
+/// - `--align WIDTH`: pad the context before each match to WIDTH columns so matches line up
+/// - `--center [WIDTH]`: trim and pad the context so each match sits in the middle of a line of
+///   WIDTH columns (default: the width of the terminal, else `COLUMNS`, else 80)
+/// - `--para-range START:END`: only search paragraphs START to END (inclusive, counting from 1)
+/// - `--dedupe`: report groups of documents whose text is identical
+/// - `--exclude-quoted`: ignore matches inside quotation marks or parentheses
+/// - `--watch`: search again whenever a file of a kind that is searched under the directory
+///   changes
+/// - `--fail-fast`: stop at the first file that cannot be searched and exit with an error
+/// - `--coverage`: show the fraction of documents in each directory that matched
+/// - `--json`: print the results as JSON instead of text
+/// - `--pretty`: indent the `--json` output
+/// - `--line-buffered`: flush the output after each file's results
+/// - `--show-alternative`: show which alternative of a `a|b|c` regex each match came from
+/// - `--cross-run-context`: let the context of a match run on into the neighbouring runs of its
+///   paragraph
+/// - `--format TEMPLATE`: print each match as TEMPLATE, filling in `{file}`, `{archive}`, `{para}`,
+///   `{pre}`, `{match}`, `{post}` and `{count}`
+/// - `--unicode-normalize nfc|nfd`: normalize the text and the regex before matching (default:
+///   no normalization)
+/// - `--embedded`: also search the cells of spreadsheets embedded in each document
+/// - `--peek N`: show the first N characters of each document's text under its name
+/// - `--max-file-size BYTES`: skip documents larger than BYTES (uncompressed, for archive entries)
+/// - `--explain`: print the pattern that would be matched, and exit without searching
+/// - `--search-metadata`: also search each document's properties (application, template,
+///   revision...)
+/// - `--filter-revisions N`: only search documents whose revision number is greater than N
+/// - `--context-lines N`: in text with line breaks, show N lines before and after each match's line
+/// - `--interactive`: list the files with matches and pick which ones to view
+/// - `--table-row-context`: match each table row as a whole, with its cells joined by ` | `
+/// - `--no-summary`: print only the results, without the footer that follows them
+/// - `--source-positions`: show which `<w:t>` element of `word/document.xml` each match is in,
+///   and its character offset within it
+/// - `--parallel-files N`: read and parse at most N documents at once, to bound memory use
+/// - `--show-formatting`: show whether each match's run is bold, italic, underlined or struck
+///   through
+/// - `--since WHEN`: only search documents modified since WHEN, a date such as `2024-05-01` or a
+///   duration back from now such as `7d`
+/// - `--para-before B`: also show the B paragraphs before each matching paragraph
+/// - `--para-after A`: also show the A paragraphs after each matching paragraph
+/// - `--allow-empty-match`: keep the empty matches of a regex that can match the empty string
+/// - `--no-archives`: skip zip and tar archives, and search only loose documents
+/// - `--archives-only`: search only the documents inside zip and tar archives
+/// - `--entity KIND,...`: also search for built-in patterns (email, phone, ssn, creditcard, ip);
+///   `--regex` may then be left out
+/// - `--luhn`: drop card numbers that fail the Luhn checksum
+/// - `--resume FILE`: skip the documents that the manifest FILE records as already searched, and
+///   add each document searched to it
+/// - `--list-numbers`: match list items with their numbers or bullets in front, as Word shows
+///   them
+/// - `--rank-paragraphs`: show the matches of each document by paragraph, those with the most
+///   matches first
+/// - `--between START END`: instead of `--regex`, show the text between each match of START and
+///   the next match of END in a run
+/// - `--head N`: only show the first N files with results
+/// - `--tail N`: only show the last N files with results
+/// - `--chunk-size N`: hand documents to the worker threads in runs of at least N
+/// - `--dump-json-tree [DIR]` (hidden): instead of searching, write the JSON tree that docx-rs
+///   reads from each document to stdout, or to a .json file under DIR, to debug extraction
+/// - `--strip-invisibles`: remove soft hyphens, zero-width characters and BOMs from the text
+///   before matching
+/// - `--config FILE`: read default options from FILE; otherwise they are read from
+///   `docread.toml` in the current directory or, failing that, the home directory
+/// - `--no-config`: ignore any config file
+/// - `--compound-index`: number each match `RUN-MATCH`, as before, rather than through the
+///   whole document
+/// - `--schema`: print the JSON Schema of the `--json` output and exit
+/// - `--color auto|always|never`: when to color the output (default: auto, which honors
+///   `NO_COLOR`)
+/// - `--min-matches N`: only report documents with at least N matches in all
+/// - `--search-custom-xml`: also search the element text and attribute values of each
+///   document's `customXml/itemN.xml` data parts
+/// - `--ordered`: print the files in the order they were found rather than as they finish
+/// - `--streaming`: search each document's body as it is unzipped rather than from its whole
+///   tree, as is done anyway for very large documents
+/// - `--count-unique [corpus|file]`: count the distinct strings matched, across all documents
+///   or in each one
+/// - `--list-unique`: with `--count-unique`, also list those strings
+/// - `--unknown-words --dictionary FILE`: instead of `--regex`, find the words of each run that
+///   are not in the word list FILE
+/// - `--sentence-index`: show the sentence each match is in, and its number in the document
+/// - `--compare OTHER_DIR`: search OTHER_DIR too, and show the documents, paired by relative
+///   path, whose matched runs differ from those under `--dir`
+/// - `--escape-output`: show control characters around matches as escapes such as `\x0c`
+/// - `--dehyphenate`: match words that were hyphenated at a line break as whole words
+/// - `--require-all-in-paragraph`: show the paragraphs in which every alternative of the
+///   pattern (`a|b|c`) matches, rather than each matching run
+/// - `--max-results K`: stop the whole scan once K matches have been found
+/// - `--count-per-paragraph N`: list the N paragraphs of each file with the most matches
+/// - `--group-separator STR`: write STR after the output of each file instead of `===`
+/// - `--no-group-separator`: write no separator after the output of each file
+/// - `--fail-on-empty`: exit with an error if any document searched has no extractable text,
+///   as when it holds only scanned images
+/// - `--highlight-style STYLE`: also emphasize matches with `bold`, `underline`, `reverse` or
+///   `background` (their color behind the text), or several of them separated by commas
+/// - `--all-document-parts`: also search the document bodies of each package besides
+///   `word/document.xml`, such as the glossary in `word/glossary/document.xml`
+/// - `--progress-interval SECS`: when standard output is not a terminal, write the number of
+///   files searched and matches found so far to standard error every SECS seconds
+/// - `--no-context`: show only the matched text, with no context around it (like `--context 0`)
+/// - `--search-diagrams`: also search the text of each document's SmartArt diagrams and charts,
+///   such as their titles, labels and the values charts cache
+/// - `--trim`: trim the white space from the start of the context before each match and the end
+///   of the context after it
+/// - `--by-author NAME`: report only matches in text that NAME inserted as a tracked change, or
+///   in comments NAME wrote
+/// - `--follow-rels`: also search the subdocuments of a master document, and the documents
+///   imported into it (altChunk), that are parts of its package or local files, and those they
+///   link to in turn
+/// - `--include-ext EXT[=HANDLER]`: also search the files with extension EXT, read as HANDLER
+///   (`docx`, `zip`, `docx.zst` or `tar.zst`), which may be left out for the other
+///   WordprocessingML extensions; may be repeated
 /// - `--help, -h`: show help message
 /// - `--version, -V`: show version information
 ///
@@ -62,16 +721,242 @@ struct Args {
 ///   and all subdirectories, and show 100 characters of context before and after each match.
 ///
 fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
-    let re = Regex::new(&args.regex).unwrap();
-    let n_context_chars = args.context.parse::<usize>()?;
-    process_files(
-        &args.dir,
-        &re,
-        args.quiet,
-        n_context_chars,
-        args.summary,
-        args.unmatched_show,
-    )?;
-    Ok(())
+    let args = parse_args()?;
+    match args.color {
+        ColorChoice::Auto => {}
+        ColorChoice::Always => colored::control::set_override(true),
+        ColorChoice::Never => colored::control::set_override(false),
+    }
+    set_highlight_styles(&args.highlight_style);
+    if args.schema {
+        println!("{}", serde_json::to_string_pretty(&schema())?);
+        return Ok(());
+    }
+    let pattern_opts = PatternOptions {
+        size_limit: args.regex_size_limit,
+        fuzzy: args.fuzzy,
+        // each match must say which alternative it is of, to tell whether all of them matched
+        show_alternative: args.show_alternative || args.require_all_in_paragraph,
+        // the matches of each alternative get a color of their own, where there are colors
+        color_alternatives: colored::control::SHOULD_COLORIZE.should_colorize() && !args.json,
+        exclude_quoted: args.exclude_quoted,
+        normalize: args.unicode_normalize,
+        allow_empty_match: args.allow_empty_match,
+        entities: args.entity.clone(),
+        luhn: args.luhn,
+        between: args
+            .between
+            .as_ref()
+            .map(|patterns| (patterns[0].clone(), patterns[1].clone())),
+        dictionary: match &args.dictionary {
+            Some(path) => Some(Arc::new(Dictionary::load(path)?)),
+            None => None,
+        },
+    };
+    let searcher = build_searcher(args.regex.as_deref().unwrap_or_default(), &pattern_opts)?;
+    if args.explain {
+        print!("{}", explain(&searcher, &pattern_opts));
+        return Ok(());
+    }
+    if searcher.matches_empty() && !args.allow_empty_match {
+        eprintln!(
+            "Warning: '{}' can match the empty string; empty matches are skipped (see --allow-empty-match)\n",
+            args.regex.as_deref().unwrap_or_default()
+        );
+    }
+    let context = if args.no_context {
+        ContextSpec::Chars(0)
+    } else {
+        args.context
+            .parse::<ContextSpec>()?
+            .with_unit(args.context_unit)?
+    };
+    let mut opts = SearchOptions::default();
+    opts.quiet = args.quiet;
+    opts.context = context;
+    opts.summary = args.summary;
+    opts.unmatched_show = args.unmatched_show;
+    opts.timeout = args.timeout_secs.map(Duration::from_secs);
+    opts.verbose = args.verbose;
+    opts.group_by_archive = args.group_by_archive;
+    opts.raw_fallback = args.raw_fallback;
+    opts.html = args.html;
+    opts.first_match_only = args.first_match_only;
+    opts.extract_text = args.extract_text;
+    opts.show_heading = args.show_heading;
+    opts.align = args.align;
+    opts.center = args
+        .center
+        .map(|width| width.unwrap_or_else(terminal_width));
+    opts.para_range = args.para_range;
+    opts.dedupe = args.dedupe;
+    opts.fail_fast = args.fail_fast;
+    opts.json = args.json;
+    opts.pretty = args.pretty;
+    opts.line_buffered = args.line_buffered;
+    opts.cross_run_context = args.cross_run_context;
+    opts.format = args.format;
+    opts.normalize = args.unicode_normalize;
+    opts.embedded = args.embedded;
+    opts.peek = args.peek;
+    opts.max_file_size = args.max_file_size;
+    opts.search_metadata = args.search_metadata;
+    opts.filter_revisions = args.filter_revisions;
+    opts.context_lines = args.context_lines;
+    opts.interactive = args.interactive;
+    opts.table_row_context = args.table_row_context;
+    opts.source_positions = args.source_positions;
+    opts.parallel_files = args.parallel_files;
+    opts.show_formatting = args.show_formatting;
+    opts.since = args.since;
+    opts.para_before = args.para_before;
+    opts.para_after = args.para_after;
+    opts.no_archives = args.no_archives;
+    opts.archives_only = args.archives_only;
+    opts.resume = args.resume.clone();
+    opts.list_numbers = args.list_numbers;
+    opts.rank_paragraphs = args.rank_paragraphs;
+    opts.head = args.head;
+    opts.tail = args.tail;
+    opts.chunk_size = args.chunk_size;
+    opts.dump_json_tree = args.dump_json_tree.clone();
+    opts.strip_invisibles = args.strip_invisibles;
+    opts.compound_index = args.compound_index;
+    opts.min_matches = args.min_matches;
+    opts.search_custom_xml = args.search_custom_xml;
+    opts.ordered = args.ordered;
+    opts.streaming = args.streaming;
+    opts.count_unique = args.count_unique;
+    opts.list_unique = args.list_unique;
+    opts.sentence_index = args.sentence_index;
+    opts.escape_output = args.escape_output;
+    opts.dehyphenate = args.dehyphenate;
+    opts.require_all_in_paragraph = args.require_all_in_paragraph;
+    opts.max_results = args.max_results;
+    opts.count_per_paragraph = args.count_per_paragraph;
+    opts.group_separator = args.group_separator.clone();
+    opts.no_group_separator = args.no_group_separator;
+    opts.all_document_parts = args.all_document_parts;
+    opts.progress_interval = args.progress_interval.map(Duration::from_secs);
+    opts.search_diagrams = args.search_diagrams;
+    opts.trim = args.trim;
+    opts.by_author = args.by_author.clone();
+    opts.follow_rels = args.follow_rels;
+    opts.include_ext = args.include_ext.clone();
+    if let Some(other_dir) = &args.compare {
+        let deltas = compare_dirs(&args.dir, other_dir, &searcher, &opts)?;
+        print_deltas(&deltas, opts.group_separator());
+        return Ok(());
+    }
+    let run = || -> anyhow::Result<()> {
+        let summary = process_files(&args.dir, &searcher, &opts)?;
+        if let Some(message) = summary.no_files_message(&args.dir) {
+            eprintln!("{}\n", message.yellow().bold());
+        }
+        // keep stdout pure JSON, or pure template lines, or the bare dumped trees
+        let dumping = matches!(opts.dump_json_tree, Some(None));
+        if !opts.json && opts.format.is_none() && !dumping {
+            if args.coverage {
+                summary.print_coverage();
+            }
+            if args.list_unique {
+                summary.print_unique_matches();
+            }
+            if !args.no_summary {
+                summary.print_footer(&searcher, &args.dir, opts.summary);
+                if args.verbose {
+                    summary.print_throughput();
+                }
+            }
+        }
+        if let Some(path) = &args.stats_json {
+            summary.write_json(path, &searcher, &args.dir)?;
+        }
+        if args.fail_on_empty && !summary.without_text.is_empty() {
+            let mut names = summary.without_text.clone();
+            names.sort();
+            anyhow::bail!(
+                "{} document(s) have no extractable text: {}",
+                names.len(),
+                names.join(", ")
+            );
+        }
+        Ok(())
+    };
+    if args.watch {
+        if is_url(&args.dir) {
+            anyhow::bail!("--watch cannot be used with a URL");
+        }
+        // clearing the screen would put escapes into the JSON
+        return watch(&args.dir, &opts.searched_extensions(), !args.json, run);
+    }
+    run()
+}
+
+/// Parses the command line, with the options set in the config file in front of it (see
+/// `config_args`), so that those given on the command line override them.
+fn parse_args() -> anyhow::Result<Args> {
+    let cli: Vec<OsString> = std::env::args_os().collect();
+    let command = Args::command();
+    // a first look at the command line alone, to see which config file to read and which of
+    // its options are given anyway; anything amiss is reported by the real parse below
+    let matches = command
+        .clone()
+        .ignore_errors(true)
+        .try_get_matches_from(&cli)
+        .ok();
+    let given = |id: &str| {
+        matches
+            .as_ref()
+            .is_some_and(|matches| matches.value_source(id) == Some(ValueSource::CommandLine))
+    };
+    let path = match &matches {
+        Some(matches) if matches.get_flag("no_config") => None,
+        Some(matches) => match matches.get_one::<PathBuf>("config") {
+            Some(path) => Some(path.clone()),
+            None => find_config(),
+        },
+        None => find_config(),
+    };
+    let config_args = match &path {
+        Some(path) => config_args(&command, path, given)?,
+        None => Vec::new(),
+    };
+    let mut cli = cli.into_iter();
+    Ok(Args::parse_from(
+        cli.next().into_iter().chain(config_args).chain(cli),
+    ))
+}
+
+/// Returns the width of the terminal in columns: that of the terminal standard output is
+/// written to, or else as given by the `COLUMNS` environment variable, or else 80.
+fn terminal_width() -> usize {
+    tty_width()
+        .or_else(|| {
+            std::env::var("COLUMNS")
+                .ok()
+                .and_then(|columns| columns.trim().parse().ok())
+        })
+        .filter(|&columns| columns > 0)
+        .unwrap_or(80)
+}
+
+/// Returns the width in columns of the terminal that standard output is written to, or `None`
+/// if it is not a terminal.
+#[cfg(unix)]
+fn tty_width() -> Option<usize> {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: TIOCGWINSZ only writes the window size into `size`, which outlives the call
+    let status = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+    (status == 0 && size.ws_col > 0).then_some(usize::from(size.ws_col))
+}
+
+#[cfg(not(unix))]
+fn tty_width() -> Option<usize> {
+    None
 }
@@ -0,0 +1,105 @@
+use anyhow::Context;
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use crate::reader::{FileLike, SearchResult};
+
+/// The record of the documents already searched, kept for `--resume`. Each line of the manifest
+/// file identifies one document as it was when it was searched: its path, its size and its
+/// modification time, separated by tabs. A document is skipped only if a line matches it
+/// exactly, so one that has changed since is searched again.
+#[derive(Debug)]
+pub(crate) struct Manifest {
+    path: PathBuf,
+    /// The lines already in the manifest
+    done: HashSet<String>,
+    /// The line to append for each document still to be searched, by source path
+    pending: HashMap<PathBuf, String>,
+    file: Mutex<File>,
+}
+
+/// Returns the manifest line for `file_like`, or `None` if its size or modification time is not
+/// known.
+fn manifest_line(file_like: &FileLike) -> Option<String> {
+    let size = file_like.get_size().ok()?.bytes();
+    let modified = file_like
+        .get_modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    Some(format!(
+        "{}\t{}\t{}",
+        file_like.get_source_path().display(),
+        size,
+        modified
+    ))
+}
+
+impl Manifest {
+    /// Reads the manifest at `path`, if there is one, and opens it for appending, creating it
+    /// if need be.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the manifest cannot be read or opened.
+    pub(crate) fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut done = HashSet::new();
+        if path.exists() {
+            let reader = BufReader::new(
+                File::open(path)
+                    .with_context(|| format!("Failed to open manifest: {}", path.display()))?,
+            );
+            for line in reader.lines() {
+                done.insert(line?);
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open manifest: {}", path.display()))?;
+        Ok(Manifest {
+            path: path.to_path_buf(),
+            done,
+            pending: HashMap::new(),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Removes from `file_surrogates` the documents that the manifest records as searched, and
+    /// remembers how to record each of the others.
+    pub(crate) fn retain_pending(&mut self, file_surrogates: &mut Vec<FileLike>) {
+        file_surrogates.retain(|file_like| match manifest_line(file_like) {
+            Some(line) if self.done.contains(&line) => false,
+            Some(line) => {
+                self.pending.insert(file_like.get_source_path(), line);
+                true
+            }
+            None => true,
+        });
+    }
+
+    /// Appends the document of `result` to the manifest, if it was searched without error or
+    /// timeout, so that it is skipped when the scan is resumed.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the manifest cannot be written.
+    pub(crate) fn record(&self, result: &SearchResult) -> anyhow::Result<()> {
+        if result.maybe_result.is_err() || result.timed_out {
+            return Ok(());
+        }
+        let Some(line) = self.pending.get(&result.source_path) else {
+            return Ok(());
+        };
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{}", line)
+            .and_then(|_| file.flush())
+            .with_context(|| format!("Failed to write manifest: {}", self.path.display()))
+    }
+}
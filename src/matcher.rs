@@ -1,6 +1,16 @@
-use colored::Colorize;
-use regex::Regex;
+use aho_corasick::{AhoCorasick, AhoCorasickKind, Input, MatchKind};
+use anyhow::Context;
+use colored::{Color, ColoredString, Colorize};
+use regex::{Regex, RegexBuilder};
+use std::borrow::Cow;
 use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::dictionary::Dictionary;
+use crate::entity::{self, Entity};
 
 #[macro_export]
 /// Truncate a string to the first `n` characters, or return the string if it is shorter than `n`.
@@ -16,21 +26,659 @@ macro_rules! first_n_chars {
 macro_rules! last_n_chars {
     ($s:expr, $n:expr) => {{
         let s: &str = $s;
+        let n: usize = $n;
         let len = s.len();
-        s.char_indices()
-            .rev()
-            .nth($n - 1)
-            .map(|(i, _)| &s[i..len])
-            .unwrap_or(s)
+        match n.checked_sub(1) {
+            Some(last) => s
+                .char_indices()
+                .rev()
+                .nth(last)
+                .map(|(i, _)| &s[i..len])
+                .unwrap_or(s),
+            None => &s[len..],
+        }
     }};
 }
 
+/// How much context to show on either side of a match.
+///
+/// Parsed from the `--context` argument: a bare number means characters, a number followed by
+/// `w` means words, a number followed by `s` means sentences, and a number followed by `%` means
+/// a percentage of the length of the run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContextSpec {
+    /// Show up to N characters before and after the match
+    Chars(usize),
+    /// Show up to N whitespace-separated words before and after the match
+    Words(usize),
+    /// Show N sentences, counting the one containing the match, on either side of it
+    Sentences(usize),
+    /// Show up to N bytes before and after the match, rounded down to whole characters
+    Bytes(usize),
+    /// Show the line containing the match, and up to N lines before and after it
+    Lines(usize),
+    /// Show up to N percent of the length of the run, in characters, before and after the
+    /// match (see `for_run`)
+    Percent(usize),
+}
+
+/// The unit in which a bare-number `--context` is measured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum ContextUnit {
+    #[default]
+    Chars,
+    Bytes,
+}
+
+/// The Unicode normalization form that text and pattern are brought to before matching.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Normalization {
+    /// Canonical composition: "e" followed by a combining acute accent becomes "é"
+    Nfc,
+    /// Canonical decomposition: "é" becomes "e" followed by a combining acute accent
+    Nfd,
+}
+
+/// Returns `text` in the normalization form `form`, or unchanged if `form` is `None`.
+pub(crate) fn normalize(text: &str, form: Option<Normalization>) -> Cow<'_, str> {
+    match form {
+        None => Cow::Borrowed(text),
+        Some(Normalization::Nfc) => Cow::Owned(text.nfc().collect()),
+        Some(Normalization::Nfd) => Cow::Owned(text.nfd().collect()),
+    }
+}
+
+/// The invisible characters that `--strip-invisibles` removes from the text before matching:
+/// the soft hyphen, the zero-width space, non-joiner and joiner, the word joiner, and the byte
+/// order mark (also known as the zero-width no-break space).
+pub(crate) const INVISIBLES: [char; 6] = [
+    '\u{ad}', '\u{200b}', '\u{200c}', '\u{200d}', '\u{2060}', '\u{feff}',
+];
+
+/// Returns `text` without the characters in `INVISIBLES`, so that a word that Word has split
+/// with a soft hyphen or a zero-width space matches as the plain word.
+pub(crate) fn strip_invisibles(text: &str) -> Cow<'_, str> {
+    if text.contains(INVISIBLES) {
+        Cow::Owned(text.replace(INVISIBLES, ""))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// Returns true if `--escape-output` shows `c` as an escape: it is a control character other
+/// than a line break or a tab, which are laid out as usual.
+fn is_escaped(c: char) -> bool {
+    c.is_control() && c != '\n' && c != '\t'
+}
+
+/// Returns `text` with each control character that could upset a terminal, such as a vertical
+/// tab or a form feed, written as a visible escape like `\x0c`. Line breaks and tabs are kept.
+pub(crate) fn escape_controls(text: &str) -> Cow<'_, str> {
+    if !text.contains(is_escaped) {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len() + 8);
+    for c in text.chars() {
+        if is_escaped(c) {
+            // every control character is below U+00A0
+            escaped.push_str(&format!("\\x{:02x}", c as u32));
+        } else {
+            escaped.push(c);
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+impl Default for ContextSpec {
+    fn default() -> Self {
+        ContextSpec::Chars(75)
+    }
+}
+
+impl FromStr for ContextSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> anyhow::Result<Self> {
+        let (digits, make): (&str, fn(usize) -> ContextSpec) =
+            if let Some(digits) = spec.strip_suffix('w') {
+                (digits, ContextSpec::Words)
+            } else if let Some(digits) = spec.strip_suffix('s') {
+                (digits, ContextSpec::Sentences)
+            } else if let Some(digits) = spec.strip_suffix('%') {
+                (digits, ContextSpec::Percent)
+            } else {
+                (spec, ContextSpec::Chars)
+            };
+        let n = digits.parse::<usize>().with_context(|| {
+            format!(
+                "Invalid context '{}': expected a number of characters, optionally followed by 'w' (words), 's' (sentences) or '%' (of the run)",
+                spec
+            )
+        })?;
+        Ok(make(n))
+    }
+}
+
+impl ContextSpec {
+    /// Reinterprets a bare-number context in the given `unit`.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if `unit` is `Bytes` and the context is counted in words or sentences.
+    pub fn with_unit(self, unit: ContextUnit) -> anyhow::Result<Self> {
+        match (self, unit) {
+            (ContextSpec::Chars(n), ContextUnit::Bytes) => Ok(ContextSpec::Bytes(n)),
+            (
+                ContextSpec::Words(_)
+                | ContextSpec::Sentences(_)
+                | ContextSpec::Lines(_)
+                | ContextSpec::Percent(_),
+                ContextUnit::Bytes,
+            ) => {
+                anyhow::bail!("--context-unit bytes requires a bare number for --context")
+            }
+            (spec, _) => Ok(spec),
+        }
+    }
+
+    /// Returns the context to use for the matches in `run`: a percentage becomes that share of
+    /// the number of characters in `run`, rounded to the nearest whole character (halves round
+    /// up), so that 20% of a 12-character run is 2 characters and 25% of a 10-character run is
+    /// 3. A share of less than half a character is no context at all. Any other context is
+    /// returned as it is.
+    pub fn for_run(self, run: &str) -> ContextSpec {
+        match self {
+            ContextSpec::Percent(percent) => {
+                ContextSpec::Chars((run.chars().count() * percent + 50) / 100)
+            }
+            spec => spec,
+        }
+    }
+
+    /// Returns the leading part of `s` to show after a match. A percentage must have been
+    /// turned into characters with `for_run` first.
+    fn head<'a>(&self, s: &'a str) -> &'a str {
+        match *self {
+            ContextSpec::Chars(n) => first_n_chars!(s, n),
+            ContextSpec::Words(n) => first_n_words(s, n),
+            ContextSpec::Sentences(n) => first_n_sentences(s, n),
+            ContextSpec::Bytes(n) => &s[..s.floor_char_boundary(n)],
+            ContextSpec::Lines(n) => first_n_lines(s, n + 1),
+            ContextSpec::Percent(_) => unreachable!("percentages are resolved by for_run"),
+        }
+    }
+
+    /// Returns the trailing part of `s` to show before a match, as `head` does.
+    fn tail<'a>(&self, s: &'a str) -> &'a str {
+        match *self {
+            ContextSpec::Chars(n) => last_n_chars!(s, n),
+            ContextSpec::Words(n) => last_n_words(s, n),
+            ContextSpec::Sentences(n) => last_n_sentences(s, n),
+            ContextSpec::Bytes(n) => &s[s.ceil_char_boundary(s.len().saturating_sub(n))..],
+            ContextSpec::Lines(n) => last_n_lines(s, n + 1),
+            ContextSpec::Percent(_) => unreachable!("percentages are resolved by for_run"),
+        }
+    }
+}
+
+/// Returns the byte spans of the whitespace-separated words in `s`.
+fn word_spans(s: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (i, c) in s.char_indices() {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some(i),
+            (true, Some(word_start)) => {
+                spans.push((word_start, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(word_start) = start {
+        spans.push((word_start, s.len()));
+    }
+    spans
+}
+
+/// Truncate a string after its first `n` words, or return the string if it has fewer.
+fn first_n_words(s: &str, n: usize) -> &str {
+    if n == 0 {
+        return "";
+    }
+    word_spans(s)
+        .get(n - 1)
+        .map(|&(_, end)| &s[..end])
+        .unwrap_or(s)
+}
+
+/// Truncate a string to its last `n` words, or return the string if it has fewer.
+fn last_n_words(s: &str, n: usize) -> &str {
+    if n == 0 {
+        return "";
+    }
+    let spans = word_spans(s);
+    if spans.len() > n {
+        &s[spans[spans.len() - n].0..]
+    } else {
+        s
+    }
+}
+
+/// Returns the byte offsets just past each sentence terminator (`.`, `!` or `?` followed by
+/// whitespace or the end of the string) in `s`.
+fn sentence_ends(s: &str) -> Vec<usize> {
+    let mut ends = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if matches!(c, '.' | '!' | '?') {
+            match chars.peek() {
+                Some((_, next)) if !next.is_whitespace() => {}
+                _ => ends.push(i + c.len_utf8()),
+            }
+        }
+    }
+    ends
+}
+
+/// Returns the byte spans of the sentences of `s`, as `--context Ns` counts them: each ends
+/// just past a sentence terminator (see `sentence_ends`), or at the end of `s` for the text
+/// after the last one, and starts after the whitespace that follows the one before it. Blank
+/// text after the last terminator is not a sentence.
+pub(crate) fn sentence_spans(s: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    for end in sentence_ends(s).into_iter().chain([s.len()]) {
+        let sentence = &s[start..end];
+        let leading = sentence.len() - sentence.trim_start().len();
+        if leading < sentence.len() {
+            spans.push((start + leading, end));
+        }
+        start = end;
+    }
+    spans
+}
+
+/// Truncate a string after its `n`th sentence, or return the string if it has fewer.
+fn first_n_sentences(s: &str, n: usize) -> &str {
+    if n == 0 {
+        return "";
+    }
+    sentence_ends(s)
+        .get(n - 1)
+        .map(|&end| &s[..end])
+        .unwrap_or(s)
+}
+
+/// Truncate a string to its last `n` sentences, where the unterminated text at the end of the
+/// string counts as the last sentence, or return the string if it has fewer.
+fn last_n_sentences(s: &str, n: usize) -> &str {
+    if n == 0 {
+        return "";
+    }
+    let ends = sentence_ends(s);
+    if ends.len() >= n {
+        s[ends[ends.len() - n]..].trim_start()
+    } else {
+        s
+    }
+}
+
+/// Truncate a string before the end of its `n`th line, or return the string if it has fewer
+/// lines.
+fn first_n_lines(s: &str, n: usize) -> &str {
+    if n == 0 {
+        return "";
+    }
+    s.match_indices('\n')
+        .nth(n - 1)
+        .map(|(i, _)| &s[..i])
+        .unwrap_or(s)
+}
+
+/// Truncate a string to its last `n` lines, where the unterminated text at the end of the string
+/// counts as the last line, or return the string if it has fewer.
+fn last_n_lines(s: &str, n: usize) -> &str {
+    if n == 0 {
+        return "";
+    }
+    s.rmatch_indices('\n')
+        .nth(n - 1)
+        .map(|(i, _)| &s[i + 1..])
+        .unwrap_or(s)
+}
+
+/// Compiles `pattern` into a `Regex`. If `size_limit` is given, it caps (in bytes) both the size
+/// of the compiled program and the lazy DFA cache, so that overly complex patterns are rejected
+/// at compile time instead of consuming unbounded memory.
+///
+/// # Errors
+///
+/// Will return an error if the pattern is invalid or exceeds the size limit.
+pub(crate) fn build_regex(pattern: &str, size_limit: Option<usize>) -> anyhow::Result<Regex> {
+    let mut builder = RegexBuilder::new(pattern);
+    if let Some(limit) = size_limit {
+        builder.size_limit(limit).dfa_size_limit(limit);
+    }
+    builder
+        .build()
+        .with_context(|| format!("Failed to compile regex: {}", pattern))
+}
+
+/// The options that determine how the `--regex` pattern is turned into a `Searcher`.
+#[derive(Debug, Default, Clone)]
+pub struct PatternOptions {
+    /// Cap on the size of the compiled regex, as in `build_regex`
+    pub size_limit: Option<usize>,
+    /// Match the pattern as a literal within this many edits, instead of as a regex
+    pub fuzzy: Option<usize>,
+    /// Report which top-level alternative of the pattern matched
+    pub show_alternative: bool,
+    /// Tell the top-level alternatives of the pattern apart, so that the matches of each are
+    /// highlighted in a color of their own (see `highlight_color`), without reporting them
+    pub color_alternatives: bool,
+    /// Drop matches inside quotes or parentheses
+    pub exclude_quoted: bool,
+    /// Normalization applied to the pattern, and to the text it is matched against
+    pub normalize: Option<Normalization>,
+    /// Keep the zero-width matches of a pattern that can match the empty string
+    pub allow_empty_match: bool,
+    /// Built-in patterns searched for as well as the pattern, as by `entity::combine`
+    pub entities: Vec<Entity>,
+    /// Drop the card numbers that fail the Luhn checksum
+    pub luhn: bool,
+    /// Find the text between a match of the first regex and the next match of the second,
+    /// instead of matching the pattern
+    pub between: Option<(String, String)>,
+    /// Find the words that are not in this dictionary, instead of matching the pattern
+    pub dictionary: Option<Arc<Dictionary>>,
+}
+
+/// Builds the `Searcher` for `pattern` as `opts` ask. This is the one place where the
+/// pattern given on the command line is turned into what is actually matched (see `explain`).
+/// If `opts.between` or `opts.dictionary` is set, `pattern` is not used.
+///
+/// # Errors
+///
+/// Will return an error if the pattern is not a valid regex or exceeds the size limit.
+pub fn build_searcher(pattern: &str, opts: &PatternOptions) -> anyhow::Result<Searcher> {
+    let pattern = entity::combine(pattern, &opts.entities);
+    let pattern = normalize(&pattern, opts.normalize);
+    let mut searcher = match (&opts.dictionary, &opts.between, opts.fuzzy) {
+        (Some(dictionary), _, _) => Searcher::UnknownWords(Arc::clone(dictionary)),
+        (None, Some((start, end)), _) => Searcher::Between {
+            start: build_regex(&normalize(start, opts.normalize), opts.size_limit)?,
+            end: build_regex(&normalize(end, opts.normalize), opts.size_limit)?,
+        },
+        (None, None, Some(max_distance)) => Searcher::Fuzzy {
+            pattern: pattern.to_string(),
+            max_distance,
+        },
+        (None, None, None) if opts.show_alternative => {
+            build_alternatives(&pattern, opts.size_limit, true)?
+        }
+        (None, None, None) => match literal_alternatives(&pattern) {
+            Some(literals) if literals.len() >= LITERALS_THRESHOLD => {
+                build_literals(&pattern, literals)?
+            }
+            _ if opts.color_alternatives && split_alternatives(&pattern).len() > 1 => {
+                build_alternatives(&pattern, opts.size_limit, false)?
+            }
+            _ => Searcher::Regex(build_regex(&pattern, opts.size_limit)?),
+        },
+    };
+    if !opts.allow_empty_match && searcher.matches_empty() {
+        searcher = Searcher::NonEmpty(Box::new(searcher));
+    }
+    if opts.luhn {
+        searcher = Searcher::LuhnChecked {
+            inner: Box::new(searcher),
+            card: Regex::new(&format!("^(?:{})$", Entity::Creditcard.pattern()))?,
+        };
+    }
+    if opts.exclude_quoted {
+        searcher = Searcher::ExcludeQuoted(Box::new(searcher));
+    }
+    Ok(searcher)
+}
+
+/// Describes, one step per line, how `searcher` (built by `build_searcher` with `opts`) finds
+/// matches: the effective pattern that is compiled, and what is done around it.
+pub fn explain(searcher: &Searcher, opts: &PatternOptions) -> String {
+    let mut lines = Vec::new();
+    if let Some(form) = opts.normalize {
+        let name = match form {
+            Normalization::Nfc => "NFC",
+            Normalization::Nfd => "NFD",
+        };
+        lines.push(format!("normalize text and pattern to {}", name));
+    }
+    let mut searcher = searcher;
+    let mut exclude_quoted = false;
+    let mut non_empty = false;
+    let mut luhn = false;
+    loop {
+        searcher = match searcher {
+            Searcher::ExcludeQuoted(inner) => {
+                exclude_quoted = true;
+                inner
+            }
+            Searcher::NonEmpty(inner) => {
+                non_empty = true;
+                inner
+            }
+            Searcher::LuhnChecked { inner, .. } => {
+                luhn = true;
+                inner
+            }
+            _ => break,
+        };
+    }
+    match searcher {
+        Searcher::Regex(re) => lines.push(format!("regex: {}", re.as_str())),
+        Searcher::Alternatives {
+            regex,
+            alternatives,
+            report,
+            ..
+        } => {
+            lines.push(format!("regex: {}", regex.as_str()));
+            let role = if *report { "reports" } else { "colors" };
+            for (index, alternative) in alternatives.iter().enumerate() {
+                lines.push(format!(
+                    "  group {} {} alternative {}",
+                    alternative_group(index),
+                    role,
+                    alternative
+                ));
+            }
+        }
+        Searcher::Fuzzy {
+            pattern,
+            max_distance,
+        } => lines.push(format!(
+            "fuzzy literal: {} (within {} edits)",
+            pattern, max_distance
+        )),
+        Searcher::Literals { literals, .. } => lines.push(format!(
+            "literals (Aho-Corasick, no regex): {}",
+            literals.join(", ")
+        )),
+        Searcher::Between { start, end } => {
+            lines.push(format!("start regex: {}", start.as_str()));
+            lines.push(format!("end regex: {}", end.as_str()));
+            lines.push("match the text between each start and the next end".to_string());
+        }
+        Searcher::UnknownWords(dictionary) => lines.push(format!(
+            "words not in the dictionary of {} words (case-folded, no regex)",
+            dictionary.len()
+        )),
+        Searcher::ExcludeQuoted(_) | Searcher::NonEmpty(_) | Searcher::LuhnChecked { .. } => {
+            unreachable!("unwrapped above")
+        }
+    }
+    let compiles_regex = !matches!(searcher, Searcher::Fuzzy { .. } | Searcher::Literals { .. });
+    if let (Some(limit), true) = (opts.size_limit, compiles_regex) {
+        lines.push(format!("size limit: {} bytes", limit));
+    }
+    if non_empty {
+        lines.push("drop empty matches".to_string());
+    }
+    if luhn {
+        lines.push("drop card numbers that fail the Luhn check".to_string());
+    }
+    if exclude_quoted {
+        lines.push("drop matches inside quotes or parentheses".to_string());
+    }
+    lines.join("\n") + "\n"
+}
+
+/// The name of the capture group that `build_alternatives` wraps the `index`th alternative in.
+fn alternative_group(index: usize) -> String {
+    format!("docread_alt{}", index)
+}
+
+/// Splits `pattern` at its top-level `|` operators, leaving alone any `|` that is escaped, in a
+/// character class or inside a group.
+fn split_alternatives(pattern: &str) -> Vec<&str> {
+    let mut alternatives = Vec::new();
+    let mut depth = 0usize;
+    // nesting depth of character classes, and whether the class has just been opened (so
+    // that a `]` there is a literal)
+    let mut class_depth = 0usize;
+    let mut class_start = false;
+    let mut start = 0;
+    let mut chars = pattern.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let at_class_start = std::mem::take(&mut class_start);
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '[' => {
+                class_depth += 1;
+                class_start = true;
+                if chars.peek().is_some_and(|&(_, next)| next == '^') {
+                    chars.next();
+                }
+            }
+            ']' if class_depth > 0 && !at_class_start => class_depth -= 1,
+            _ if class_depth > 0 => {}
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            '|' if depth == 0 => {
+                alternatives.push(&pattern[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    alternatives.push(&pattern[start..]);
+    alternatives
+}
+
+/// Compiles `pattern` into a `Searcher::Alternatives`, which reports which of the pattern's
+/// top-level alternatives produced each match. Each alternative is wrapped in a named group; a
+/// leading inline flag group such as `(?i)` is kept outside the wrapping, so that it still
+/// applies to every alternative. The size limit is applied as in `build_regex`. Unless
+/// `report` is set, the alternatives are told apart only to color their matches, and are not
+/// given with them.
+///
+/// # Errors
+///
+/// Will return an error if the pattern is invalid or exceeds the size limit.
+pub(crate) fn build_alternatives(
+    pattern: &str,
+    size_limit: Option<usize>,
+    report: bool,
+) -> anyhow::Result<Searcher> {
+    // the original pattern is compiled first so that its errors are reported against it
+    build_regex(pattern, size_limit)?;
+    let flags_len = pattern
+        .strip_prefix("(?")
+        .and_then(|rest| rest.find(')').map(|close| (rest, close)))
+        .filter(|(rest, close)| {
+            rest[..*close]
+                .chars()
+                .all(|c| c.is_ascii_alphabetic() || c == '-')
+        })
+        .map_or(0, |(_, close)| close + 3);
+    let (flags, body) = pattern.split_at(flags_len);
+    let alternatives: Vec<String> = split_alternatives(body)
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    let wrapped: Vec<String> = alternatives
+        .iter()
+        .enumerate()
+        .map(|(index, alternative)| format!("(?P<{}>{})", alternative_group(index), alternative))
+        .collect();
+    let regex = build_regex(&format!("{}{}", flags, wrapped.join("|")), size_limit)?;
+    Ok(Searcher::Alternatives {
+        regex,
+        pattern: pattern.to_string(),
+        alternatives,
+        report,
+    })
+}
+
+/// Returns the alternatives of `pattern` if it is nothing but two or more non-empty literal
+/// strings joined by `|`, with no regex metacharacters (escaped or not) anywhere.
+fn literal_alternatives(pattern: &str) -> Option<Vec<&str>> {
+    let alternatives = split_alternatives(pattern);
+    let all_literal = alternatives
+        .iter()
+        .all(|alternative| !alternative.is_empty() && regex::escape(alternative) == *alternative);
+    (alternatives.len() > 1 && all_literal).then_some(alternatives)
+}
+
+/// The number of literal alternatives from which `build_searcher` matches them with an
+/// Aho-Corasick automaton rather than the regex engine. Timed run by run with
+/// examples/literals.rs, the two are level up to about 2000 literals, and from 3000 on the
+/// automaton searches about a third faster.
+const LITERALS_THRESHOLD: usize = 3000;
+
+/// Builds a `Searcher::Literals` that finds `literals`, the alternatives of `pattern`, with an
+/// Aho-Corasick automaton. With leftmost-first matching it finds the same matches as the regex
+/// `pattern` would, so it can stand in for it. The automaton is always built as a DFA.
+///
+/// # Errors
+///
+/// Will return an error if the automaton cannot be built.
+fn build_literals(pattern: &str, literals: Vec<&str>) -> anyhow::Result<Searcher> {
+    let automaton = AhoCorasick::builder()
+        .match_kind(MatchKind::LeftmostFirst)
+        .kind(Some(AhoCorasickKind::DFA))
+        .build(&literals)
+        .with_context(|| format!("Failed to build automaton for: {}", pattern))?;
+    Ok(Searcher::Literals {
+        automaton,
+        pattern: pattern.to_string(),
+        literals: literals.into_iter().map(str::to_string).collect(),
+    })
+}
+
+/// A match with the context on either side of it, as the segmenting functions below return it.
 #[derive(Debug)]
-pub(crate) struct MatchTriple(
-    String, //preamble
-    String, //matched
-    String, //postamble
-);
+#[non_exhaustive]
+pub struct MatchTriple {
+    /// The context before the match
+    pub pre: String,
+    /// The matched text
+    pub matched: String,
+    /// The context after the match
+    pub post: String,
+    /// The alternative of the pattern that matched, if reported
+    pub alternative: Option<String>,
+    /// The index of the alternative that matched, if known (see `highlight_color`)
+    pub alternative_index: Option<usize>,
+    /// The length of the searched run, in characters
+    pub run_len: usize,
+    /// True if the preamble was cut short by the context
+    pub pre_truncated: bool,
+    /// True if the postamble was cut short by the context
+    pub post_truncated: bool,
+}
 
 impl FromIterator<String> for MatchTriple {
     /// Creates a new `MatchTriple` from an iterator of `String`s.
@@ -39,64 +687,795 @@ impl FromIterator<String> for MatchTriple {
     /// becomes the matched text, and the third element becomes the postamble.
     ///
     /// If the iterator does not contain enough elements, empty strings are used for
-    /// any missing elements.
+    /// any missing elements. No alternative is recorded, the run is taken to be the three
+    /// joined, and neither side is marked as truncated.
     ///
     /// # Example
     ///
     ///
     fn from_iter<T: IntoIterator<Item = String>>(iter: T) -> Self {
         let mut iter = iter.into_iter();
-        MatchTriple(
+        let (pre, matched, post) = (
             iter.next().unwrap_or_default(),
             iter.next().unwrap_or_default(),
             iter.next().unwrap_or_default(),
+        );
+        let run_len = [&pre, &matched, &post]
+            .iter()
+            .map(|part| part.chars().count())
+            .sum();
+        MatchTriple {
+            pre,
+            matched,
+            post,
+            alternative: None,
+            alternative_index: None,
+            run_len,
+            pre_truncated: false,
+            post_truncated: false,
+        }
+    }
+}
+
+impl MatchTriple {
+    /// Writes the control characters of the preamble, match and postamble as visible escapes
+    /// (see `escape_controls`), for `--escape-output`.
+    pub(crate) fn escape(&mut self) {
+        for part in [&mut self.pre, &mut self.matched, &mut self.post] {
+            if let Cow::Owned(escaped) = escape_controls(part) {
+                *part = escaped;
+            }
+        }
+    }
+
+    /// Trims the white space from the start of the preamble and the end of the postamble, where
+    /// the context was cut off, for `--trim`. The white space next to the match is kept.
+    pub(crate) fn trim_ends(&mut self) {
+        self.pre
+            .drain(..self.pre.len() - self.pre.trim_start().len());
+        self.post.truncate(self.post.trim_end().len());
+    }
+
+    /// Pads the preamble on the left with spaces to a display width of at least `width` columns,
+    /// so that the matches of successive triples line up when printed one per line.
+    pub(crate) fn align(&mut self, width: usize) {
+        let padding = width.saturating_sub(display_width(&self.pre));
+        self.pre.insert_str(0, &" ".repeat(padding));
+    }
+
+    /// Trims the preamble and postamble, and pads the preamble on the left, so that the triple
+    /// takes `width` display columns with the match in the middle, as in a keyword-in-context
+    /// concordance. Line breaks, tabs and other control characters in the context become
+    /// spaces. A match wider than `width` is kept whole, with no context.
+    pub(crate) fn center(&mut self, width: usize) {
+        let flatten = |s: &str| -> String {
+            s.chars()
+                .map(|c| if c.is_control() { ' ' } else { c })
+                .collect()
+        };
+        let matched = display_width(&self.matched);
+        let left = width.saturating_sub(matched) / 2;
+        let right = width.saturating_sub(matched + left);
+
+        let preamble = flatten(&self.pre);
+        let (mut kept, mut start) = (0, preamble.len());
+        for (i, c) in preamble.char_indices().rev() {
+            if kept + char_width(c) > left {
+                break;
+            }
+            kept += char_width(c);
+            start = i;
+        }
+        self.pre = format!("{}{}", " ".repeat(left - kept), &preamble[start..]);
+
+        let postamble = flatten(&self.post);
+        let (mut kept, mut end) = (0, 0);
+        for (i, c) in postamble.char_indices() {
+            if kept + char_width(c) > right {
+                break;
+            }
+            kept += char_width(c);
+            end = i + c.len_utf8();
+        }
+        self.post = postamble[..end].to_string();
+    }
+}
+
+/// Returns the number of terminal columns needed to display `s`. Combining marks and other
+/// zero-width characters take no columns, East Asian wide and fullwidth characters (and most
+/// emoji) take two, and everything else takes one.
+pub(crate) fn display_width(s: &str) -> usize {
+    s.chars().map(char_width).sum()
+}
+
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x0300..=0x036F
+        | 0x0483..=0x0489
+        | 0x0591..=0x05BD
+        | 0x064B..=0x065F
+        | 0x1AB0..=0x1AFF
+        | 0x1DC0..=0x1DFF
+        | 0x061C
+        | 0x200B..=0x200F
+        | 0x202A..=0x202E
+        | 0x2066..=0x2069
+        | 0x20D0..=0x20FF
+        | 0xFE00..=0xFE0F
+        | 0xFE20..=0xFE2F
+        | 0xFEFF => 0,
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// The colors that matches are highlighted in, by the index of the alternative of the pattern
+/// that matched, in turn.
+const HIGHLIGHT_COLORS: [Color; 6] = [
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+];
+
+/// Returns the color to highlight a match in: that of its alternative, if known, cycling
+/// through `HIGHLIGHT_COLORS`, or red.
+pub(crate) fn highlight_color(alternative: Option<usize>) -> Color {
+    match alternative {
+        Some(index) => HIGHLIGHT_COLORS[index % HIGHLIGHT_COLORS.len()],
+        None => Color::Red,
+    }
+}
+
+/// A way of emphasizing matches besides their color, chosen with `--highlight-style`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum HighlightStyle {
+    Bold,
+    Underline,
+    /// Swap the foreground and background colors
+    Reverse,
+    /// Color the background of the match instead of its text
+    Background,
+}
+
+impl HighlightStyle {
+    /// Returns the bit that stands for the style in `HIGHLIGHT_STYLES`.
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// The styles that `MatchTriple`'s `Display` highlights matches with, as the bits of their
+/// `HighlightStyle::bit`, set once for the whole run by `set_highlight_styles`.
+static HIGHLIGHT_STYLES: AtomicU8 = AtomicU8::new(0);
+
+/// Sets the styles, besides their color, that matches are highlighted with from now on.
+pub fn set_highlight_styles(styles: &[HighlightStyle]) {
+    let bits = styles.iter().fold(0, |bits, style| bits | style.bit());
+    HIGHLIGHT_STYLES.store(bits, Ordering::Relaxed);
+}
+
+/// Returns `text` highlighted in `color` with the styles whose bits are set in `styles`: in the
+/// background if `HighlightStyle::Background` is one of them, and otherwise in the foreground.
+/// Like all colored output, it is left plain when coloring is turned off.
+fn highlight(text: &str, color: Color, styles: u8) -> ColoredString {
+    let has = |style: HighlightStyle| styles & style.bit() != 0;
+    let mut highlighted = if has(HighlightStyle::Background) {
+        text.on_color(color)
+    } else {
+        text.color(color)
+    };
+    if has(HighlightStyle::Bold) {
+        highlighted = highlighted.bold();
+    }
+    if has(HighlightStyle::Underline) {
+        highlighted = highlighted.underline();
+    }
+    if has(HighlightStyle::Reverse) {
+        highlighted = highlighted.reversed();
+    }
+    highlighted
+}
+
+/// The left-to-right isolate and the pop directional isolate, which `MatchTriple`'s `Display`
+/// puts around a match in bidirectional text.
+const LEFT_TO_RIGHT_ISOLATE: char = '\u{2066}';
+const POP_DIRECTIONAL_ISOLATE: char = '\u{2069}';
+
+/// Returns the character that closes the bidi embedding, override or isolate that `c` opens, or
+/// `None` if `c` opens none.
+fn bidi_closer(c: char) -> Option<char> {
+    match c {
+        '\u{202A}' | '\u{202B}' | '\u{202D}' | '\u{202E}' => Some('\u{202C}'),
+        '\u{2066}' | '\u{2067}' | '\u{2068}' => Some(POP_DIRECTIONAL_ISOLATE),
+        _ => None,
+    }
+}
+
+/// Returns true if `c` is a bidi formatting character: an embedding, override or isolate, the
+/// character that closes one, or a directional mark.
+fn is_bidi_control(c: char) -> bool {
+    bidi_closer(c).is_some()
+        || matches!(
+            c,
+            '\u{202C}' | '\u{2069}' | '\u{200E}' | '\u{200F}' | '\u{061C}'
         )
+}
+
+/// Returns true if `c` belongs to a right-to-left script, such as Hebrew or Arabic.
+fn is_right_to_left(c: char) -> bool {
+    matches!(c as u32, 0x0590..=0x08FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFE | 0x10800..=0x10FFF | 0x1E800..=0x1EFFF)
+}
+
+/// Returns `s` with its bidi embeddings, overrides and isolates balanced, as they may not be
+/// once context has been cut from the text around them: a character that closes one that was
+/// not opened in `s` is dropped, and those left open at the end of `s` are closed, innermost
+/// first. Each piece of a match's context can then be printed without its directions leaking
+/// into the rest of the line.
+pub(crate) fn balance_bidi(s: &str) -> Cow<'_, str> {
+    if !s.contains(is_bidi_control) {
+        return Cow::Borrowed(s);
+    }
+    let mut balanced = String::with_capacity(s.len());
+    // the closers of the embeddings and isolates open at this point, innermost last
+    let mut open: Vec<char> = Vec::new();
+    for c in s.chars() {
+        if let Some(closer) = bidi_closer(c) {
+            open.push(closer);
+        } else if c == POP_DIRECTIONAL_ISOLATE {
+            // closes the innermost isolate, and any embeddings opened inside it
+            match open
+                .iter()
+                .rposition(|&closer| closer == POP_DIRECTIONAL_ISOLATE)
+            {
+                Some(position) => open.truncate(position),
+                None => continue,
+            }
+        } else if c == '\u{202C}' {
+            if open.last() == Some(&'\u{202C}') {
+                open.pop();
+            } else {
+                continue;
+            }
+        }
+        balanced.push(c);
+    }
+    balanced.extend(open.into_iter().rev());
+    if balanced == s {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(balanced)
     }
 }
 
 impl Display for MatchTriple {
+    /// Writes the preamble, the match, highlighted in its color with the styles set by
+    /// `set_highlight_styles`, and the postamble. If any of them holds
+    /// right-to-left text or bidi formatting characters, the match is wrapped in a left-to-right
+    /// isolate, so that a terminal applying the bidi algorithm cannot reorder it with the text
+    /// around it.
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{}{}{}", self.0, self.1.red(), self.2)
+        let bidi = [&self.pre, &self.matched, &self.post]
+            .iter()
+            .any(|part| part.contains(|c| is_right_to_left(c) || is_bidi_control(c)));
+        let matched = highlight(
+            &self.matched,
+            highlight_color(self.alternative_index),
+            HIGHLIGHT_STYLES.load(Ordering::Relaxed),
+        );
+        if bidi {
+            write!(
+                f,
+                "{}{}{}{}{}",
+                self.pre, LEFT_TO_RIGHT_ISOLATE, matched, POP_DIRECTIONAL_ISOLATE, self.post
+            )
+        } else {
+            write!(f, "{}{}{}", self.pre, matched, self.post)
+        }
     }
 }
 
 /// Segment the given string `s` into a vector of `MatchTriple`s based on the matches of the
-/// regular expression `re`. The first element of each `MatchTriple` is the text preceding the
-/// match, the second element is the matched text itself, and the third element is the text
-/// following the match. If the regular expression matches the beginning of the string, the first
-/// element of the `MatchTriple` will be an empty string. If the regular expression matches the end
-/// of the string, the third element of the `MatchTriple` will be an empty string.
-pub(crate) fn segment_on_regex(s: &str, re: &Regex, context_len: usize) -> Vec<MatchTriple> {
-    let mut segments = Vec::new();
+/// regular expression `re`. The `pre` of each `MatchTriple` is the text preceding the match,
+/// its `matched` the matched text itself, and its `post` the text following the match. If the
+/// regular expression matches the beginning of the string, `pre` will be an empty string, and
+/// if it matches the end of the string, `post` will be. The preamble and postamble are
+/// truncated according to `context`, a percentage being taken of the length of `s` (see
+/// `ContextSpec::for_run`). Each `MatchTriple` records the length of `s` in characters, and
+/// whether its preamble and postamble were cut short, for callers that offer to show more.
+pub(crate) fn segment_on_regex(s: &str, re: &Regex, context: ContextSpec) -> Vec<MatchTriple> {
+    segment_on_spans(
+        s,
+        re.find_iter(s).map(|m| (m.start(), m.end())),
+        context.for_run(s),
+    )
+}
+
+/// Segment the given string `s` into a vector of `MatchTriple`s around the given byte `spans` of
+/// matches, which must be in order and must not overlap. See `segment_on_regex`. The bidi
+/// embeddings and isolates of each piece are balanced (see `balance_bidi`).
+fn segment_on_spans(
+    s: &str,
+    spans: impl IntoIterator<Item = (usize, usize)>,
+    context: ContextSpec,
+) -> Vec<MatchTriple> {
+    // each piece, with whether the context cut it short
+    let mut segments: Vec<(String, bool)> = Vec::new();
     let mut start = 0;
     let mut end;
-    let mut end_of_prev_match = 0usize;
-    for m in re.find_iter(s) {
-        end = m.start();
+    let mut end_of_prev_match: Option<usize> = None;
+    for (match_start, match_end) in spans {
+        end = match_start;
         // push postamble if there is any
-        if end_of_prev_match > 0 {
-            segments.push(first_n_chars!(&s[end_of_prev_match..end], context_len).to_string());
+        if let Some(end_of_prev_match) = end_of_prev_match {
+            let postamble = &s[end_of_prev_match..end];
+            let shown = context.head(postamble);
+            segments.push((shown.to_string(), shown.len() < postamble.len()));
         }
         // push preamble
-        segments.push(last_n_chars!(&s[start..end], context_len).to_string()); // push preamble.push(s[start..end].to_string());
-        let matched = m.as_str().to_string();
-        end_of_prev_match = m.end();
+        let preamble = &s[start..end];
+        let shown = context.tail(preamble);
+        segments.push((shown.to_string(), shown.len() < preamble.len()));
+        let matched = s[match_start..match_end].to_string();
+        end_of_prev_match = Some(match_end);
         start = end + matched.len();
         // push match itself
-        segments.push(matched);
+        segments.push((matched, false));
     }
     if start < s.len() {
         // push postamble of last match
-        segments.push(first_n_chars!(&s[start..], context_len).to_string()); // segments.push(s[start..].to_string());
+        let shown = context.head(&s[start..]);
+        segments.push((shown.to_string(), shown.len() < s.len() - start));
     }
+    // the context may have been cut inside an embedding or isolate
+    if s.contains(is_bidi_control) {
+        for (segment, _) in &mut segments {
+            if let Cow::Owned(balanced) = balance_bidi(segment) {
+                *segment = balanced;
+            }
+        }
+    }
+    let run_len = s.chars().count();
     let mut triples: Vec<MatchTriple> = Vec::new();
     segments.chunks(3).for_each(|chunk| {
-        let mtriple = MatchTriple::from_iter(chunk.to_owned());
+        let mut mtriple = MatchTriple::from_iter(chunk.iter().map(|(text, _)| text.clone()));
+        mtriple.run_len = run_len;
+        mtriple.pre_truncated = chunk[0].1;
+        mtriple.post_truncated = chunk.get(2).is_some_and(|(_, cut)| *cut);
         triples.push(mtriple);
     });
     triples
 }
 
+/// The engine used to find matches in the text runs of a document.
+#[derive(Debug, Clone)]
+pub enum Searcher {
+    /// Match a regular expression
+    Regex(Regex),
+    /// Match a literal pattern approximately, allowing up to `max_distance` edits
+    Fuzzy {
+        pattern: String,
+        max_distance: usize,
+    },
+    /// Match with the inner searcher, but drop matches inside quotes or parentheses
+    ExcludeQuoted(Box<Searcher>),
+    /// Match with the inner searcher, but drop zero-width matches
+    NonEmpty(Box<Searcher>),
+    /// Match with the inner searcher, but drop matches that look like card numbers (that match
+    /// `card` as a whole) yet fail the Luhn checksum
+    LuhnChecked { inner: Box<Searcher>, card: Regex },
+    /// Match a regular expression, reporting which of its top-level alternatives matched (see
+    /// `build_alternatives`)
+    Alternatives {
+        /// The pattern with each alternative wrapped in a named group
+        regex: Regex,
+        pattern: String,
+        alternatives: Vec<String>,
+        /// True if the alternative that matched is given with each match, not only used to
+        /// color it
+        report: bool,
+    },
+    /// Match any of several literal strings at once with an Aho-Corasick automaton, finding
+    /// what the regex `pattern`, their alternation, would (see `build_literals`)
+    Literals {
+        automaton: AhoCorasick,
+        pattern: String,
+        literals: Vec<String>,
+    },
+    /// Match the text between a match of `start` and the next match of `end` (see
+    /// `between_spans`)
+    Between { start: Regex, end: Regex },
+    /// Match the words that are not in the dictionary (see `Dictionary::unknown_word_spans`)
+    UnknownWords(Arc<Dictionary>),
+}
+
+impl From<Regex> for Searcher {
+    fn from(re: Regex) -> Self {
+        Searcher::Regex(re)
+    }
+}
+
+impl Display for Searcher {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Searcher::Regex(re) => write!(f, "{}", re),
+            Searcher::Fuzzy {
+                pattern,
+                max_distance,
+            } => write!(f, "{} (fuzzy, distance <= {})", pattern, max_distance),
+            Searcher::ExcludeQuoted(inner) => write!(f, "{} (excluding quoted)", inner),
+            Searcher::NonEmpty(inner) => write!(f, "{} (excluding empty matches)", inner),
+            Searcher::LuhnChecked { inner, .. } => write!(f, "{} (Luhn-checked)", inner),
+            Searcher::Alternatives { pattern, .. } | Searcher::Literals { pattern, .. } => {
+                write!(f, "{}", pattern)
+            }
+            Searcher::Between { start, end } => write!(f, "between {} and {}", start, end),
+            Searcher::UnknownWords(dictionary) => {
+                write!(f, "words not in a {}-word dictionary", dictionary.len())
+            }
+        }
+    }
+}
+
+impl Searcher {
+    /// Returns true if `text` contains at least one match.
+    pub fn is_match(&self, text: &str) -> bool {
+        match self {
+            Searcher::Regex(re) | Searcher::Alternatives { regex: re, .. } => re.is_match(text),
+            Searcher::Literals { automaton, .. } => automaton.is_match(text),
+            Searcher::Fuzzy { .. }
+            | Searcher::ExcludeQuoted(_)
+            | Searcher::NonEmpty(_)
+            | Searcher::LuhnChecked { .. }
+            | Searcher::Between { .. }
+            | Searcher::UnknownWords(_) => !self.find_spans(text).is_empty(),
+        }
+    }
+
+    /// Returns true if the pattern can match the empty string, whether or not such matches are
+    /// reported. Fuzzy matches and words are never empty, and the text between two patterns is
+    /// not taken as a pattern that matches the empty string.
+    pub fn matches_empty(&self) -> bool {
+        match self {
+            Searcher::Regex(re) | Searcher::Alternatives { regex: re, .. } => re.is_match(""),
+            Searcher::Fuzzy { .. }
+            | Searcher::Literals { .. }
+            | Searcher::Between { .. }
+            | Searcher::UnknownWords(_) => false,
+            Searcher::ExcludeQuoted(inner)
+            | Searcher::NonEmpty(inner)
+            | Searcher::LuhnChecked { inner, .. } => inner.matches_empty(),
+        }
+    }
+
+    /// Returns the byte spans of all non-overlapping matches in `text`, in order.
+    pub fn find_spans(&self, text: &str) -> Vec<(usize, usize)> {
+        match self {
+            Searcher::Regex(re) | Searcher::Alternatives { regex: re, .. } => {
+                re.find_iter(text).map(|m| (m.start(), m.end())).collect()
+            }
+            Searcher::Literals { automaton, .. } => automaton
+                .find_iter(text)
+                .map(|m| (m.start(), m.end()))
+                .collect(),
+            Searcher::Between { start, end } => between_spans(text, start, end),
+            Searcher::UnknownWords(dictionary) => dictionary.unknown_word_spans(text),
+            Searcher::Fuzzy {
+                pattern,
+                max_distance,
+            } => fuzzy_find(text, pattern, *max_distance),
+            Searcher::ExcludeQuoted(inner) => {
+                let regions = quoted_regions(text);
+                inner
+                    .find_spans(text)
+                    .into_iter()
+                    .filter(|&(start, end)| {
+                        !regions
+                            .iter()
+                            .any(|&(open, close)| open <= start && end <= close)
+                    })
+                    .collect()
+            }
+            Searcher::NonEmpty(inner) => inner
+                .find_spans(text)
+                .into_iter()
+                .filter(|&(start, end)| start < end)
+                .collect(),
+            Searcher::LuhnChecked { inner, card } => inner
+                .find_spans(text)
+                .into_iter()
+                .filter(|&(start, end)| {
+                    let matched = &text[start..end];
+                    !card.is_match(matched) || entity::luhn_valid(matched)
+                })
+                .collect(),
+        }
+    }
+
+    /// Segments `s` into `MatchTriple`s around its matches, as `segment_on_regex` does. Each
+    /// triple records the alternative that matched if the searcher reports alternatives, and
+    /// its index if the searcher knows it.
+    pub fn segment(&self, s: &str, context: ContextSpec) -> Vec<MatchTriple> {
+        match self {
+            Searcher::Regex(re) => segment_on_regex(s, re, context),
+            Searcher::Fuzzy { .. }
+            | Searcher::ExcludeQuoted(_)
+            | Searcher::NonEmpty(_)
+            | Searcher::LuhnChecked { .. }
+            | Searcher::Alternatives { .. }
+            | Searcher::Literals { .. }
+            | Searcher::Between { .. }
+            | Searcher::UnknownWords(_) => self.segment_between("", s, "", context),
+        }
+    }
+
+    /// Segments `s` into `MatchTriple`s around its matches, as `segment` does, but with `before`
+    /// and `after` joined on either side of it, so that the context of a match can run on into
+    /// them. Only matches within `s` itself are found, and a percentage context is taken of the
+    /// length of `s` alone, as is the length of the run recorded in each triple.
+    pub fn segment_between(
+        &self,
+        before: &str,
+        s: &str,
+        after: &str,
+        context: ContextSpec,
+    ) -> Vec<MatchTriple> {
+        let spans = self.find_spans(s);
+        let joined = format!("{}{}{}", before, s, after);
+        let offset = before.len();
+        let mut mtriples = segment_on_spans(
+            &joined,
+            spans
+                .iter()
+                .map(|&(start, end)| (start + offset, end + offset)),
+            context.for_run(s),
+        );
+        let run_len = s.chars().count();
+        for (mtriple, &(start, _)) in mtriples.iter_mut().zip(&spans) {
+            mtriple.run_len = run_len;
+            mtriple.alternative_index = self.alternative_index_at(s, start);
+            mtriple.alternative = mtriple
+                .alternative_index
+                .and_then(|index| self.alternative(index));
+        }
+        mtriples
+    }
+
+    /// Returns the index of the alternative of the pattern, or of the literal, that produced the
+    /// match starting at byte `start` of `text`, or `None` if this searcher cannot tell. When
+    /// several alternatives could match there, this is the one the regex engine chose.
+    fn alternative_index_at(&self, text: &str, start: usize) -> Option<usize> {
+        match self {
+            Searcher::Alternatives {
+                regex,
+                alternatives,
+                ..
+            } => {
+                let captures = regex.captures_at(text, start)?;
+                (0..alternatives.len())
+                    .find(|&index| captures.name(&alternative_group(index)).is_some())
+            }
+            Searcher::Literals { automaton, .. } => automaton
+                .find(Input::new(text).range(start..))
+                .filter(|m| m.start() == start)
+                .map(|m| m.pattern().as_usize()),
+            Searcher::ExcludeQuoted(inner)
+            | Searcher::NonEmpty(inner)
+            | Searcher::LuhnChecked { inner, .. } => inner.alternative_index_at(text, start),
+            Searcher::Regex(_)
+            | Searcher::Fuzzy { .. }
+            | Searcher::Between { .. }
+            | Searcher::UnknownWords(_) => None,
+        }
+    }
+
+    /// Returns the number of top-level alternatives of the pattern, or of literals, if this
+    /// searcher can tell which of them produced a match (see `alternative_index_at`).
+    pub fn alternative_count(&self) -> Option<usize> {
+        match self {
+            Searcher::Alternatives { alternatives, .. } => Some(alternatives.len()),
+            Searcher::Literals { literals, .. } => Some(literals.len()),
+            Searcher::ExcludeQuoted(inner)
+            | Searcher::NonEmpty(inner)
+            | Searcher::LuhnChecked { inner, .. } => inner.alternative_count(),
+            Searcher::Regex(_)
+            | Searcher::Fuzzy { .. }
+            | Searcher::Between { .. }
+            | Searcher::UnknownWords(_) => None,
+        }
+    }
+
+    /// Returns true if every one of the pattern's alternatives (see `alternative_count`) has a
+    /// match in `text`, or, for a searcher that does not report alternatives, if `text` has any
+    /// match at all.
+    pub fn matches_all_alternatives(&self, text: &str) -> bool {
+        let Some(count) = self.alternative_count() else {
+            return self.is_match(text);
+        };
+        let mut found = vec![false; count];
+        for (start, _) in self.find_spans(text) {
+            if let Some(index) = self.alternative_index_at(text, start) {
+                found[index] = true;
+            }
+        }
+        found.into_iter().all(|found| found)
+    }
+
+    /// Returns the alternative at `index` of the pattern, if this searcher reports
+    /// alternatives.
+    fn alternative(&self, index: usize) -> Option<String> {
+        match self {
+            Searcher::Alternatives {
+                alternatives,
+                report: true,
+                ..
+            } => alternatives.get(index).cloned(),
+            Searcher::Alternatives { report: false, .. } => None,
+            Searcher::ExcludeQuoted(inner)
+            | Searcher::NonEmpty(inner)
+            | Searcher::LuhnChecked { inner, .. } => inner.alternative(index),
+            Searcher::Regex(_)
+            | Searcher::Fuzzy { .. }
+            | Searcher::Literals { .. }
+            | Searcher::Between { .. }
+            | Searcher::UnknownWords(_) => None,
+        }
+    }
+}
+
+/// Scans `text` for the text between a match of `start` and the next match of `end` after it,
+/// and returns the byte spans of that text with the whitespace at either end left out. The
+/// scan resumes after the end match, so spans never overlap; a start with no end after it
+/// yields nothing.
+pub(crate) fn between_spans(text: &str, start: &Regex, end: &Regex) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut pos = 0;
+    while let Some(start_match) = start.find_at(text, pos) {
+        let Some(end_match) = end.find_at(text, start_match.end()) else {
+            break;
+        };
+        let between = &text[start_match.end()..end_match.start()];
+        let leading = between.len() - between.trim_start().len();
+        let trimmed = between.trim();
+        let span_start = start_match.end() + leading;
+        spans.push((span_start, span_start + trimmed.len()));
+        if end_match.end() > pos {
+            pos = end_match.end();
+        } else {
+            // both matches were empty: step over a character so that the scan moves on
+            match text[pos..].chars().next() {
+                Some(c) => pos += c.len_utf8(),
+                None => break,
+            }
+        }
+    }
+    spans
+}
+
+/// Returns the byte spans, delimiters included, of the balanced quoted and parenthesised
+/// regions of `text`: `"..."`, `“...”` and `(...)`, which may nest. Quotes or parentheses
+/// that are never closed do not start a region, and unmatched closers are ignored. Single
+/// quotes are not tracked, since they cannot be told apart from apostrophes.
+fn quoted_regions(text: &str) -> Vec<(usize, usize)> {
+    let mut regions = Vec::new();
+    // open delimiters, with the closer each expects and where it starts
+    let mut open: Vec<(char, usize)> = Vec::new();
+    for (i, c) in text.char_indices() {
+        let closer = match c {
+            '"' if open.iter().any(|&(closer, _)| closer == '"') => Some('"'),
+            '"' => {
+                open.push(('"', i));
+                None
+            }
+            '“' => {
+                open.push(('”', i));
+                None
+            }
+            '(' => {
+                open.push((')', i));
+                None
+            }
+            '”' | ')' => Some(c),
+            _ => None,
+        };
+        if let Some(closer) = closer {
+            if let Some(depth) = open.iter().rposition(|&(expected, _)| expected == closer) {
+                regions.push((open[depth].1, i + c.len_utf8()));
+                open.truncate(depth);
+            }
+        }
+    }
+    regions
+}
+
+/// Finds approximate occurrences of the literal `pattern` in `text`: windows of `text` whose
+/// Levenshtein distance from `pattern` is at most `max_distance`.
+///
+/// Windows of every length from `pattern.len() - max_distance` to `pattern.len() + max_distance`
+/// characters are tried at each start position. When a start position matches, the next
+/// `max_distance` positions are also tried and the closest window wins, so a match is not
+/// dragged off by a neighbouring character. Windows may not begin or end with whitespace unless
+/// the pattern does. Matching resumes after the end of each match, so spans never overlap.
+///
+/// # Returns
+///
+/// * `Vec<(usize, usize)>` - The byte spans of the approximate matches, in order.
+pub(crate) fn fuzzy_find(text: &str, pattern: &str, max_distance: usize) -> Vec<(usize, usize)> {
+    let pattern_len = pattern.chars().count();
+    if pattern_len == 0 {
+        return Vec::new();
+    }
+    let trim_start = !pattern.starts_with(char::is_whitespace);
+    let trim_end = !pattern.ends_with(char::is_whitespace);
+    // byte offset of every char boundary, including the end of the text
+    let bounds: Vec<usize> = text
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(text.len()))
+        .collect();
+    let nchars = bounds.len() - 1;
+    let min_len = pattern_len.saturating_sub(max_distance).max(1);
+    let max_len = pattern_len + max_distance;
+
+    // the closest window starting at char `start`, as (distance, end char)
+    let best_at = |start: usize| -> Option<(usize, usize)> {
+        if trim_start && text[bounds[start]..].starts_with(char::is_whitespace) {
+            return None;
+        }
+        let mut best: Option<(usize, usize)> = None;
+        for len in min_len..=max_len {
+            let end = start + len;
+            if end > nchars {
+                break;
+            }
+            let window = &text[bounds[start]..bounds[end]];
+            if trim_end && window.ends_with(char::is_whitespace) {
+                continue;
+            }
+            let distance = strsim::levenshtein(window, pattern);
+            if distance <= max_distance && best.is_none_or(|(d, _)| distance < d) {
+                best = Some((distance, end));
+            }
+        }
+        best
+    };
+
+    let mut spans = Vec::new();
+    let mut start = 0;
+    while start < nchars {
+        let Some((mut distance, mut end)) = best_at(start) else {
+            start += 1;
+            continue;
+        };
+        let mut match_start = start;
+        for later in start + 1..=(start + max_distance).min(nchars - 1) {
+            if let Some((d, e)) = best_at(later) {
+                if d < distance {
+                    (distance, end, match_start) = (d, e, later);
+                }
+            }
+        }
+        spans.push((bounds[match_start], bounds[end]));
+        start = end;
+    }
+    spans
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -105,12 +1484,65 @@ mod tests {
     fn test_segment_on_regex() {
         let s = "Hello, world!";
         let re = Regex::new(r"[Hh]ello").unwrap();
-        let mtriples = segment_on_regex(s, &re, 1000);
+        let mtriples = segment_on_regex(s, &re, ContextSpec::Chars(1000));
         println!("{:?}", mtriples);
         assert_eq!(mtriples.len(), 1);
-        assert_eq!(mtriples[0].0, "");
-        assert_eq!(mtriples[0].1, "Hello");
-        assert_eq!(mtriples[0].2, ", world!");
+        assert_eq!(mtriples[0].pre, "");
+        assert_eq!(mtriples[0].matched, "Hello");
+        assert_eq!(mtriples[0].post, ", world!");
+    }
+
+    #[test]
+    fn test_segment_on_regex_no_context() {
+        let re = Regex::new("world").unwrap();
+        let mtriples = segment_on_regex("Hello, world!", &re, ContextSpec::Chars(0));
+        assert_eq!(
+            (
+                mtriples[0].pre.as_str(),
+                mtriples[0].matched.as_str(),
+                mtriples[0].post.as_str()
+            ),
+            ("", "world", "")
+        );
+    }
+
+    #[test]
+    fn test_segment_on_regex_truncation() {
+        let re = Regex::new("fox").unwrap();
+        let s = "The quick brown fox jumps over the dog";
+        let mtriples = segment_on_regex(s, &re, ContextSpec::Chars(6));
+        assert_eq!(mtriples[0].pre, "brown ");
+        assert_eq!(mtriples[0].post, " jumps");
+        assert_eq!(mtriples[0].run_len, 38);
+        assert!(mtriples[0].pre_truncated && mtriples[0].post_truncated);
+
+        // context that reaches the ends of the run is not truncated
+        let mtriples = segment_on_regex(s, &re, ContextSpec::Chars(1000));
+        assert!(!mtriples[0].pre_truncated && !mtriples[0].post_truncated);
+        let mtriples = segment_on_regex("a fox", &re, ContextSpec::Chars(1));
+        assert!(mtriples[0].pre_truncated && !mtriples[0].post_truncated);
+        assert_eq!(mtriples[0].run_len, 5);
+
+        // between two matches, the postamble of the first and the preamble of the second are
+        // each cut from the text between them
+        let mtriples = segment_on_regex("fox and the fox", &re, ContextSpec::Words(1));
+        assert_eq!(
+            (mtriples[0].post.as_str(), mtriples[0].post_truncated),
+            (" and", true)
+        );
+        assert_eq!(
+            (mtriples[1].pre.as_str(), mtriples[1].pre_truncated),
+            ("the ", true)
+        );
+        assert!(!mtriples[0].pre_truncated && !mtriples[1].post_truncated);
+    }
+
+    #[test]
+    fn test_segment_between_run_len() {
+        let searcher = Searcher::from(Regex::new("fox").unwrap());
+        let mtriples = searcher.segment_between("the ", "fox", " runs", ContextSpec::Chars(2));
+        assert_eq!(mtriples[0].run_len, 3);
+        assert!(mtriples[0].pre_truncated && mtriples[0].post_truncated);
     }
 
     // Tests to verify the macro works correctly
@@ -119,24 +1551,433 @@ mod tests {
     fn test_segment_on_regex_multi() {
         let s = "This, that, and the other thing";
         let re = Regex::new(r"[Tt]h").unwrap();
-        let mtriples = segment_on_regex(s, &re, 1000);
+        let mtriples = segment_on_regex(s, &re, ContextSpec::Chars(1000));
         println!("{:?}", mtriples);
         assert_eq!(mtriples.len(), 5);
-        assert_eq!(mtriples[0].0, "");
-        assert_eq!(mtriples[0].1, "Th");
-        assert_eq!(mtriples[0].2, "is, ");
-        assert_eq!(mtriples[1].0, "is, ");
-        assert_eq!(mtriples[1].1, "th");
-        assert_eq!(mtriples[1].2, "at, and ");
-        assert_eq!(mtriples[2].0, "at, and ");
-        assert_eq!(mtriples[2].1, "th");
-        assert_eq!(mtriples[2].2, "e o");
-        assert_eq!(mtriples[3].0, "e o");
-        assert_eq!(mtriples[3].1, "th");
-        assert_eq!(mtriples[3].2, "er ");
-        assert_eq!(mtriples[4].0, "er ");
-        assert_eq!(mtriples[4].1, "th");
-        assert_eq!(mtriples[4].2, "ing");
+        assert_eq!(mtriples[0].pre, "");
+        assert_eq!(mtriples[0].matched, "Th");
+        assert_eq!(mtriples[0].post, "is, ");
+        assert_eq!(mtriples[1].pre, "is, ");
+        assert_eq!(mtriples[1].matched, "th");
+        assert_eq!(mtriples[1].post, "at, and ");
+        assert_eq!(mtriples[2].pre, "at, and ");
+        assert_eq!(mtriples[2].matched, "th");
+        assert_eq!(mtriples[2].post, "e o");
+        assert_eq!(mtriples[3].pre, "e o");
+        assert_eq!(mtriples[3].matched, "th");
+        assert_eq!(mtriples[3].post, "er ");
+        assert_eq!(mtriples[4].pre, "er ");
+        assert_eq!(mtriples[4].matched, "th");
+        assert_eq!(mtriples[4].post, "ing");
+    }
+
+    #[test]
+    fn test_context_bytes() {
+        // "é" and "ü" are two bytes each
+        let spec = ContextSpec::Bytes(3);
+        assert_eq!(spec.head("aéb"), "aé");
+        assert_eq!(spec.head("éüx"), "é");
+        assert_eq!(spec.tail("béa"), "éa");
+        assert_eq!(spec.tail("xéü"), "ü");
+        assert_eq!(spec.head("ab"), "ab");
+        assert_eq!(ContextSpec::Bytes(0).tail("abc"), "");
+        let re = Regex::new("X").unwrap();
+        let triples = segment_on_regex("ééX üü", &re, spec);
+        assert_eq!(triples[0].pre, "é");
+        assert_eq!(triples[0].post, " ü");
+    }
+
+    #[test]
+    fn test_segment_on_regex_lines() {
+        let s = "one\ntwo\nthree MATCH here\nfour\nfive";
+        let re = Regex::new("MATCH").unwrap();
+        let mtriples = segment_on_regex(s, &re, ContextSpec::Lines(1));
+        assert_eq!(mtriples[0].pre, "two\nthree ");
+        assert_eq!(mtriples[0].post, " here\nfour");
+        let mtriples = segment_on_regex(s, &re, ContextSpec::Lines(0));
+        assert_eq!(mtriples[0].pre, "three ");
+        assert_eq!(mtriples[0].post, " here");
+        let mtriples = segment_on_regex(s, &re, ContextSpec::Lines(5));
+        assert_eq!(mtriples[0].pre, "one\ntwo\nthree ");
+        assert_eq!(mtriples[0].post, " here\nfour\nfive");
+    }
+
+    #[test]
+    fn test_context_with_unit() {
+        assert_eq!(
+            ContextSpec::Chars(5).with_unit(ContextUnit::Bytes).unwrap(),
+            ContextSpec::Bytes(5)
+        );
+        assert_eq!(
+            ContextSpec::Words(5).with_unit(ContextUnit::Chars).unwrap(),
+            ContextSpec::Words(5)
+        );
+        assert!(ContextSpec::Words(5).with_unit(ContextUnit::Bytes).is_err());
+    }
+
+    #[test]
+    fn test_quoted_regions() {
+        let text = r#"a "b (c)" d (e “f”) g" (h"#;
+        let regions: Vec<&str> = quoted_regions(text)
+            .into_iter()
+            .map(|(start, end)| &text[start..end])
+            .collect();
+        assert_eq!(regions, vec!["(c)", r#""b (c)""#, "“f”", "(e “f”)"]);
+    }
+
+    #[test]
+    fn test_exclude_quoted() {
+        let re = Regex::new("cat").unwrap();
+        let searcher = Searcher::ExcludeQuoted(Box::new(Searcher::from(re)));
+        let text = r#"The cat said "my cat" (the other cat) to a cat."#;
+        assert_eq!(searcher.find_spans(text), vec![(4, 7), (43, 46)]);
+        assert!(!searcher.is_match(r#"only "cat" here"#));
+        assert!(searcher.is_match(r#"an unclosed "cat"#));
+    }
+
+    #[test]
+    fn test_context_spec_from_str() {
+        assert_eq!("75".parse::<ContextSpec>().unwrap(), ContextSpec::Chars(75));
+        assert_eq!("2w".parse::<ContextSpec>().unwrap(), ContextSpec::Words(2));
+        assert_eq!(
+            "1s".parse::<ContextSpec>().unwrap(),
+            ContextSpec::Sentences(1)
+        );
+        assert_eq!(
+            "20%".parse::<ContextSpec>().unwrap(),
+            ContextSpec::Percent(20)
+        );
+        for bad in ["5x", "w", "", "-3", "%", "2.5%"] {
+            let err = bad.parse::<ContextSpec>().unwrap_err();
+            assert!(err
+                .to_string()
+                .starts_with(&format!("Invalid context '{}'", bad)));
+        }
+    }
+
+    #[test]
+    fn test_context_percent() {
+        assert_eq!(
+            ContextSpec::Percent(20).for_run("twelve chars"),
+            ContextSpec::Chars(2)
+        );
+        assert_eq!(
+            ContextSpec::Percent(25).for_run("ten chars!"),
+            ContextSpec::Chars(3)
+        );
+        assert_eq!(
+            ContextSpec::Words(2).for_run("a b c"),
+            ContextSpec::Words(2)
+        );
+        let re = Regex::new("MATCH").unwrap();
+        // 10% of 30 characters
+        let mtriples = segment_on_regex(
+            "0123456789abcMATCHdef012345678",
+            &re,
+            ContextSpec::Percent(10),
+        );
+        assert_eq!(
+            (mtriples[0].pre.as_str(), mtriples[0].post.as_str()),
+            ("abc", "def")
+        );
+        // 1% of 30 characters is no context
+        let mtriples = segment_on_regex(
+            "0123456789abcMATCHdef012345678",
+            &re,
+            ContextSpec::Percent(1),
+        );
+        assert_eq!(
+            (mtriples[0].pre.as_str(), mtriples[0].post.as_str()),
+            ("", "")
+        );
+        let searcher = Searcher::from(Regex::new("MATCH").unwrap());
+        let mtriples =
+            searcher.segment_between("before ", "xMATCHx", " after", ContextSpec::Percent(50));
+        // 50% of the 7 characters of the run itself, rounded up to 4
+        assert_eq!(
+            (mtriples[0].pre.as_str(), mtriples[0].post.as_str()),
+            ("re x", "x af")
+        );
+    }
+
+    #[test]
+    fn test_segment_on_regex_words() {
+        let s = "one two three MATCH four five  six";
+        let re = Regex::new("MATCH").unwrap();
+        let mtriples = segment_on_regex(s, &re, ContextSpec::Words(2));
+        assert_eq!(mtriples.len(), 1);
+        assert_eq!(mtriples[0].pre, "two three ");
+        assert_eq!(mtriples[0].post, " four five");
+    }
+
+    #[test]
+    fn test_segment_on_regex_sentences() {
+        let s = "First one. Second one has the MATCH in it. Third one! Fourth.";
+        let re = Regex::new("MATCH").unwrap();
+        let mtriples = segment_on_regex(s, &re, ContextSpec::Sentences(1));
+        assert_eq!(mtriples[0].pre, "Second one has the ");
+        assert_eq!(mtriples[0].post, " in it.");
+        let mtriples = segment_on_regex(s, &re, ContextSpec::Sentences(2));
+        assert_eq!(mtriples[0].pre, "First one. Second one has the ");
+        assert_eq!(mtriples[0].post, " in it. Third one!");
+    }
+
+    #[test]
+    fn test_fuzzy_find() {
+        let text = "the liabiliry clause and the liability cap";
+        let spans = fuzzy_find(text, "liability", 1);
+        let found: Vec<&str> = spans.iter().map(|&(s, e)| &text[s..e]).collect();
+        assert_eq!(found, vec!["liabiliry", "liability"]);
+        assert!(fuzzy_find(text, "liability", 0).len() == 1);
+        assert!(fuzzy_find("nothing here", "liability", 2).is_empty());
+    }
+
+    #[test]
+    fn test_searcher_fuzzy_segment() {
+        let searcher = Searcher::Fuzzy {
+            pattern: "Célimène".to_string(),
+            max_distance: 1,
+        };
+        assert!(searcher.is_match("Dear Celimène,"));
+        let mtriples = searcher.segment("Dear Celimène, hello", ContextSpec::Chars(1000));
+        assert_eq!(mtriples.len(), 1);
+        assert_eq!(mtriples[0].pre, "Dear ");
+        assert_eq!(mtriples[0].matched, "Celimène");
+        assert_eq!(mtriples[0].post, ", hello");
+    }
+
+    #[test]
+    fn test_build_regex_size_limit() {
+        assert!(build_regex(r"\w{50}", None).is_ok());
+        let err = build_regex(r"\w{50}", Some(1000)).unwrap_err();
+        assert_eq!(err.to_string(), r"Failed to compile regex: \w{50}");
+        assert!(build_regex("[Hh]ello", Some(1000)).is_ok());
+    }
+
+    #[test]
+    fn test_explain() -> anyhow::Result<()> {
+        let opts = PatternOptions {
+            show_alternative: true,
+            exclude_quoted: true,
+            ..Default::default()
+        };
+        let searcher = build_searcher("cat|dog", &opts)?;
+        assert_eq!(
+            explain(&searcher, &opts),
+            "regex: (?P<docread_alt0>cat)|(?P<docread_alt1>dog)\n  group docread_alt0 reports alternative cat\n  group docread_alt1 reports alternative dog\ndrop matches inside quotes or parentheses\n"
+        );
+        let opts = PatternOptions {
+            fuzzy: Some(2),
+            normalize: Some(Normalization::Nfc),
+            ..Default::default()
+        };
+        let searcher = build_searcher("cafe\u{301}", &opts)?;
+        assert_eq!(
+            explain(&searcher, &opts),
+            "normalize text and pattern to NFC\nfuzzy literal: caf\u{e9} (within 2 edits)\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_matches() -> anyhow::Result<()> {
+        let searcher = build_searcher("a*", &PatternOptions::default())?;
+        assert!(searcher.matches_empty());
+        assert_eq!(searcher.find_spans("baac"), vec![(1, 3)]);
+        assert!(!searcher.is_match("bc"));
+        let mtriples = searcher.segment("baac", ContextSpec::Chars(5));
+        assert_eq!(mtriples.len(), 1);
+        assert_eq!(
+            (
+                mtriples[0].pre.as_str(),
+                mtriples[0].matched.as_str(),
+                mtriples[0].post.as_str()
+            ),
+            ("b", "aa", "c")
+        );
+        assert!(explain(&searcher, &PatternOptions::default()).contains("drop empty matches"));
+
+        let opts = PatternOptions {
+            allow_empty_match: true,
+            ..Default::default()
+        };
+        let searcher = build_searcher("a*", &opts)?;
+        assert_eq!(searcher.find_spans("baac"), vec![(0, 0), (1, 3), (4, 4)]);
+        // an empty match at the very start still gets its postamble
+        let mtriples = searcher.segment("baac", ContextSpec::Chars(5));
+        let matched: Vec<&str> = mtriples.iter().map(|m| m.matched.as_str()).collect();
+        assert_eq!(matched, vec!["", "aa", ""]);
+        assert_eq!(mtriples[0].post, "b");
+
+        let searcher = build_searcher("a+", &PatternOptions::default())?;
+        assert!(!searcher.matches_empty());
+        assert!(matches!(searcher, Searcher::Regex(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_searcher_literals() -> anyhow::Result<()> {
+        let opts = PatternOptions::default();
+        let fillers: Vec<String> = (0..LITERALS_THRESHOLD).map(|i| format!("x{}", i)).collect();
+        let pattern = format!("Sam|Samuel|muel|{}", fillers.join("|"));
+        let searcher = build_searcher(&pattern, &opts)?;
+        assert!(matches!(searcher, Searcher::Literals { .. }));
+        assert_eq!(searcher.to_string(), pattern);
+        // leftmost-first, as the regex would match
+        let text = "Samuel and muel";
+        let regex = Regex::new("Sam|Samuel|muel").unwrap();
+        let expected: Vec<(usize, usize)> = regex
+            .find_iter(text)
+            .map(|m| (m.start(), m.end()))
+            .collect();
+        assert_eq!(searcher.find_spans(text), expected);
+        assert!(explain(&searcher, &opts)
+            .starts_with("literals (Aho-Corasick, no regex): Sam, Samuel, muel, x0, x1, "));
+        for pattern in [
+            "Sam|Samuel|muel",
+            "Sam",
+            "Sam|Sa.",
+            r"Sam|Sa\.m",
+            "Sam|",
+            "(?i)Sam|Bob",
+        ] {
+            assert!(
+                !matches!(build_searcher(pattern, &opts)?, Searcher::Literals { .. }),
+                "{}",
+                pattern
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_between() -> anyhow::Result<()> {
+        let opts = PatternOptions {
+            between: Some(("Total:".to_string(), "end".to_string())),
+            ..Default::default()
+        };
+        let searcher = build_searcher("", &opts)?;
+        let text = "Total: 42 USD end";
+        assert_eq!(searcher.find_spans(text), vec![(7, 13)]);
+        let mtriples = searcher.segment(text, ContextSpec::Chars(10));
+        assert_eq!(
+            (
+                mtriples[0].pre.as_str(),
+                mtriples[0].matched.as_str(),
+                mtriples[0].post.as_str()
+            ),
+            ("Total: ", "42 USD", " end")
+        );
+        assert_eq!(searcher.to_string(), "between Total: and end");
+        assert!(explain(&searcher, &opts).contains("end regex: end"));
+
+        let start = Regex::new(r"\$").unwrap();
+        let end = Regex::new(r"\b").unwrap();
+        assert_eq!(
+            between_spans("$1, $22 and $ ", &start, &end),
+            vec![(1, 1), (5, 5)]
+        );
+        let end = Regex::new(r"\.").unwrap();
+        assert_eq!(
+            between_spans("a $1.5 and $2. $", &start, &end),
+            vec![(3, 4), (12, 13)]
+        );
+        // empty start and end matches do not stall the scan
+        let empty = Regex::new("").unwrap();
+        assert_eq!(between_spans("ab", &empty, &empty).len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_searcher_entities() -> anyhow::Result<()> {
+        let text = "Card 4111 1111 1111 1111 or 4111 1111 1111 1112, mail ops@example.com";
+        let opts = PatternOptions {
+            entities: vec![Entity::Creditcard, Entity::Email],
+            ..Default::default()
+        };
+        let searcher = build_searcher("", &opts)?;
+        let found = |searcher: &Searcher| -> Vec<String> {
+            searcher
+                .find_spans(text)
+                .iter()
+                .map(|&(start, end)| text[start..end].to_string())
+                .collect()
+        };
+        assert_eq!(found(&searcher).len(), 3);
+        let opts = PatternOptions { luhn: true, ..opts };
+        let searcher = build_searcher("Card", &opts)?;
+        assert_eq!(
+            found(&searcher),
+            vec!["Card", "4111 1111 1111 1111", "ops@example.com"]
+        );
+        assert!(explain(&searcher, &opts).contains("Luhn"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_strip_invisibles() {
+        assert_eq!(
+            strip_invisibles("inter\u{ad}national\u{200b} zero\u{2060}width\u{feff}"),
+            "international zerowidth"
+        );
+        assert!(matches!(strip_invisibles("plain text"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_normalize() {
+        let composed = "caf\u{e9}";
+        let decomposed = "cafe\u{301}";
+        assert_eq!(normalize(decomposed, Some(Normalization::Nfc)), composed);
+        assert_eq!(normalize(composed, Some(Normalization::Nfd)), decomposed);
+        assert_eq!(normalize(decomposed, None), decomposed);
+    }
+
+    #[test]
+    fn test_segment_between() {
+        let searcher = Searcher::from(Regex::new("fox").unwrap());
+        let mtriples =
+            searcher.segment_between("quick brown ", "fox", " jumps", ContextSpec::Words(1));
+        assert_eq!(mtriples.len(), 1);
+        assert_eq!(mtriples[0].pre, "brown ");
+        assert_eq!(mtriples[0].post, " jumps");
+        // matches are only looked for in the middle text
+        let mtriples = searcher.segment_between("fox ", "a fox", " fox", ContextSpec::Chars(10));
+        assert_eq!(mtriples.len(), 1);
+        assert_eq!(mtriples[0].pre, "fox a ");
+        assert_eq!(mtriples[0].post, " fox");
+    }
+
+    #[test]
+    fn test_split_alternatives() {
+        assert_eq!(
+            split_alternatives("cat|dog|bird"),
+            vec!["cat", "dog", "bird"]
+        );
+        assert_eq!(
+            split_alternatives(r"a(b|c)|[|\]]|\||d"),
+            vec!["a(b|c)", r"[|\]]", r"\|", "d"]
+        );
+        assert_eq!(split_alternatives("[]|]x|y"), vec!["[]|]x", "y"]);
+        assert_eq!(split_alternatives("plain"), vec!["plain"]);
+    }
+
+    #[test]
+    fn test_alternatives_segment() -> anyhow::Result<()> {
+        let searcher = build_alternatives("cat|catalog|dog", None, true)?;
+        let mtriples = searcher.segment("a catalog and a dog", ContextSpec::Chars(1000));
+        let found: Vec<(&str, Option<&str>)> = mtriples
+            .iter()
+            .map(|mtriple| (mtriple.matched.as_str(), mtriple.alternative.as_deref()))
+            .collect();
+        // leftmost-first: "cat" is chosen over the longer "catalog"
+        assert_eq!(found, vec![("cat", Some("cat")), ("dog", Some("dog"))]);
+        assert_eq!(searcher.to_string(), "cat|catalog|dog");
+
+        let searcher = build_alternatives("(?i)hello|bye", None, true)?;
+        let mtriples = searcher.segment("HELLO and BYE", ContextSpec::Chars(1000));
+        assert_eq!(mtriples[1].alternative.as_deref(), Some("bye"));
+        assert!(build_alternatives("a(|b", None, true).is_err());
+        Ok(())
     }
 
     #[test]
@@ -171,5 +2012,188 @@ mod tests {
         assert_eq!(last_n_chars!("   ", 2), "  ");
         assert_eq!(last_n_chars!("NoSpaces", 3), "ces");
         assert_eq!(last_n_chars!("Célimène", 3), "ène");
+        assert_eq!(last_n_chars!("Célimène", 0), "");
+    }
+
+    #[test]
+    fn test_display_width() {
+        assert_eq!(display_width("hello"), 5);
+        assert_eq!(display_width("日本語"), 6);
+        assert_eq!(display_width("cafe\u{301}"), 4);
+        assert_eq!(display_width(""), 0);
+    }
+
+    #[test]
+    fn test_align() {
+        let mut short = MatchTriple::from_iter(["ab".to_string(), "x".to_string()]);
+        short.align(6);
+        assert_eq!(short.pre, "    ab");
+        let mut wide = MatchTriple::from_iter(["日本".to_string(), "x".to_string()]);
+        wide.align(6);
+        assert_eq!(wide.pre, "  日本");
+        let mut long = MatchTriple::from_iter(["abcdefgh".to_string(), "x".to_string()]);
+        long.align(6);
+        assert_eq!(long.pre, "abcdefgh");
+    }
+
+    #[test]
+    fn test_highlight() {
+        let styles =
+            |styles: &[HighlightStyle]| styles.iter().fold(0, |bits, style| bits | style.bit());
+        // the escape sequences are written only where colored output is on, as it is on a
+        // terminal, and otherwise the match is left plain
+        let colorize = colored::control::SHOULD_COLORIZE.should_colorize();
+        let ansi = |bits, expected: &str| {
+            let highlighted = highlight("cat", Color::Red, bits).to_string();
+            assert_eq!(highlighted, if colorize { expected } else { "cat" });
+        };
+        ansi(0, "\x1b[31mcat\x1b[0m");
+        ansi(styles(&[HighlightStyle::Bold]), "\x1b[1;31mcat\x1b[0m");
+        ansi(
+            styles(&[HighlightStyle::Underline, HighlightStyle::Reverse]),
+            "\x1b[4;7;31mcat\x1b[0m",
+        );
+        ansi(styles(&[HighlightStyle::Background]), "\x1b[41mcat\x1b[0m");
+        let background = highlight("cat", Color::Green, styles(&[HighlightStyle::Background]));
+        assert_eq!(
+            (background.fgcolor, background.bgcolor),
+            (None, Some(Color::Green))
+        );
+        let bold = highlight("cat", Color::Green, styles(&[HighlightStyle::Bold]));
+        assert!(bold.style.contains(colored::Styles::Bold));
+        assert_eq!(bold.fgcolor, Some(Color::Green));
+    }
+
+    #[test]
+    fn test_alternative_colors() -> anyhow::Result<()> {
+        let opts = PatternOptions::default();
+        let colors = |searcher: &Searcher| -> Vec<Color> {
+            searcher
+                .segment("cat, dog and cat", ContextSpec::default())
+                .iter()
+                .map(|mtriple| highlight_color(mtriple.alternative_index))
+                .collect()
+        };
+        let fillers: Vec<String> = (0..LITERALS_THRESHOLD).map(|i| format!("x{}", i)).collect();
+        let literals = build_searcher(&format!("cat|dog|{}", fillers.join("|")), &opts)?;
+        assert!(matches!(literals, Searcher::Literals { .. }));
+        let expected = [Color::Red, Color::Green, Color::Red];
+        assert_eq!(colors(&literals), expected);
+        let opts = PatternOptions {
+            show_alternative: true,
+            ..Default::default()
+        };
+        assert_eq!(colors(&build_searcher("c.t|do+g", &opts)?), expected);
+        // with color on, any alternation is told apart, but its alternatives are not reported
+        let opts = PatternOptions {
+            color_alternatives: true,
+            ..Default::default()
+        };
+        for pattern in ["cat|dog", "c.t|do+g"] {
+            let searcher = build_searcher(pattern, &opts)?;
+            assert_eq!(colors(&searcher), expected);
+            let mtriples = searcher.segment("cat", ContextSpec::default());
+            assert_eq!(mtriples[0].alternative, None);
+        }
+        assert!(explain(&build_searcher("cat|dog", &opts)?, &opts)
+            .contains("group docread_alt0 colors alternative cat"));
+        // without alternatives to tell apart, every match is red
+        let regex = build_searcher("c.t|do+g", &PatternOptions::default())?;
+        assert_eq!(colors(&regex), [Color::Red; 3]);
+        let single = build_searcher("cat", &opts)?;
+        assert!(matches!(single, Searcher::Regex(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_center() {
+        let mut mtriple = MatchTriple::from_iter([
+            "the quick\nbrown ".to_string(),
+            "fox".to_string(),
+            " jumps over".to_string(),
+        ]);
+        mtriple.center(21);
+        assert_eq!(
+            (mtriple.pre.as_str(), mtriple.post.as_str()),
+            ("ck brown ", " jumps ov")
+        );
+        let mut short = MatchTriple::from_iter(["ab".to_string(), "x".to_string()]);
+        short.center(7);
+        assert_eq!(short.pre, " ab");
+        let mut wide =
+            MatchTriple::from_iter(["日本語".to_string(), "x".to_string(), "日本語".to_string()]);
+        wide.center(8);
+        // a wide character that would straddle the edge is dropped
+        assert_eq!((wide.pre.as_str(), wide.post.as_str()), (" 語", "日本"));
+        let mut long = MatchTriple::from_iter(["ab".to_string(), "xyz".to_string()]);
+        long.center(2);
+        assert_eq!((long.pre.as_str(), long.matched.as_str()), ("", "xyz"));
+    }
+
+    #[test]
+    fn test_bidi() {
+        // "I use Linux every day" in Hebrew, with the English word in a left-to-right embedding
+        // inside a right-to-left one
+        let text = "\u{202B}אני משתמש ב-\u{202A}Linux\u{202C} כל יום\u{202C}";
+        let searcher = Searcher::from(Regex::new("Lin").unwrap());
+        let mtriples = searcher.segment(text, ContextSpec::Chars(3));
+        // the preamble and the postamble each cut through the embedding around "Linux"
+        assert_eq!(mtriples[0].pre, "ב-\u{202A}\u{202C}");
+        assert_eq!(mtriples[0].post, "ux");
+        let shown = mtriples[0].to_string();
+        let isolate = shown.find('\u{2066}').unwrap();
+        let matched = shown.find("Lin").unwrap();
+        let pop = shown.find('\u{2069}').unwrap();
+        assert!(isolate < matched && matched < pop);
+        assert_eq!(
+            balance_bidi("a\u{2069}b\u{2067}c\u{202B}d"),
+            "ab\u{2067}c\u{202B}d\u{202C}\u{2069}"
+        );
+        assert_eq!(balance_bidi("plain"), Cow::Borrowed("plain"));
+        let plain = searcher.segment("Linux", ContextSpec::Chars(3));
+        assert!(!plain[0].to_string().contains('\u{2066}'));
+    }
+
+    #[test]
+    fn test_escape() {
+        let searcher = Searcher::from(Regex::new("page\x0c?").unwrap());
+        let mut mtriples =
+            searcher.segment("end of\x0bline\tone page\x0c\nnext", ContextSpec::Chars(20));
+        mtriples[0].escape();
+        assert_eq!(
+            (
+                mtriples[0].pre.as_str(),
+                mtriples[0].matched.as_str(),
+                mtriples[0].post.as_str()
+            ),
+            ("end of\\x0bline\tone ", "page\\x0c", "\nnext")
+        );
+        assert_eq!(
+            escape_controls("plain\ttext\n"),
+            Cow::Borrowed("plain\ttext\n")
+        );
+    }
+
+    #[test]
+    fn test_trim_ends() {
+        let searcher = Searcher::from(Regex::new("fox").unwrap());
+        let mut mtriples = searcher.segment(
+            "over   the  quick  fox  jumps  over  ",
+            ContextSpec::Chars(14),
+        );
+        assert_eq!(mtriples[0].pre, "  the  quick  ");
+        mtriples[0].trim_ends();
+        assert_eq!(
+            (
+                mtriples[0].pre.as_str(),
+                mtriples[0].matched.as_str(),
+                mtriples[0].post.as_str()
+            ),
+            ("the  quick  ", "fox", "  jumps  over")
+        );
+        let mut bare =
+            MatchTriple::from_iter([" \n".to_string(), "fox".to_string(), "\t".to_string()]);
+        bare.trim_ends();
+        assert_eq!((bare.pre.as_str(), bare.post.as_str()), ("", ""));
     }
 }
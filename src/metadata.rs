@@ -0,0 +1,105 @@
+use crate::rawxml::{read_part, xtract_element_text};
+
+/// The part of a .docx package that holds the extended (application) properties.
+const APP_PART: &str = "docProps/app.xml";
+
+/// The part of a .docx package that holds the core properties, including the revision number.
+const CORE_PART: &str = "docProps/core.xml";
+
+/// The properties of a document that say what produced it and how much it has been edited.
+/// Properties missing from the package are `None`.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct DocProperties {
+    pub(crate) application: Option<String>,
+    pub(crate) app_version: Option<String>,
+    pub(crate) template: Option<String>,
+    pub(crate) company: Option<String>,
+    /// Total editing time, in minutes
+    pub(crate) total_time: Option<u64>,
+    /// Number of times the document has been saved
+    pub(crate) revision: Option<u64>,
+}
+
+/// Returns the text of the first `<tag>` element of `xml`, if it has any.
+fn element(xml: &str, tag: &str) -> Option<String> {
+    xtract_element_text(xml, tag)
+        .into_iter()
+        .next()
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+/// Reads the application properties from `docProps/app.xml`, and the revision number from
+/// `docProps/core.xml`, of the .docx package held in `buffer`. A missing or unreadable part
+/// leaves its properties unset.
+pub(crate) fn read_properties(buffer: &[u8]) -> DocProperties {
+    let app = read_part(buffer, APP_PART).unwrap_or_default();
+    let core = read_part(buffer, CORE_PART).unwrap_or_default();
+    DocProperties {
+        application: element(&app, "Application"),
+        app_version: element(&app, "AppVersion"),
+        template: element(&app, "Template"),
+        company: element(&app, "Company"),
+        total_time: element(&app, "TotalTime").and_then(|time| time.parse().ok()),
+        revision: element(&core, "cp:revision").and_then(|revision| revision.parse().ok()),
+    }
+}
+
+impl DocProperties {
+    /// Returns a `Label: value` line for each property that is set, for searching.
+    pub(crate) fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let texts = [
+            ("Application", &self.application),
+            ("Application version", &self.app_version),
+            ("Template", &self.template),
+            ("Company", &self.company),
+        ];
+        for (label, value) in texts {
+            if let Some(value) = value {
+                lines.push(format!("{}: {}", label, value));
+            }
+        }
+        if let Some(minutes) = self.total_time {
+            lines.push(format!("Total editing time: {} minutes", minutes));
+        }
+        if let Some(revision) = self.revision {
+            lines.push(format!("Revision: {}", revision));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_properties() -> anyhow::Result<()> {
+        let buffer = std::fs::read("resources/testdoc.docx")?;
+        let properties = read_properties(&buffer);
+        assert_eq!(
+            properties,
+            DocProperties {
+                application: Some("Microsoft Office Word".to_string()),
+                app_version: Some("16.0000".to_string()),
+                template: Some("Normal.dotm".to_string()),
+                company: None,
+                total_time: Some(1),
+                revision: Some(1),
+            }
+        );
+        assert_eq!(
+            properties.lines(),
+            vec![
+                "Application: Microsoft Office Word",
+                "Application version: 16.0000",
+                "Template: Normal.dotm",
+                "Total editing time: 1 minutes",
+                "Revision: 1",
+            ]
+        );
+        assert_eq!(read_properties(b"not a zip"), DocProperties::default());
+        Ok(())
+    }
+}
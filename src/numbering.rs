@@ -0,0 +1,234 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// The deepest list level is 8, counting from 0.
+const LEVELS: usize = 9;
+
+/// One level of a numbering definition, as given in `numbering.xml`.
+#[derive(Debug)]
+struct LevelDef {
+    level: usize,
+    /// The number format, such as `decimal`, `lowerLetter` or `bullet`
+    format: String,
+    /// The marker text, with `%1` to `%9` standing for the counters of each level
+    text: String,
+    start: usize,
+    /// What follows the marker: `tab`, `space` or `nothing`
+    suffix: String,
+}
+
+/// Computes the markers ("1.", "a)", "•"...) that Word shows in front of list items, whose text
+/// is not part of their runs but comes from the numbering definitions of the document. List
+/// paragraphs must be passed to `marker` in document order, so that the counters advance as
+/// Word's do.
+///
+/// Only numbering set directly on a paragraph is followed, not that of its paragraph style;
+/// each numbering instance counts on its own, and a level restarts whenever a higher level
+/// advances.
+#[derive(Debug, Default)]
+pub(crate) struct ListNumbering {
+    /// The levels of each numbering instance, by its id
+    definitions: HashMap<u64, Vec<LevelDef>>,
+    /// The current counter of each level of each numbering instance, if it has started
+    counters: HashMap<u64, [Option<usize>; LEVELS]>,
+}
+
+impl ListNumbering {
+    /// Reads the numbering definitions of a document from its JSON representation.
+    pub(crate) fn new(root: &Value) -> Self {
+        let numberings = &root["numberings"];
+        let abstract_nums: HashMap<u64, &Vec<Value>> = numberings["abstractNums"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|abstract_num| {
+                Some((
+                    abstract_num["id"].as_u64()?,
+                    abstract_num["levels"].as_array()?,
+                ))
+            })
+            .collect();
+        let mut definitions = HashMap::new();
+        for numbering in numberings["numberings"].as_array().into_iter().flatten() {
+            let (Some(id), Some(levels)) = (
+                numbering["id"].as_u64(),
+                numbering["abstractNumId"]
+                    .as_u64()
+                    .and_then(|abstract_id| abstract_nums.get(&abstract_id)),
+            ) else {
+                continue;
+            };
+            let overrides = numbering["levelOverrides"].as_array();
+            let levels = levels
+                .iter()
+                .map(|level| {
+                    let index = level["level"].as_u64().unwrap_or_default() as usize;
+                    let start_override = overrides
+                        .into_iter()
+                        .flatten()
+                        .find(|o| o["level"].as_u64() == Some(index as u64))
+                        .and_then(|o| o["overrideStart"].as_u64());
+                    LevelDef {
+                        level: index,
+                        format: level["format"].as_str().unwrap_or("decimal").to_string(),
+                        text: level["text"].as_str().unwrap_or_default().to_string(),
+                        start: start_override
+                            .or_else(|| level["start"].as_u64())
+                            .unwrap_or(1) as usize,
+                        suffix: level["suffix"].as_str().unwrap_or("tab").to_string(),
+                    }
+                })
+                .collect();
+            definitions.insert(id, levels);
+        }
+        ListNumbering {
+            definitions,
+            counters: HashMap::new(),
+        }
+    }
+
+    /// Advances the counters for the paragraph node `paragraph` and returns its marker, followed
+    /// by a space unless the level asks for nothing after it, or `None` if the paragraph is not
+    /// a list item or its marker is empty.
+    pub(crate) fn marker(&mut self, paragraph: &Value) -> Option<String> {
+        let property = &paragraph["data"]["property"]["numberingProperty"];
+        let id = property["id"].as_u64().filter(|&id| id != 0)?;
+        let level = property["level"].as_u64().unwrap_or_default() as usize;
+        if level >= LEVELS {
+            return None;
+        }
+        let levels = self.definitions.get(&id)?;
+        let definition = levels.iter().find(|def| def.level == level)?;
+        let counters = self.counters.entry(id).or_default();
+        counters[level] = Some(counters[level].map_or(definition.start, |n| n + 1));
+        counters[level + 1..].fill(None);
+
+        let mut marker = definition.text.clone();
+        for def in levels.iter().filter(|def| def.level < LEVELS) {
+            let placeholder = format!("%{}", def.level + 1);
+            if marker.contains(&placeholder) {
+                let number = counters[def.level].unwrap_or(def.start);
+                marker = marker.replace(&placeholder, &format_number(number, &def.format));
+            }
+        }
+        if marker.is_empty() {
+            return None;
+        }
+        if definition.suffix != "nothing" {
+            marker.push(' ');
+        }
+        Some(marker)
+    }
+}
+
+/// Writes `n` in the number format `format` of a list level. Formats other than the common
+/// Latin ones are written as decimal numbers.
+fn format_number(n: usize, format: &str) -> String {
+    match format {
+        "decimalZero" => format!("{:02}", n),
+        "lowerLetter" => letters(n),
+        "upperLetter" => letters(n).to_uppercase(),
+        "lowerRoman" => roman(n),
+        "upperRoman" => roman(n).to_uppercase(),
+        "bullet" | "none" => String::new(),
+        _ => n.to_string(),
+    }
+}
+
+/// Writes `n` as Word numbers lists with letters: a to z, then aa to zz, and so on.
+fn letters(n: usize) -> String {
+    if n == 0 {
+        return String::new();
+    }
+    let letter = char::from(b'a' + ((n - 1) % 26) as u8);
+    letter.to_string().repeat((n - 1) / 26 + 1)
+}
+
+/// Writes `n` in lowercase Roman numerals.
+fn roman(mut n: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let mut out = String::new();
+    for (value, numeral) in NUMERALS {
+        while n >= value {
+            out.push_str(numeral);
+            n -= value;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_format_number() {
+        assert_eq!(format_number(3, "decimal"), "3");
+        assert_eq!(format_number(3, "decimalZero"), "03");
+        assert_eq!(format_number(28, "lowerLetter"), "bb");
+        assert_eq!(format_number(2, "upperLetter"), "B");
+        assert_eq!(format_number(1994, "lowerRoman"), "mcmxciv");
+        assert_eq!(format_number(4, "upperRoman"), "IV");
+        assert_eq!(format_number(4, "bullet"), "");
+    }
+
+    #[test]
+    fn test_marker() {
+        let level = |level: u64, format: &str, text: &str| {
+            json!({
+                "level": level,
+                "format": format,
+                "text": text,
+                "start": 1,
+                "suffix": "tab",
+            })
+        };
+        let root = json!({"numberings": {
+            "abstractNums": [{"id": 7, "levels": [
+                level(0, "decimal", "%1."),
+                level(1, "lowerLetter", "%1.%2)"),
+            ]}],
+            "numberings": [
+                {"id": 1, "abstractNumId": 7, "levelOverrides": []},
+                {"id": 2, "abstractNumId": 7,
+                 "levelOverrides": [{"level": 0, "overrideStart": 5}]},
+            ],
+        }});
+        let item = |id: u64, level: u64| json!({"data": {"property": {"numberingProperty": {"id": id, "level": level}}}});
+        let mut numbering = ListNumbering::new(&root);
+        let markers: Vec<Option<String>> = [item(1, 0), item(1, 1), item(1, 1), item(1, 0)]
+            .iter()
+            .chain(&[item(1, 1), item(2, 0), item(3, 0), json!({"data": {}})])
+            .map(|paragraph| numbering.marker(paragraph))
+            .collect();
+        let expected = [
+            Some("1. "),
+            Some("1.a) "),
+            Some("1.b) "),
+            Some("2. "),
+            Some("2.a) "),
+            Some("5. "),
+            None,
+            None,
+        ];
+        assert_eq!(
+            markers,
+            expected.map(|marker| marker.map(str::to_string)).to_vec()
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// The counts of documents searched and of matches found so far in a scan, which the search
+/// threads add to and a `ProgressReporter` reads.
+#[derive(Debug, Default)]
+pub(crate) struct Progress {
+    files_done: AtomicUsize,
+    matches: AtomicUsize,
+}
+
+impl Progress {
+    /// Counts one more document searched, in which `matches` matches were found.
+    pub(crate) fn record(&self, matches: usize) {
+        self.files_done.fetch_add(1, Ordering::Relaxed);
+        self.matches.fetch_add(matches, Ordering::Relaxed);
+    }
+
+    /// Returns the one-line status of a scan of `total` documents that has run for `elapsed`,
+    /// such as "120/5000 files, 37 matches, 45s elapsed".
+    fn status(&self, total: usize, elapsed: Duration) -> String {
+        let matches = self.matches.load(Ordering::Relaxed);
+        let matchword = if matches == 1 { "match" } else { "matches" };
+        format!(
+            "{}/{} files, {} {}, {}s elapsed",
+            self.files_done.load(Ordering::Relaxed),
+            total,
+            matches,
+            matchword,
+            elapsed.as_secs()
+        )
+    }
+}
+
+/// A thread that writes the status of a scan (see `Progress::status`) every interval until it
+/// is dropped, for `--progress-interval`.
+#[derive(Debug)]
+pub(crate) struct ProgressReporter {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ProgressReporter {
+    /// Starts writing the status of the scan of `total` documents counted by `progress` to
+    /// `out`, a line every `interval` from now. Nothing is written if the scan ends sooner.
+    pub(crate) fn start(
+        interval: Duration,
+        total: usize,
+        progress: Arc<Progress>,
+        mut out: impl Write + Send + 'static,
+    ) -> Self {
+        let started = Instant::now();
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = std::thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let _ = writeln!(out, "{}", progress.status(total, started.elapsed()));
+            }
+        });
+        ProgressReporter {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for ProgressReporter {
+    fn drop(&mut self) {
+        // hanging up wakes the thread at once, however long the interval
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// A `Write` into a buffer that the test can read while the reporter holds the writer.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_status() {
+        let progress = Progress::default();
+        progress.record(1);
+        assert_eq!(
+            progress.status(3, Duration::from_millis(2500)),
+            "1/3 files, 1 match, 2s elapsed"
+        );
+        progress.record(36);
+        assert_eq!(
+            progress.status(3, Duration::from_secs(45)),
+            "2/3 files, 37 matches, 45s elapsed"
+        );
+    }
+
+    #[test]
+    fn test_progress_reporter() {
+        let progress = Arc::new(Progress::default());
+        let out = SharedBuf::default();
+        let reporter =
+            ProgressReporter::start(Duration::from_millis(20), 5, progress.clone(), out.clone());
+        progress.record(2);
+        std::thread::sleep(Duration::from_millis(200));
+        drop(reporter);
+        let written = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        assert!(written.lines().count() >= 1);
+        assert!(written.ends_with("1/5 files, 2 matches, 0s elapsed\n"));
+
+        // a scan that ends before the first interval is not reported on
+        let out = SharedBuf::default();
+        drop(ProgressReporter::start(
+            Duration::from_secs(60),
+            5,
+            progress,
+            out.clone(),
+        ));
+        assert!(out.0.lock().unwrap().is_empty());
+    }
+}
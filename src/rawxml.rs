@@ -0,0 +1,341 @@
+use anyhow::Context;
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+
+/// The part of a .docx package that holds the main document body.
+pub(crate) const DOCUMENT_PART: &str = "word/document.xml";
+
+/// Reads the raw `word/document.xml` part out of a .docx package held in `buffer`, without
+/// interpreting the rest of the package.
+///
+/// # Errors
+///
+/// Will return an error if `buffer` is not a zip archive, or if it has no readable
+/// `word/document.xml` part.
+pub(crate) fn read_document_xml(buffer: &[u8]) -> anyhow::Result<String> {
+    read_part(buffer, DOCUMENT_PART)
+}
+
+/// Reads the XML part called `name` out of the package held in `buffer`. A leading UTF-8 byte
+/// order mark, which some producers write, is dropped.
+///
+/// # Errors
+///
+/// Will return an error if `buffer` is not a zip archive, or if it has no readable part called
+/// `name`.
+pub(crate) fn read_part(buffer: &[u8], name: &str) -> anyhow::Result<String> {
+    let mut archive = ZipArchive::new(Cursor::new(buffer))?;
+    let mut part = archive
+        .by_name(name)
+        .with_context(|| format!("No {} in package", name))?;
+    let mut xml = String::new();
+    part.read_to_string(&mut xml)?;
+    match xml.strip_prefix('\u{feff}') {
+        Some(stripped) => Ok(stripped.to_string()),
+        None => Ok(xml),
+    }
+}
+
+/// Returns the name and texts of each part of the package held in `buffer` for which `select`
+/// gives a key, ordered by that key and then by name, with the texts that `extract` gets from
+/// the name and bytes of the part. Parts that cannot be read, or that `extract` returns `None`
+/// for, are skipped, as are all parts if `buffer` is not a zip archive.
+pub(crate) fn part_texts<K: Ord>(
+    buffer: &[u8],
+    select: impl Fn(&str) -> Option<K>,
+    extract: impl Fn(&str, &[u8]) -> Option<Vec<String>>,
+) -> Vec<(String, Vec<String>)> {
+    let Ok(mut archive) = ZipArchive::new(Cursor::new(buffer)) else {
+        return Vec::new();
+    };
+    let mut names: Vec<(K, String)> = archive
+        .file_names()
+        .filter_map(|name| Some((select(name)?, name.to_string())))
+        .collect();
+    names.sort();
+    let mut parts = Vec::new();
+    for (_, name) in names {
+        let Ok(mut part) = archive.by_name(&name) else {
+            continue;
+        };
+        let mut bytes = Vec::new();
+        if part.read_to_end(&mut bytes).is_err() {
+            continue;
+        }
+        if let Some(texts) = extract(&name, &bytes) {
+            parts.push((name, texts));
+        }
+    }
+    parts
+}
+
+/// Extracts the text of every `<w:t>` element of a WordprocessingML document, one string per
+/// element, with XML character references decoded. All other markup is skipped. The namespace
+/// prefix is not checked, so text elements written with another prefix than `w:` (or none)
+/// are found too; so are the `<a:t>` elements of DrawingML shapes.
+///
+/// This is a deliberately simple scanner rather than a real XML parser, so that it can salvage
+/// text from documents that a strict parser rejects.
+pub(crate) fn xtract_raw_text(xml: &str) -> Vec<String> {
+    let xml = xml.strip_prefix('\u{feff}').unwrap_or(xml);
+    xtract_matching_elements(xml, |name| {
+        name.rsplit_once(':').map_or(name, |(_, local)| local) == "t"
+    })
+}
+
+/// Extracts the text of every paragraph (`<w:p>`) of a WordprocessingML document that has any,
+/// joining its `<w:t>` elements as `xtract_raw_text` finds them, so that the paragraphs match
+/// those read from the document tree. As there, the paragraphs of a text box belong to the
+/// paragraph the box is anchored in.
+pub(crate) fn xtract_raw_paragraphs(xml: &str) -> Vec<String> {
+    let mut paragraphs = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut pos = 0;
+    while let Some(offset) = xml[pos..].find('<') {
+        let tag_start = pos + offset;
+        let rest = &xml[tag_start + 1..];
+        let Some(tag_end) = rest.find('>') else {
+            break;
+        };
+        pos = tag_start + 1 + tag_end + 1;
+        let tag = &rest[..tag_end];
+        let (closing, tag) = match tag.strip_prefix('/') {
+            Some(tag) => (true, tag),
+            None => (false, tag),
+        };
+        let name = tag
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default();
+        if name.rsplit_once(':').map_or(name, |(_, local)| local) != "p" || tag.ends_with('/') {
+            continue;
+        }
+        if !closing {
+            if depth == 0 {
+                start = pos;
+            }
+            depth += 1;
+        } else if depth > 0 {
+            depth -= 1;
+            if depth == 0 {
+                let paragraph = xtract_raw_text(&xml[start..tag_start]).concat();
+                if !paragraph.is_empty() {
+                    paragraphs.push(paragraph);
+                }
+            }
+        }
+    }
+    paragraphs
+}
+
+/// Extracts the text of every `<tag>` element of `xml`, one string per element, with XML
+/// character references decoded, in the same way as `xtract_raw_text`. `tag` must be an element
+/// that holds only text.
+pub(crate) fn xtract_element_text(xml: &str, tag: &str) -> Vec<String> {
+    xtract_matching_elements(xml, |name| name == tag)
+}
+
+/// Extracts the text of every element of `xml` whose qualified name satisfies `wanted`, one
+/// string per element, with XML character references decoded. The elements must hold only
+/// text.
+pub(crate) fn xtract_matching_elements(xml: &str, wanted: impl Fn(&str) -> bool) -> Vec<String> {
+    let mut texts = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let name_len = rest
+            .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            .unwrap_or(rest.len());
+        let name = &rest[..name_len];
+        // skip end tags, comments, processing instructions and elements such as <w:tab>
+        if name.is_empty() || !wanted(name) {
+            continue;
+        }
+        let Some(tag_end) = rest.find('>') else {
+            break;
+        };
+        if rest[..tag_end].ends_with('/') {
+            // a self-closing <w:t/> holds no text
+            rest = &rest[tag_end + 1..];
+            continue;
+        }
+        let close_tag = format!("</{}>", name);
+        rest = &rest[tag_end + 1..];
+        let Some(close) = rest.find(&close_tag) else {
+            break;
+        };
+        texts.push(unescape_xml(&rest[..close]));
+        rest = &rest[close + close_tag.len()..];
+    }
+    texts
+}
+
+/// Extracts the data values of an arbitrary XML document, in document order: the text between
+/// its tags and the values of its attributes, with XML character references decoded and
+/// surrounding whitespace trimmed. Namespace declarations, comments, processing instructions
+/// and whitespace-only text are skipped; CDATA sections are taken as text.
+pub(crate) fn xtract_xml_values(xml: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut push = |value: String| {
+        let value = value.trim();
+        if !value.is_empty() {
+            values.push(value.to_string());
+        }
+    };
+    let mut rest = xml.strip_prefix('\u{feff}').unwrap_or(xml);
+    while !rest.is_empty() {
+        let Some(start) = rest.find('<') else {
+            push(unescape_xml(rest));
+            break;
+        };
+        push(unescape_xml(&rest[..start]));
+        rest = &rest[start..];
+        let (end_marker, is_text) = if rest.starts_with("<![CDATA[") {
+            ("]]>", true)
+        } else if rest.starts_with("<!--") {
+            ("-->", false)
+        } else if rest.starts_with("<?") {
+            ("?>", false)
+        } else {
+            (">", false)
+        };
+        let Some(end) = rest.find(end_marker) else {
+            break;
+        };
+        let markup = &rest[..end];
+        if is_text {
+            push(markup["<![CDATA[".len()..].to_string());
+        } else if !markup.starts_with("<!") && !markup.starts_with("<?") {
+            push_attribute_values(markup, &mut push);
+        }
+        rest = &rest[end + end_marker.len()..];
+    }
+    values
+}
+
+/// Passes the decoded value of each attribute of the start tag `tag` (without its closing `>`)
+/// to `push`, except for namespace declarations.
+fn push_attribute_values(tag: &str, push: &mut impl FnMut(String)) {
+    let mut rest = tag;
+    while let Some(eq) = rest.find('=') {
+        let name = rest[..eq].split_whitespace().last().unwrap_or_default();
+        let after = rest[eq + 1..].trim_start();
+        let Some(quote) = after.chars().next().filter(|&c| c == '"' || c == '\'') else {
+            break;
+        };
+        let Some(close) = after[1..].find(quote) else {
+            break;
+        };
+        if name != "xmlns" && !name.starts_with("xmlns:") {
+            push(unescape_xml(&after[1..close + 1]));
+        }
+        rest = &after[close + 2..];
+    }
+}
+
+/// Decodes the predefined XML entities and numeric character references in `s`. Unknown or
+/// malformed references are left as they are.
+pub(crate) fn unescape_xml(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest.find(';').and_then(|semi| {
+            let entity = &rest[1..semi];
+            let ch = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ => entity
+                    .strip_prefix("#x")
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse::<u32>()))
+                    .and_then(|code| code.ok())
+                    .and_then(char::from_u32),
+            };
+            ch.map(|ch| (ch, semi))
+        });
+        match decoded {
+            Some((ch, semi)) => {
+                out.push(ch);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    #[test]
+    fn test_xtract_raw_text() {
+        let xml = r#"<w:body><w:p><w:r><w:t>Hello</w:t></w:r><w:r><w:tab/><w:t xml:space="preserve"> Tom &amp; Jerry&#33;</w:t></w:r><w:r><w:t/></w:r></w:p></w:body>"#;
+        assert_eq!(xtract_raw_text(xml), vec!["Hello", " Tom & Jerry!"]);
+    }
+
+    #[test]
+    fn test_xtract_raw_text_other_prefixes() {
+        let xml = "\u{feff}<?xml version=\"1.0\"?><ns0:body><ns0:p><ns0:r><ns0:t>first</ns0:t>\
+                   <ns0:tab/></ns0:r><t xml:space=\"preserve\">second</t><ns0:tbl/>\
+                   <ns0:r><ns0:t\n>third</ns0:t></ns0:r></ns0:p></ns0:body>";
+        assert_eq!(xtract_raw_text(xml), vec!["first", "second", "third"]);
+    }
+
+    #[test]
+    fn test_xtract_raw_paragraphs() {
+        let xml = r#"<w:body><w:p><w:pPr/><w:r><w:t>Hello</w:t></w:r><w:r><w:t>, world</w:t><w:drawing><w:txbxContent><w:p><w:r><w:t> boxed</w:t></w:r></w:p></w:txbxContent></w:drawing></w:r></w:p><w:p/><w:p><w:r><w:tab/></w:r></w:p><w:tbl><w:tc><w:p><w:r><w:t>cell</w:t></w:r></w:p></w:tc></w:tbl></w:body>"#;
+        assert_eq!(
+            xtract_raw_paragraphs(xml),
+            vec!["Hello, world boxed", "cell"]
+        );
+    }
+
+    #[test]
+    fn test_xtract_xml_values() {
+        let xml = "\u{feff}<?xml version=\"1.0\"?><!-- a comment -->\
+                   <ns:order xmlns:ns=\"urn:x\" id='ACME-12345'>\n  \
+                   <ns:customer>Tom &amp; Jerry</ns:customer><ns:empty/>\
+                   <ns:note><![CDATA[<raw> text]]></ns:note></ns:order>";
+        assert_eq!(
+            xtract_xml_values(xml),
+            vec!["ACME-12345", "Tom & Jerry", "<raw> text"]
+        );
+    }
+
+    #[test]
+    fn test_unescape_xml() {
+        assert_eq!(unescape_xml("a &lt;b&gt; &#x41;"), "a <b> A");
+        assert_eq!(unescape_xml("fish & chips &bogus;"), "fish & chips &bogus;");
+    }
+
+    #[test]
+    fn test_read_document_xml() -> anyhow::Result<()> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file(DOCUMENT_PART, SimpleFileOptions::default())?;
+        zip.write_all(b"<w:t>salvaged</w:t>")?;
+        let buffer = zip.finish()?.into_inner();
+        assert_eq!(read_document_xml(&buffer)?, "<w:t>salvaged</w:t>");
+        assert!(read_document_xml(b"not a zip").is_err());
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file(DOCUMENT_PART, SimpleFileOptions::default())?;
+        zip.write_all("\u{feff}<x:t>salvaged</x:t>".as_bytes())?;
+        let buffer = zip.finish()?.into_inner();
+        assert_eq!(read_document_xml(&buffer)?, "<x:t>salvaged</x:t>");
+        Ok(())
+    }
+}
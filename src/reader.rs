@@ -1,22 +1,550 @@
 use docx_rs::*;
-use regex::Regex;
 use serde_json::Value;
-use std::io::Read;
-type Run = String;
+use std::io::{IsTerminal, Read, Write};
 type Runs = Vec<Run>;
 use anyhow::Context;
 use colored::Colorize;
 use rayon::prelude::*;
-use std::collections::VecDeque;
-use std::sync::{Arc, Mutex};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{self, Display, Formatter};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::num::NonZeroUsize;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::matcher;
+use crate::customxml;
+use crate::diagrams;
+use crate::docparts;
+use crate::embedded;
+use crate::html;
+use crate::interactive;
+use crate::jsonout;
+use crate::linkeddocs::{self, LinkedSource};
+use crate::manifest::Manifest;
+use crate::matcher::{
+    normalize, sentence_spans, strip_invisibles, ContextSpec, MatchTriple, Normalization, Searcher,
+};
+use crate::metadata;
+use crate::numbering::ListNumbering;
+use crate::progress::{Progress, ProgressReporter};
+use crate::rawxml;
 use crate::selector::make_fnames;
+use crate::semaphore::Semaphore;
+use crate::since::Since;
+use crate::streaming;
+use crate::summary::{ScanSummary, UniqueScope};
+use crate::template::{MatchFields, Template};
+use crate::urlfile;
 use crate::ziphandler::{zip_to_zipentries, ZipEntry};
+use crate::zstfile;
 
-struct SearchResult {
-    file_name: String,
-    maybe_result: anyhow::Result<Runs>,
+pub type FileLike = Arc<dyn ReadIntoBuf + Send + Sync>;
+
+/// The text of a single run, together with where it was found in the document.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Run {
+    pub text: String,
+    /// The text of the closest heading paragraph before the run, if any
+    pub heading: Option<String>,
+    /// 1-based index of the paragraph holding the run, or 0 if it is not known
+    pub para_index: usize,
+    /// The text of the previous run in the same paragraph, kept with `--cross-run-context`
+    pub before: String,
+    /// The text of the next run in the same paragraph, kept with `--cross-run-context`
+    pub after: String,
+    /// Where the text came from, if it is not in the main body of the searched document
+    pub source: Option<RunSource>,
+    /// True if the text is one of the document's properties rather than part of its body
+    pub metadata: bool,
+    /// True if the text is inside a text box or shape rather than in the flow of the body
+    pub text_box: bool,
+    /// 0-based index of the `<w:t>` element of `word/document.xml` that holds the run, counting
+    /// every `<w:t>` of the body in document order, or `None` if the text is not a single element
+    pub element_index: Option<usize>,
+    /// The direct formatting of the run, such as "bold" or "italic" (see `run_formatting`)
+    pub formatting: Vec<&'static str>,
+    /// The text of the paragraphs just before the run's, kept with `--para-before`
+    pub para_before: Vec<String>,
+    /// The text of the paragraphs just after the run's, kept with `--para-after`
+    pub para_after: Vec<String>,
+    /// Byte offset of the run's text, as matched, in the text of its paragraph
+    pub para_offset: usize,
+    /// For each match in the run, in order, the sentence it is in, kept with `--sentence-index`
+    pub sentences: Vec<Sentence>,
+    /// The author of the tracked insertion or comment the text is in, kept with `--by-author`
+    pub author: Option<String>,
+    /// True if the text is in a comment on the document rather than in its body
+    pub comment: bool,
+}
+
+/// Where in or beyond the package of a document the text of a `Run` came from, when it is not
+/// in the main document body.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunSource {
+    /// The embedded spreadsheet part with this name, searched with `--embedded`
+    Embedded(String),
+    /// The custom XML data part with this name, searched with `--search-custom-xml`
+    CustomXml(String),
+    /// The document body part with this name, such as the glossary, searched with
+    /// `--all-document-parts`
+    DocumentPart(String),
+    /// The SmartArt diagram or chart part with this name, searched with `--search-diagrams`
+    Diagram(String),
+    /// The subdocument or imported content, kept with `--follow-rels`, with this label of the
+    /// link to it (see `linkeddocs::Link::label`) after those of the links leading to the
+    /// document that holds that link
+    Linked(String),
+}
+
+impl RunSource {
+    /// Returns the kind of source, as it is given in `--json` output: "embedded",
+    /// "custom_xml", "document_part", "diagram" or "linked".
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RunSource::Embedded(_) => "embedded",
+            RunSource::CustomXml(_) => "custom_xml",
+            RunSource::DocumentPart(_) => "document_part",
+            RunSource::Diagram(_) => "diagram",
+            RunSource::Linked(_) => "linked",
+        }
+    }
+
+    /// Returns the name of the part, or the label of the link, that the text came from.
+    pub fn name(&self) -> &str {
+        match self {
+            RunSource::Embedded(name)
+            | RunSource::CustomXml(name)
+            | RunSource::DocumentPart(name)
+            | RunSource::Diagram(name)
+            | RunSource::Linked(name) => name,
+        }
+    }
+
+    /// Returns how matches from the source are labelled in the text output, such as
+    /// "embedded xlsx word/embeddings/Sheet1.xlsx" or "chart word/charts/chart1.xml".
+    fn label(&self) -> String {
+        match self {
+            RunSource::Embedded(part) => format!("embedded xlsx {}", part),
+            RunSource::CustomXml(part) => format!("custom xml {}", part),
+            RunSource::DocumentPart(part) => format!("document part {}", part),
+            RunSource::Diagram(part) => {
+                diagrams::graphic_label(part).unwrap_or_else(|| part.clone())
+            }
+            RunSource::Linked(label) => label.clone(),
+        }
+    }
+}
+
+/// The sentence of its paragraph that a match is in (see `add_sentences`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sentence {
+    /// 1-based number of the sentence, counting the sentences of every paragraph of the document
+    /// in order
+    pub number: usize,
+    /// The text of the sentence, as matched
+    pub text: String,
+    /// The byte span of the match in `text`
+    pub span: (usize, usize),
+}
+
+impl Run {
+    /// Segments the run into `MatchTriple`s around the matches of `searcher`. The context of
+    /// each match runs on into the neighbouring runs if they were kept.
+    pub fn segment(&self, searcher: &Searcher, context: ContextSpec) -> Vec<MatchTriple> {
+        searcher.segment_between(&self.before, &self.text, &self.after, context)
+    }
+
+    /// Returns the offset, in characters from the start of the run's text, of each match of
+    /// `searcher`, in the same order as the `MatchTriple`s of `segment`.
+    pub fn match_offsets(&self, searcher: &Searcher) -> Vec<usize> {
+        searcher
+            .find_spans(&self.text)
+            .into_iter()
+            .map(|(start, _)| self.text[..start].chars().count())
+            .collect()
+    }
+
+    /// Returns, for each match of `searcher`, the index of the run's `<w:t>` element and the
+    /// character offset of the match within it, or `None` for every match if the run has no
+    /// single element.
+    pub fn source_positions(&self, searcher: &Searcher) -> Vec<Option<(usize, usize)>> {
+        self.match_offsets(searcher)
+            .into_iter()
+            .map(|offset| self.element_index.map(|element| (element, offset)))
+            .collect()
+    }
+}
+
+impl From<&str> for Run {
+    fn from(text: &str) -> Self {
+        Run {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<String> for Run {
+    fn from(text: String) -> Self {
+        Run {
+            text,
+            ..Default::default()
+        }
+    }
+}
+
+impl PartialEq<&str> for Run {
+    fn eq(&self, other: &&str) -> bool {
+        self.text == *other
+    }
+}
+
+pub struct SearchResult {
+    pub file_name: String,
+    pub archive_name: Option<String>,
+    /// Path of the document, with archive entries under their archive (see `get_source_path`)
+    pub source_path: PathBuf,
+    pub maybe_result: anyhow::Result<ParsedDoc>,
+    pub timed_out: bool,
+    pub size: Option<FileSize>,
+}
+
+/// Options controlling how files are searched and how the results are displayed.
+#[derive(Debug, Default, Clone)]
+#[non_exhaustive]
+pub struct SearchOptions {
+    /// True if only the number of matched runs of each file is shown, not the matches
+    pub quiet: bool,
+    pub context: ContextSpec,
+    pub summary: bool,
+    /// True if files without matches are shown too
+    pub unmatched_show: bool,
+    pub timeout: Option<Duration>,
+    pub verbose: bool,
+    pub group_by_archive: bool,
+    /// True if the text of a document that cannot be read as .docx is recovered directly
+    /// from its `word/document.xml` instead
+    pub raw_fallback: bool,
+    pub html: Option<PathBuf>,
+    /// True if only whether each file matched is shown, not the matches
+    pub first_match_only: bool,
+    /// Where the full text of each document is also written out
+    pub extract_text: Option<PathBuf>,
+    pub show_heading: bool,
+    pub align: Option<usize>,
+    pub center: Option<usize>,
+    pub para_range: Option<ParaRange>,
+    /// True if a hash of the full text of each document is returned along with its runs,
+    /// so that documents with the same text are shown once
+    pub dedupe: bool,
+    /// True if the first document that cannot be searched cancels the rest of the scan, and
+    /// `process_files` returns its error
+    pub fail_fast: bool,
+    pub json: bool,
+    pub pretty: bool,
+    pub line_buffered: bool,
+    pub cross_run_context: bool,
+    /// Each match is shown as a single line made from this template instead (see
+    /// `format_templated`)
+    pub format: Option<Template>,
+    pub normalize: Option<Normalization>,
+    /// True if the cells of embedded spreadsheets are searched, after the SmartArt diagrams
+    /// and charts
+    pub embedded: bool,
+    /// The beginning of the full text of each document, this many characters of it, is
+    /// returned along with its runs and shown even if it has no matches
+    pub peek: Option<usize>,
+    /// Documents larger than this, in bytes, are skipped, and not counted as searched
+    pub max_file_size: Option<u64>,
+    /// True if the document properties are searched, after the custom XML data parts
+    pub search_metadata: bool,
+    /// A document whose revision number does not exceed this, or is not recorded, is
+    /// not searched and has no runs
+    pub filter_revisions: Option<u64>,
+    pub context_lines: Option<usize>,
+    /// True if the results are browsed one file at a time (see `interactive::browse`)
+    /// instead of printed, when standard input is a terminal
+    pub interactive: bool,
+    pub table_row_context: bool,
+    pub source_positions: bool,
+    /// No more than this many documents are read and parsed at once
+    pub parallel_files: Option<NonZeroUsize>,
+    pub show_formatting: bool,
+    /// Documents last modified before this are skipped, and not counted as searched;
+    /// those whose modification time is unknown are searched anyway
+    pub since: Option<Since>,
+    pub para_before: usize,
+    pub para_after: usize,
+    /// True if zip and tar archives are not opened
+    pub no_archives: bool,
+    /// True if only zip and tar archives are searched
+    pub archives_only: bool,
+    /// The manifest of documents already searched (see `manifest::Manifest`): those it
+    /// records as searched unchanged are skipped, and not counted as searched, and each
+    /// document searched is added to it
+    pub resume: Option<PathBuf>,
+    pub list_numbers: bool,
+    /// True if the runs of the body are ordered by paragraph, the paragraphs with the most
+    /// matches first
+    pub rank_paragraphs: bool,
+    /// Only the first this many files with results, in the order they were found, are
+    /// output, but all are counted in the summary
+    pub head: Option<usize>,
+    /// Only the last this many files with results, in the order they were found, are
+    /// output, but all are counted in the summary
+    pub tail: Option<usize>,
+    /// The pool hands out documents in runs of at least this many, rather than one at
+    /// a time
+    pub chunk_size: Option<NonZeroUsize>,
+    /// The JSON representation of each document is written out, to the directory given
+    /// or else to stdout, instead of being searched, and there are no runs
+    pub dump_json_tree: Option<Option<PathBuf>>,
+    pub strip_invisibles: bool,
+    /// True if each match is numbered as `RUN-MATCH`, by its run and its place in the run,
+    /// rather than in order through the whole document
+    pub compound_index: bool,
+    /// A document with fewer matches than this is treated as having none, and counted
+    /// apart in the summary
+    pub min_matches: Option<usize>,
+    /// True if the custom XML data parts are searched, after the cells of embedded
+    /// spreadsheets
+    pub search_custom_xml: bool,
+    /// True if each file is held back until the files found before it have been printed, so
+    /// that the output is always in the order the files were found, rather than printed as
+    /// soon as it has been searched
+    pub ordered: bool,
+    /// True if the body of each document is searched by streaming it (see
+    /// `stream_matching_runs`), as it is anyway when `word/document.xml` is larger than
+    /// `STREAMING_THRESHOLD`, unless an option that needs the whole document tree is set
+    pub streaming: bool,
+    /// Where distinct matched strings are counted; with `UniqueScope::File`, their number
+    /// follows the matches of each file
+    pub count_unique: Option<UniqueScope>,
+    /// True if the distinct matched strings counted by `count_unique` are listed too, sorted
+    pub list_unique: bool,
+    pub sentence_index: bool,
+    /// True if control characters in the text shown around each match are written as
+    /// escapes (see `MatchTriple::escape`)
+    pub escape_output: bool,
+    pub dehyphenate: bool,
+    pub require_all_in_paragraph: bool,
+    /// Once this many matches have been found, the rest of the scan is cancelled (see
+    /// `take_matches`); which matches those are depends on which documents the pool finished
+    /// searching first, and so may differ from one run to the next
+    pub max_results: Option<usize>,
+    /// The paragraphs with the most matches, this many of them, are listed instead of the
+    /// matches (see `format_paragraph_counts`)
+    pub count_per_paragraph: Option<usize>,
+    /// The line written after the output of each file, or `===` if not set
+    pub group_separator: Option<String>,
+    /// True if no line is written after the output of each file, whatever `group_separator` is
+    pub no_group_separator: bool,
+    /// True if the document bodies of the package other than the main one, such as its
+    /// glossary, are searched too, after the main body
+    pub all_document_parts: bool,
+    /// If standard output is not a terminal, a line giving the number of documents
+    /// searched and matches found so far is written to standard error at this interval (see
+    /// `ProgressReporter`)
+    pub progress_interval: Option<Duration>,
+    /// True if the text of SmartArt diagrams and charts is searched, after the other
+    /// document bodies
+    pub search_diagrams: bool,
+    /// True if the white space at the outer ends of the context of each match is left out
+    /// (see `MatchTriple::trim_ends`)
+    pub trim: bool,
+    pub by_author: Option<String>,
+    /// True if the bodies of the documents each document links to are searched last (see
+    /// `search_linked_documents`)
+    pub follow_rels: bool,
+    /// Further extensions of files to search, with the formats they are read as
+    pub include_ext: Vec<IncludedExtension>,
+    /// Readers for other kinds of file, added with `register_reader`
+    pub(crate) custom_readers: CustomReaders,
+}
+
+impl SearchOptions {
+    /// Registers `factory` to make the reader of each file whose name ends with the extension
+    /// `ext`, such as `.docz`, given with or without its leading dot. The files with that
+    /// extension under the base directory are then searched along with the .docx files, each
+    /// with the reader that `factory` makes from its path; the bytes it reads must be those of
+    /// a .docx package. A file that a registered extension matches is read only by its reader,
+    /// even if its name ends with `.docx` or `.zip` as well, and if several extensions match,
+    /// the one registered last is used. See `ReaderFactory` for what the factory must allow.
+    pub fn register_reader(&mut self, ext: &str, factory: ReaderFactory) {
+        let extension = format!(".{}", ext.trim_start_matches('.'));
+        self.custom_readers.0.push((extension, factory));
+    }
+
+    /// Returns the line written after the output of each file: `group_separator`, `===` by
+    /// default, or `None` if `no_group_separator` is set.
+    pub fn group_separator(&self) -> Option<&str> {
+        if self.no_group_separator {
+            None
+        } else {
+            Some(self.group_separator.as_deref().unwrap_or("==="))
+        }
+    }
+
+    /// Returns the extensions, with their leading dots, of the files searched under a
+    /// directory: the default ones (see `FileFormat::DEFAULTS`), those of `include_ext` and
+    /// those of the registered readers, each once, without the archive formats if
+    /// `no_archives` is set or the others if `archives_only` is.
+    pub fn searched_extensions(&self) -> Vec<String> {
+        let formats = FileFormat::DEFAULTS.into_iter().chain(
+            self.include_ext
+                .iter()
+                .map(|included| (included.extension.as_str(), included.format)),
+        );
+        let mut extensions: Vec<String> = Vec::new();
+        for (extension, format) in formats {
+            let wanted = if format.is_archive() {
+                !self.no_archives
+            } else {
+                !self.archives_only
+            };
+            if wanted && !extensions.iter().any(|known| known == extension) {
+                extensions.push(extension.to_string());
+            }
+        }
+        if !self.archives_only {
+            for (extension, _) in &self.custom_readers.0 {
+                if !extensions.contains(extension) {
+                    extensions.push(extension.clone());
+                }
+            }
+        }
+        extensions
+    }
+
+    /// Returns true if text written by `author`, or by no one in particular if it is `None`, is
+    /// searched: always without `by_author`, and otherwise only if `author` is that name, in any
+    /// case.
+    fn is_by_author(&self, author: Option<&str>) -> bool {
+        match (&self.by_author, author) {
+            (None, _) => true,
+            (Some(name), Some(author)) => author.to_lowercase() == name.to_lowercase(),
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Returns the context to show around matches in `text`: `context_lines` lines if it is set
+    /// and `text` has more than one line, or `context` otherwise.
+    pub(crate) fn context_for(&self, text: &str) -> ContextSpec {
+        match self.context_lines {
+            Some(n) if text.contains('\n') => ContextSpec::Lines(n),
+            _ => self.context,
+        }
+    }
+
+    /// Returns `text` as it is matched: without invisible characters if `strip_invisibles` is
+    /// set, and then in the normalization form `normalize`, if set.
+    fn match_text<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let stripped = if self.strip_invisibles {
+            strip_invisibles(text)
+        } else {
+            Cow::Borrowed(text)
+        };
+        match normalize(&stripped, self.normalize) {
+            Cow::Borrowed(_) => stripped,
+            Cow::Owned(normalized) => Cow::Owned(normalized),
+        }
+    }
+
+    /// Returns true if documents can be searched by streaming their body (see
+    /// `streaming::walk_runs`): none of the options that need the whole document tree is set.
+    fn can_stream(&self) -> bool {
+        !self.table_row_context
+            && !self.list_numbers
+            && self.para_before == 0
+            && self.para_after == 0
+            && self.extract_text.is_none()
+            && !self.dedupe
+            && self.peek.is_none()
+            && self.dump_json_tree.is_none()
+            && !self.sentence_index
+            && !self.dehyphenate
+            && !self.require_all_in_paragraph
+            && self.by_author.is_none()
+    }
+}
+
+/// The size of `word/document.xml`, unzipped, above which a document is searched by streaming
+/// it even without `--streaming`, rather than by building its whole tree in memory.
+const STREAMING_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// The name of the group under which `--group-by-archive` lists files that are not in an archive.
+const LOOSE_FILES: &str = "loose files";
+
+/// The size of a searched document: on disk for regular files, or as recorded in the
+/// archive header for zip entries.
+#[derive(Debug, PartialEq)]
+pub enum FileSize {
+    OnDisk(u64),
+    Archived { compressed: u64, uncompressed: u64 },
+}
+
+impl Display for FileSize {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FileSize::OnDisk(size) => write!(f, "size: {} bytes", size),
+            FileSize::Archived {
+                compressed,
+                uncompressed,
+            } => {
+                let ratio = if *uncompressed == 0 {
+                    1.0
+                } else {
+                    *compressed as f64 / *uncompressed as f64
+                };
+                write!(
+                    f,
+                    "compressed: {} bytes, uncompressed: {} bytes (ratio {:.2})",
+                    compressed, uncompressed, ratio
+                )
+            }
+        }
+    }
+}
+
+impl FileSize {
+    /// Returns the number of bytes the document will take once read: its size on disk, or its
+    /// uncompressed size for an archive entry.
+    pub fn bytes(&self) -> u64 {
+        match *self {
+            FileSize::OnDisk(size) => size,
+            FileSize::Archived { uncompressed, .. } => uncompressed,
+        }
+    }
+}
+
+/// How far past its declared size an archive entry may inflate before it is rejected as a
+/// possible decompression bomb. An entry may produce twice its declared size plus this margin.
+const INFLATE_MARGIN: u64 = 1024 * 1024;
+
+/// Reads an archive entry called `name`, whose header declares `declared` uncompressed bytes,
+/// from `reader`. Reading stops with an error as soon as the entry produces more than twice its
+/// declared size plus `INFLATE_MARGIN`, so that a lying header cannot exhaust memory.
+fn read_bounded(reader: impl Read, declared: u64, name: &str) -> anyhow::Result<Vec<u8>> {
+    let bound = declared.saturating_mul(2).saturating_add(INFLATE_MARGIN);
+    let mut buffer = Vec::new();
+    reader
+        .take(bound.saturating_add(1))
+        .read_to_end(&mut buffer)
+        .with_context(|| format!("Failed to read {}", name))?;
+    if buffer.len() as u64 > bound {
+        anyhow::bail!(
+            "Refusing to read {}: it inflates to more than {} bytes but declares {}",
+            name,
+            bound,
+            declared
+        );
+    }
+    Ok(buffer)
 }
 
 /// Reads the contents of a file at the given `path` into a vector of bytes.
@@ -36,6 +564,157 @@ fn read_to_vec(path: &str) -> anyhow::Result<Vec<u8>> {
 pub trait ReadIntoBuf {
     fn read_into_buf(&self) -> anyhow::Result<Vec<u8>>;
     fn get_fname(&self) -> String;
+    fn get_size(&self) -> anyhow::Result<FileSize>;
+    /// Returns when the document was last modified: the file's modification time, or for an
+    /// archive entry the time recorded for it in the archive. By default the time is not known,
+    /// and the document is searched whatever `--since` says and left out of `--resume`
+    /// manifests.
+    fn get_modified(&self) -> anyhow::Result<SystemTime> {
+        anyhow::bail!("Modification time of {} is not known", self.get_fname())
+    }
+    fn get_archive_name(&self) -> Option<String>;
+    fn get_source_path(&self) -> PathBuf;
+}
+
+/// Makes the `ReadIntoBuf` for the file at the path it is given, for a reader registered with
+/// `SearchOptions::register_reader`.
+///
+/// The factory is called while the documents to search are being listed, before any is
+/// searched, but the searches run on a thread pool: it must be `Send + Sync` so that the
+/// `SearchOptions` holding it can be shared with the pool, and the readers it makes must be
+/// `Send + Sync` too, as each may be read on any of its threads, and by several documents'
+/// searches at once if they share state.
+pub type ReaderFactory = Arc<dyn Fn(&str) -> Box<dyn ReadIntoBuf + Send + Sync> + Send + Sync>;
+
+/// The readers registered with `SearchOptions::register_reader`, with the file extensions they
+/// read, in the order they were registered.
+#[derive(Clone, Default)]
+pub(crate) struct CustomReaders(Vec<(String, ReaderFactory)>);
+
+impl fmt::Debug for CustomReaders {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|(extension, _)| extension))
+            .finish()
+    }
+}
+
+impl CustomReaders {
+    /// Returns the factory registered last for an extension that `fname` ends with, if any.
+    fn factory(&self, fname: &str) -> Option<&ReaderFactory> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(extension, _)| fname.ends_with(extension.as_str()))
+            .map(|(_, factory)| factory)
+    }
+}
+
+/// The format a file is read as, which decides the documents it yields.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FileFormat {
+    /// A .docx package, or another WordprocessingML package such as a .docm or .dotx
+    Docx,
+    /// A zip archive, whose .docx entries are searched
+    Zip,
+    /// A zstandard-compressed .docx package
+    DocxZst,
+    /// A zstandard-compressed tar archive, whose .docx entries are searched
+    TarZst,
+}
+
+impl FileFormat {
+    /// The extensions searched without `--include-ext`, in the order their files are listed,
+    /// with the formats they are read as.
+    pub(crate) const DEFAULTS: [(&'static str, FileFormat); 4] = [
+        (".docx", FileFormat::Docx),
+        (".zip", FileFormat::Zip),
+        (".docx.zst", FileFormat::DocxZst),
+        (".tar.zst", FileFormat::TarZst),
+    ];
+
+    /// The names of the formats, as given after the `=` of `--include-ext EXT=HANDLER`.
+    const NAMES: [(&'static str, FileFormat); 4] = [
+        ("docx", FileFormat::Docx),
+        ("zip", FileFormat::Zip),
+        ("docx.zst", FileFormat::DocxZst),
+        ("tar.zst", FileFormat::TarZst),
+    ];
+
+    /// Returns the format that files with the extension `extension` are known to be in: the
+    /// default ones, and the other WordprocessingML packages (macro-enabled documents and
+    /// templates), which are read as .docx. Like the listing of files, this goes by case, so
+    /// that `.DOCM` is not known.
+    fn for_extension(extension: &str) -> Option<FileFormat> {
+        match extension {
+            ".docm" | ".dotx" | ".dotm" => Some(FileFormat::Docx),
+            extension => Self::DEFAULTS
+                .iter()
+                .find(|(known, _)| *known == extension)
+                .map(|&(_, format)| format),
+        }
+    }
+
+    /// Returns true if files in this format are archives of documents rather than documents,
+    /// and so are left out with `--no-archives` and alone searched with `--archives-only`.
+    fn is_archive(self) -> bool {
+        matches!(self, FileFormat::Zip | FileFormat::TarZst)
+    }
+}
+
+/// An extension of files to search, with the format they are read as, parsed from
+/// `--include-ext EXT[=HANDLER]`: `.docm`, say, or `.tzst=tar.zst`. The handler may be left out
+/// for an extension whose format is known (see `FileFormat::for_extension`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncludedExtension {
+    /// The extension, with its leading dot
+    extension: String,
+    format: FileFormat,
+}
+
+impl FromStr for IncludedExtension {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> anyhow::Result<Self> {
+        let (extension, handler) = match spec.split_once('=') {
+            Some((extension, handler)) => (extension.trim(), Some(handler.trim())),
+            None => (spec.trim(), None),
+        };
+        let extension = format!(".{}", extension.trim_start_matches('.'));
+        if extension == "." {
+            anyhow::bail!("Invalid extension '{}': expected EXT[=HANDLER]", spec);
+        }
+        let handlers = || {
+            FileFormat::NAMES
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let format = match handler {
+            Some(handler) => FileFormat::NAMES
+                .iter()
+                .find(|(name, _)| *name == handler.to_ascii_lowercase())
+                .map(|&(_, format)| format)
+                .with_context(|| {
+                    format!(
+                        "Unknown handler '{}' for {} files: expected one of {}",
+                        handler,
+                        extension,
+                        handlers()
+                    )
+                })?,
+            None => FileFormat::for_extension(&extension).with_context(|| {
+                format!(
+                    "No handler is known for {} files: give one as {}=HANDLER, where HANDLER is one of {}",
+                    extension,
+                    extension,
+                    handlers()
+                )
+            })?,
+        };
+        Ok(IncludedExtension { extension, format })
+    }
 }
 
 #[derive(Debug)]
@@ -59,260 +738,4019 @@ impl ReadIntoBuf for RegularFile {
     fn get_fname(&self) -> String {
         self.fname.clone()
     }
+
+    fn get_size(&self) -> anyhow::Result<FileSize> {
+        let metadata = std::fs::metadata(&self.fname)
+            .with_context(|| format!("Failed to read metadata: {}", self.fname))?;
+        Ok(FileSize::OnDisk(metadata.len()))
+    }
+
+    fn get_modified(&self) -> anyhow::Result<SystemTime> {
+        modified_time(&self.fname)
+    }
+
+    fn get_archive_name(&self) -> Option<String> {
+        None
+    }
+
+    fn get_source_path(&self) -> PathBuf {
+        PathBuf::from(&self.fname)
+    }
 }
 
 impl ReadIntoBuf for ZipEntry {
     fn read_into_buf(&self) -> anyhow::Result<Vec<u8>> {
         let mut archive = zip::ZipArchive::new(std::fs::File::open(&self.archive_name)?)?;
-        let mut file = archive.by_name(&self.entry_name)?;
-        let mut buffer = vec![];
-        file.read_to_end(&mut buffer)?;
-        Ok(buffer)
+        let file = archive.by_name(&self.entry_name)?;
+        read_bounded(file, self.uncompressed_size, &self.get_fname())
     }
 
     fn get_fname(&self) -> String {
         format! {"File: {} in {}", self.entry_name, self.archive_name}.clone()
     }
+
+    fn get_size(&self) -> anyhow::Result<FileSize> {
+        Ok(FileSize::Archived {
+            compressed: self.compressed_size,
+            uncompressed: self.uncompressed_size,
+        })
+    }
+
+    fn get_modified(&self) -> anyhow::Result<SystemTime> {
+        self.last_modified
+            .with_context(|| format!("No valid modification time for {}", self.get_fname()))
+    }
+
+    fn get_archive_name(&self) -> Option<String> {
+        Some(self.archive_name.clone())
+    }
+
+    fn get_source_path(&self) -> PathBuf {
+        Path::new(&self.archive_name).join(&self.entry_name)
+    }
+}
+
+/// The text runs extracted from a document that match the search, together with how they
+/// were extracted.
+#[derive(Debug, Default)]
+pub struct ParsedDoc {
+    pub runs: Runs,
+    /// True if `read_docx` rejected the document and the runs were salvaged from the raw XML
+    pub raw_fallback: bool,
+    /// Hash of the document's full text, computed when searching with `--dedupe`
+    pub content_hash: Option<u64>,
+    /// The beginning of the document's full text, kept when searching with `--peek`
+    pub peek: Option<String>,
+    /// True if the runs were dropped for holding fewer matches than `--min-matches`
+    pub below_min_matches: bool,
+    /// Length of the .docx package that was read, once taken out of any archive it is in
+    pub bytes_read: u64,
+    /// Number of runs of the body with any text other than white space, whether or not they
+    /// matched, or `None` if the body was not searched; the count stops where the search did
+    pub text_runs: Option<usize>,
+}
+
+impl ParsedDoc {
+    /// Returns true if the body of the document was searched but has no text, as happens when
+    /// it holds only scanned images.
+    pub fn has_no_text(&self) -> bool {
+        self.text_runs == Some(0)
+    }
 }
 
 /// Parses a DOCX file or archive entry specified by `file_like` (which must implement `ReadIntoBuf`)
-/// and extracts text that matches the given regular expression `search_re`.
+/// and extracts text that matches the given `searcher`.
 ///
 /// # Arguments
 ///
 /// * `file_like` - A reference to the name of a `file_like` object (docx or zip subarchive) to be parsed.
-/// * `search_re` - A reference to the regular expression used to find matching text within the DOCX file.
+/// * `searcher` - A reference to the `Searcher` used to find matching text within the DOCX file.
+/// * `opts` - The `SearchOptions` for this search, which choose the parts of the package that
+///   are searched and how (see the fields of `SearchOptions`).
 ///
 /// # Returns
 ///
-/// * `anyhow::Result<Runs>` - A result containing a vector of text runs that match the regular expression,
-///   or an error if the parsing or reading process fails.
-#[allow(clippy::borrowed_box)]
+/// * `anyhow::Result<ParsedDoc>` - A result containing the text runs that match the regular
+///   expression, or an error if the parsing or reading process fails.
 fn parse_docx(
-    file_like: &Box<dyn ReadIntoBuf + Send + Sync>,
-    search_re: &Regex,
-) -> anyhow::Result<Runs> {
+    file_like: &FileLike,
+    searcher: &Searcher,
+    opts: &SearchOptions,
+) -> anyhow::Result<ParsedDoc> {
     let buffer = file_like.read_into_buf()?;
-    let data: Value = serde_json::from_str(
-        &read_docx(&buffer)
-            .with_context(|| {
-                format!(
-                    "Error decoding {}",
-                    file_like.get_fname().bright_red().on_black()
-                )
-            })?
-            .json(),
-    )?;
-    let matched_runs = xtract_text_from_doctree(&data, search_re);
-    Ok(matched_runs)
-}
-
-/// Processes files matching the given glob pattern, searching for text that matches the
-/// specified regular expression, and printing the results.
-///
-/// # Arguments
-///
-/// * `base_dir` - A glob base_dir to match files`.
-/// * `search_re` - A regular expression used to search for matching text within each file.
-/// * `quiet` - A boolean flag to control whether minimal output is shown.
-///
-/// # Returns
-///
-/// * `anyhow::Result<()>` - Returns an Ok result if processing is successful; otherwise, returns an error.
-pub(crate) fn process_files(
-    base_dir: &str,
-    search_re: &Regex,
-    quiet: bool,
-    n_context_chars: usize,
-    summary: bool,
-    unmatched_show: bool,
-) -> anyhow::Result<()> {
-    // output mutex
-    let output_mutex = Arc::new(Mutex::new(0));
-    let zip_fnames = make_fnames(base_dir, ".zip")?;
-    let docx_fnames = make_fnames(base_dir, ".docx")?;
-    let nfiles = docx_fnames.fnames.len();
-    let nzips = zip_fnames.fnames.len();
-    let mut file_surrogates: Vec<Box<dyn ReadIntoBuf + Send + Sync>> = Vec::new();
-    for fname in &docx_fnames.fnames {
-        file_surrogates.push(Box::new(RegularFile {
-            fname: fname.clone(),
-        }));
+    let bytes_read = buffer.len() as u64;
+    if let Some(threshold) = opts.filter_revisions {
+        let revision = metadata::read_properties(&buffer).revision;
+        if revision.is_none_or(|revision| revision <= threshold) {
+            return Ok(ParsedDoc {
+                bytes_read,
+                ..Default::default()
+            });
+        }
     }
-    for zip_fname in &zip_fnames.fnames {
-        let zipentries = zip_to_zipentries(zip_fname)?;
-        for ze in zipentries {
-            file_surrogates.push(Box::new(ze));
+    let decode_error = || {
+        format!(
+            "Error decoding {}",
+            file_like.get_fname().bright_red().on_black()
+        )
+    };
+    if opts.can_stream()
+        && (opts.streaming
+            || streaming::document_size(&buffer).is_some_and(|size| size > STREAMING_THRESHOLD))
+    {
+        let (mut runs, text_runs) =
+            stream_matching_runs(&buffer, searcher, opts).with_context(decode_error)?;
+        if opts.rank_paragraphs {
+            rank_paragraphs(&mut runs, searcher);
         }
+        search_outside_body(&buffer, searcher, opts, &mut runs);
+        search_linked_documents(&buffer, file_like, searcher, opts, &mut runs);
+        return Ok(ParsedDoc {
+            runs,
+            bytes_read,
+            text_runs: Some(text_runs),
+            ..Default::default()
+        });
     }
-
-    file_surrogates
-        .par_iter()
-        .map(|file_like| {
-            let result = parse_docx(file_like, search_re);
-            SearchResult {
-                file_name: file_like.get_fname().to_string(),
-                maybe_result: result,
+    let docx = match read_docx_catching_panics(&buffer) {
+        Ok(docx) => docx,
+        Err(e) if opts.raw_fallback => {
+            let xml = rawxml::read_document_xml(&buffer)
+                .with_context(|| format!("{} (raw fallback failed: {})", decode_error(), e))?;
+            let texts = rawxml::xtract_raw_text(&xml);
+            let mut content_hash = None;
+            let mut peek = None;
+            if opts.extract_text.is_some() || opts.dedupe || opts.peek.is_some() {
+                // the same paragraphs as the document tree gives, so that the hash of a document
+                // read this way is the hash of the same text read normally
+                let paragraphs = rawxml::xtract_raw_paragraphs(&xml);
+                if let Some(out_dir) = &opts.extract_text {
+                    write_extracted_text(out_dir, file_like, &paragraphs)?;
+                }
+                content_hash = opts.dedupe.then(|| hash_text(&paragraphs));
+                peek = opts.peek.map(|n| peek_text(&paragraphs, n));
             }
-        })
-        .for_each(|search_result| {
-            print_result(
-                &search_result,
-                search_re,
-                quiet,
-                output_mutex.clone(),
-                n_context_chars,
-                unmatched_show,
-            );
-        });
-    let fileword = if nfiles == 1 { "file" } else { "files" };
-    let zipword = if nzips == 1 {
-        "zip archive"
-    } else {
-        "zip archives"
-    };
-    println!("Searched {nfiles} {fileword} amd {nzips} {zipword}\n");
-    println!(
-        "  Search parameters: regex: {}, base_path={:#?}\n\n",
-        search_re, base_dir
-    );
-    if summary {
-        for fname in &docx_fnames.fnames {
-            println!("Searched docx file  {}", fname);
+            let text_runs = texts.iter().filter(|text| has_text(text)).count();
+            let limit = if opts.first_match_only { 1 } else { usize::MAX };
+            let mut runs: Runs = texts
+                .iter()
+                .map(|text| opts.match_text(text).into_owned())
+                .enumerate()
+                // the raw text carries no authors to match `opts.by_author`
+                .filter(|(_, text)| searcher.is_match(text) && opts.is_by_author(None))
+                .take(limit)
+                .map(|(element_index, text)| Run {
+                    text,
+                    element_index: Some(element_index),
+                    ..Default::default()
+                })
+                .collect();
+            search_outside_body(&buffer, searcher, opts, &mut runs);
+            search_linked_documents(&buffer, file_like, searcher, opts, &mut runs);
+            return Ok(ParsedDoc {
+                runs,
+                raw_fallback: true,
+                content_hash,
+                peek,
+                below_min_matches: false,
+                bytes_read,
+                text_runs: Some(text_runs),
+            });
         }
-        for fname in &zip_fnames.fnames {
-            println!("Searched zip archive  {}", fname);
+        Err(e) => return Err(e).with_context(decode_error),
+    };
+    let data: Value = serde_json::from_str(&docx.json())?;
+    if let Some(out_dir) = &opts.dump_json_tree {
+        dump_json_tree(out_dir.as_deref(), file_like, &data)?;
+        return Ok(ParsedDoc {
+            bytes_read,
+            ..Default::default()
+        });
+    }
+    let mut content_hash = None;
+    let mut peek = None;
+    if opts.extract_text.is_some() || opts.dedupe || opts.peek.is_some() {
+        let paragraphs = xtract_paragraphs_from_doctree(&data);
+        if let Some(out_dir) = &opts.extract_text {
+            write_extracted_text(out_dir, file_like, &paragraphs)?;
         }
+        content_hash = opts.dedupe.then(|| hash_text(&paragraphs));
+        peek = opts.peek.map(|n| peek_text(&paragraphs, n));
     }
-    Ok(())
+    let (mut runs, text_runs) = xtract_text_from_doctree(&data, searcher, opts);
+    if opts.require_all_in_paragraph {
+        runs = paragraphs_matching_all(&data, &runs, searcher, opts);
+    }
+    if opts.para_before > 0 || opts.para_after > 0 {
+        let paragraphs = xtract_indexed_paragraphs(&data);
+        add_neighbouring_paragraphs(&mut runs, &paragraphs, opts.para_before, opts.para_after);
+    }
+    if opts.sentence_index {
+        let paragraphs: Vec<(usize, String)> = xtract_indexed_paragraphs(&data)
+            .into_iter()
+            .map(|(index, text)| (index, opts.match_text(&text).into_owned()))
+            .collect();
+        add_sentences(&mut runs, &paragraphs, searcher);
+    }
+    if opts.rank_paragraphs {
+        rank_paragraphs(&mut runs, searcher);
+    }
+    if opts.by_author.is_some() && (runs.is_empty() || !opts.first_match_only) {
+        runs.extend(search_comments(&data, searcher, opts));
+    }
+    search_outside_body(&buffer, searcher, opts, &mut runs);
+    search_linked_documents(&buffer, file_like, searcher, opts, &mut runs);
+    Ok(ParsedDoc {
+        runs,
+        raw_fallback: false,
+        content_hash,
+        peek,
+        below_min_matches: false,
+        bytes_read,
+        text_runs: Some(text_runs),
+    })
 }
 
-/// Prints the search results for a DOCX file, highlighting matches of a regular expression.
-///
-/// # Arguments
-///
-/// * `result` - A reference to a `SearchResult` struct containing the file name and potential matches.
-/// * `re` - A reference to the regular expression used for identifying matches in the text runs.
-/// * `quiet` - A boolean indicating whether to suppress detailed output. If true, only the count of
-///   matched runs is printed. Otherwise, details of each match within each run are printed.
+/// Calls `read_docx` on `buffer`, turning a panic inside it, which some malformed documents
+/// cause, into an error like any other, so that it fails only this document rather than the
+/// whole search.
 ///
-/// # Behavior
+/// # Errors
 ///
-/// If a `SearchResult` contains matches (`Ok` variant), the function prints the number of matched runs
-/// when `quiet` is true. Otherwise, it iterates through each match and prints details in a formatted
-/// manner, using `segment_on_regex` to divide the text into segments. If there's an error (`Err` variant),
-/// the error is printed to standard error.
-fn print_result(
-    result: &SearchResult,
-    re: &Regex,
-    quiet: bool,
-    output_mutex: Arc<Mutex<u32>>,
-    n_context_chars: usize,
-    unmatched_show: bool,
-) {
-    let _output_guard = output_mutex.lock().unwrap();
-    match &result.maybe_result {
-        Ok(runs) => {
-            if quiet {
-                println!("Searched file--> {}\n", result.file_name.bright_red());
-                if !runs.is_empty() {
-                    let runs_len = format!("Matched {} runs", runs.len())
-                        .bright_green()
-                        .on_black();
-                    println!("{runs_len}\n");
-                } else {
-                    let not_found = "No matches found".to_string().bright_red().on_black();
-                    println!("{not_found}\n");
-                }
-            } else {
-                if runs.is_empty() && !unmatched_show {
-                    return;
-                }
-                println!("Searched file--> {}\n", result.file_name.bright_red());
-                for (run_index, run) in runs.iter().enumerate() {
-                    let mtriples = matcher::segment_on_regex(run, re, n_context_chars);
-                    for (match_index, mtriple) in mtriples.iter().enumerate() {
-                        let prompt = format!("{}-{}", run_index + 1, match_index + 1);
-                        println!("  {}-> {}\n", prompt.bright_yellow().on_blue(), mtriple);
-                    }
-                }
-            }
-            println!("===\n");
+/// Will return an error if `read_docx` returns one, or panics.
+fn read_docx_catching_panics(buffer: &[u8]) -> anyhow::Result<Docx> {
+    match std::panic::catch_unwind(|| read_docx(buffer)) {
+        Ok(result) => Ok(result?),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown cause");
+            anyhow::bail!("The document reader panicked: {}", message)
         }
-        Err(e) => eprintln!("{:?}\n", e),
     }
 }
 
-/// Recursively traverse the JSON representation of a DOCX file, extracting all text runs that match
-/// the given regular expression `search_re`.
-///
-/// # Arguments
-///
-/// * `root` - The JSON representation of the DOCX file, as a `serde_json::Value`.
-/// * `search_re` - A reference to the regular expression used to find matching text within the DOCX file.
-///
-/// # Returns
-///
-/// * `Runs` - A vector of text runs that match the regular expression.
-fn xtract_text_from_doctree(root: &Value, search_re: &Regex) -> Runs {
-    let mut queue = VecDeque::new();
-    let mut matching_runs = Vec::new();
-    if let Some(children) = root["document"]["children"].as_array() {
-        for child in children {
-            queue.push_back(child);
-        }
+/// Returns true if `result` is reported whatever the output options: it has matches or could
+/// not be searched, or files without matches are listed as well.
+fn is_reported(result: &SearchResult, opts: &SearchOptions) -> bool {
+    match &result.maybe_result {
+        Ok(parsed) => !parsed.runs.is_empty() || opts.unmatched_show || opts.peek.is_some(),
+        Err(_) => true,
     }
-    while let Some(child) = queue.pop_front() {
-        if child["type"] == "text" {
-            let text = child["data"]["text"].as_str().unwrap();
-            if search_re.is_match(text) {
-                matching_runs.push(text.to_string());
-            }
-        } else if let Some(children) = child["data"]["children"].as_array() {
-            for child in children {
-                queue.push_back(child);
-            }
-        }
+}
+
+/// Cuts `results`, in output order, down to the first `opts.head` or the last `opts.tail` of
+/// the files that are reported (see `is_reported`). The files that are not reported on either
+/// side of them are dropped as well.
+fn limit_reported(results: &mut Vec<SearchResult>, opts: &SearchOptions) {
+    let reported: Vec<usize> = (0..results.len())
+        .filter(|&index| is_reported(&results[index], opts))
+        .collect();
+    if let Some(n) = opts.head {
+        let end = match n.checked_sub(1).and_then(|last| reported.get(last)) {
+            Some(&index) => index + 1,
+            None if n == 0 => 0,
+            None => results.len(),
+        };
+        results.truncate(end);
+    }
+    if let Some(n) = opts.tail {
+        let start = match reported.len().checked_sub(n) {
+            Some(first) if n > 0 => reported[first],
+            Some(_) => results.len(),
+            None => 0,
+        };
+        results.drain(..start);
     }
-    matching_runs
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Drops the runs of `parsed`, and marks it as below the threshold, if they hold fewer than
+/// `min_matches` matches of `searcher` in all. A document without matches is left alone.
+fn apply_min_matches(
+    mut parsed: ParsedDoc,
+    searcher: &Searcher,
+    min_matches: Option<usize>,
+) -> ParsedDoc {
+    let Some(min_matches) = min_matches else {
+        return parsed;
+    };
+    let count: usize = parsed
+        .runs
+        .iter()
+        .map(|run| searcher.find_spans(&run.text).len())
+        .sum();
+    if count > 0 && count < min_matches {
+        parsed.runs.clear();
+        parsed.below_min_matches = true;
+    }
+    parsed
+}
 
-    #[test]
-    fn test_xtract_text_from_doctree() {
-        let data = r#"
-        {
-            "document": {
-                "children": [
-                    {
-                        "type": "text",
-                        "data": {
-                            "text": "Hello, world!"
-                        }
-                    }
-                ]
-            }
+/// Returns the distinct strings that `searcher` matches in `runs`, sorted.
+fn unique_matches(runs: &[Run], searcher: &Searcher) -> BTreeSet<String> {
+    runs.iter()
+        .flat_map(|run| {
+            searcher
+                .find_spans(&run.text)
+                .into_iter()
+                .map(|(start, end)| run.text[start..end].to_string())
+        })
+        .collect()
+}
+
+/// Counts the matches of `searcher` in `runs`, by the index of the paragraph they are in. Runs
+/// whose paragraph is not known are not counted.
+fn paragraph_match_counts(runs: &[Run], searcher: &Searcher) -> HashMap<usize, usize> {
+    let mut counts = HashMap::new();
+    for run in runs.iter().filter(|run| run.para_index > 0) {
+        *counts.entry(run.para_index).or_default() += searcher.find_spans(&run.text).len();
+    }
+    counts
+}
+
+/// Formats, for `--count-per-paragraph`, the `top` paragraphs of `runs` with the most matches
+/// of `searcher`, most first and then in document order, one per line: the paragraph's index,
+/// its number of matches, and its first match with the context that `opts` asks for.
+fn format_paragraph_counts(
+    runs: &[Run],
+    searcher: &Searcher,
+    opts: &SearchOptions,
+    top: usize,
+) -> String {
+    let counts = paragraph_match_counts(runs, searcher);
+    let mut ranked: Vec<(usize, usize)> = counts.into_iter().collect();
+    ranked.sort_by_key(|&(para_index, count)| (Reverse(count), para_index));
+    let mut out = String::new();
+    for (para_index, count) in ranked.into_iter().take(top) {
+        let matchword = if count == 1 { "match" } else { "matches" };
+        let label = format!("para {}: {} {}", para_index, count, matchword);
+        let snippet = runs
+            .iter()
+            .filter(|run| run.para_index == para_index)
+            .flat_map(|run| run.segment(searcher, opts.context_for(&run.text)))
+            .next();
+        match snippet {
+            Some(mtriple) => out.push_str(&format!(
+                "  {} \u{2014} \"{}\"\n",
+                label.bright_cyan(),
+                mtriple
+            )),
+            None => out.push_str(&format!("  {}\n", label.bright_cyan())),
         }
-        "#;
-        let root: Value = serde_json::from_str(data).unwrap();
-        let search_re = Regex::new(r"[Hh]ello").unwrap();
-        let runs = xtract_text_from_doctree(&root, &search_re);
-        assert_eq!(runs.len(), 1);
-        assert_eq!(runs[0], "Hello, world!");
     }
+    out.push('\n');
+    out
+}
 
-    #[test]
-    fn test_zip_entry_name() {
-        let zip_entry = ZipEntry {
-            archive_name: "test.zip".to_string(),
-            entry_name: "test.docx".to_string(),
-        };
+/// Reorders `runs` so that the paragraphs with the most matches come first, and paragraphs with
+/// as many matches in document order. The runs of a paragraph stay together and in order, and
+/// runs whose paragraph is not known are left at the end.
+fn rank_paragraphs(runs: &mut Runs, searcher: &Searcher) {
+    let counts = paragraph_match_counts(runs, searcher);
+    runs.sort_by_key(|run| match counts.get(&run.para_index) {
+        Some(&count) => (false, Reverse(count), run.para_index),
+        None => (true, Reverse(0), 0),
+    });
+}
+
+/// Appends to `runs`, which hold the matches in the body of the document held in `buffer`, the
+/// matches in its other document bodies, such as its glossary, if `opts.all_document_parts` is
+/// set, then those in its SmartArt diagrams and charts if `opts.search_diagrams` is set, then
+/// those in the cells of its embedded spreadsheets if `opts.embedded` is set, then
+/// those in its custom XML data parts if `opts.search_custom_xml` is set, and then those in its
+/// properties if `opts.search_metadata` is set. Nothing more is searched once there is a match
+/// if `opts.first_match_only` is set, and nothing at all if `opts.by_author` is set, since
+/// none of this text has an author.
+fn search_outside_body(buffer: &[u8], searcher: &Searcher, opts: &SearchOptions, runs: &mut Runs) {
+    if opts.by_author.is_some() {
+        return;
+    }
+    if opts.all_document_parts && (runs.is_empty() || !opts.first_match_only) {
+        runs.extend(search_parts(
+            docparts::other_document_texts(buffer),
+            searcher,
+            opts,
+            |text, part| Run {
+                text,
+                source: Some(RunSource::DocumentPart(part.to_string())),
+                ..Default::default()
+            },
+        ));
+    }
+    if opts.search_diagrams && (runs.is_empty() || !opts.first_match_only) {
+        runs.extend(search_parts(
+            diagrams::diagram_texts(buffer),
+            searcher,
+            opts,
+            |text, part| Run {
+                text,
+                source: Some(RunSource::Diagram(part.to_string())),
+                ..Default::default()
+            },
+        ));
+    }
+    if opts.embedded && (runs.is_empty() || !opts.first_match_only) {
+        runs.extend(search_parts(
+            embedded::embedded_xlsx_texts(buffer),
+            searcher,
+            opts,
+            |text, part| Run {
+                text,
+                source: Some(RunSource::Embedded(part.to_string())),
+                ..Default::default()
+            },
+        ));
+    }
+    if opts.search_custom_xml && (runs.is_empty() || !opts.first_match_only) {
+        runs.extend(search_parts(
+            customxml::custom_xml_texts(buffer),
+            searcher,
+            opts,
+            |text, part| Run {
+                text,
+                source: Some(RunSource::CustomXml(part.to_string())),
+                ..Default::default()
+            },
+        ));
+    }
+    if opts.search_metadata && (runs.is_empty() || !opts.first_match_only) {
+        let limit = if opts.first_match_only { 1 } else { usize::MAX };
+        let matching = metadata::read_properties(buffer)
+            .lines()
+            .into_iter()
+            .map(|line| opts.match_text(&line).into_owned())
+            .filter(|line| searcher.is_match(line))
+            .take(limit)
+            .map(|text| Run {
+                text,
+                metadata: true,
+                ..Default::default()
+            });
+        runs.extend(matching);
+    }
+}
+
+/// Appends to `runs` the matches in the bodies of the subdocuments and imported content that
+/// the document `file_like`, held in `buffer`, links to (see `linkeddocs::links`), if
+/// `opts.follow_rels` is set, and in turn those in the documents that they link to. Only links to
+/// parts of the package and to local files are followed, relative ones from the folder of the
+/// linking document, and each linked file is searched at most once, so that documents that
+/// link to each other are not searched over and over. Linked documents that cannot be read or
+/// parsed are skipped. Nothing more is searched once there is a match if
+/// `opts.first_match_only` is set.
+fn search_linked_documents(
+    buffer: &[u8],
+    file_like: &FileLike,
+    searcher: &Searcher,
+    opts: &SearchOptions,
+    runs: &mut Runs,
+) {
+    if !opts.follow_rels {
+        return;
+    }
+    let path = file_like.get_source_path();
+    let mut visited: BTreeSet<PathBuf> = path.canonicalize().into_iter().collect();
+    search_links(
+        buffer,
+        path.parent(),
+        None,
+        searcher,
+        opts,
+        &mut visited,
+        runs,
+    );
+}
+
+/// Appends to `runs` the matches in the documents linked from the document held in `buffer`,
+/// whose relative links are resolved from `dir`, for `search_linked_documents`. `via` is the
+/// label of the links that lead to the document, if it is not the searched one, and `visited`
+/// holds the files already searched.
+fn search_links(
+    buffer: &[u8],
+    dir: Option<&Path>,
+    via: Option<&str>,
+    searcher: &Searcher,
+    opts: &SearchOptions,
+    visited: &mut BTreeSet<PathBuf>,
+    runs: &mut Runs,
+) {
+    for link in linkeddocs::links(buffer) {
+        if opts.first_match_only && !runs.is_empty() {
+            return;
+        }
+        let Some(source) = link.resolve(dir) else {
+            continue;
+        };
+        let linked_dir = match &source {
+            LinkedSource::Part(_) => dir.map(Path::to_path_buf),
+            LinkedSource::File(path) => {
+                let Ok(path) = path.canonicalize() else {
+                    continue;
+                };
+                if !visited.insert(path.clone()) {
+                    continue;
+                }
+                path.parent().map(Path::to_path_buf)
+            }
+        };
+        let Some(linked) = source.read(buffer) else {
+            continue;
+        };
+        let Ok(docx) = read_docx_catching_panics(&linked) else {
+            continue;
+        };
+        let Ok(data) = serde_json::from_str::<Value>(&docx.json()) else {
+            continue;
+        };
+        let label = match via {
+            Some(via) => format!("{} > {}", via, link.label()),
+            None => link.label(),
+        };
+        let (linked_runs, _) = xtract_text_from_doctree(&data, searcher, opts);
+        runs.extend(linked_runs.into_iter().map(|run| Run {
+            source: Some(RunSource::Linked(label.clone())),
+            ..run
+        }));
+        search_links(
+            &linked,
+            linked_dir.as_deref(),
+            Some(&label),
+            searcher,
+            opts,
+            visited,
+            runs,
+        );
+    }
+}
+
+/// Searches the texts of the package parts in `parts`, as pairs of part name and texts, such as
+/// the cells of embedded spreadsheets, returning the matching texts as runs made by `make_run`
+/// from the text and the name of the part it came from.
+fn search_parts(
+    parts: Vec<(String, Vec<String>)>,
+    searcher: &Searcher,
+    opts: &SearchOptions,
+    make_run: impl Fn(String, &str) -> Run,
+) -> Runs {
+    let mut runs = Vec::new();
+    for (part, texts) in parts {
+        for text in texts {
+            let text = opts.match_text(&text).into_owned();
+            if searcher.is_match(&text) {
+                runs.push(make_run(text, &part));
+                if opts.first_match_only {
+                    return runs;
+                }
+            }
+        }
+    }
+    runs
+}
+
+/// Returns the first `n` characters of a document's text, with its paragraphs joined by spaces.
+fn peek_text(paragraphs: &[String], n: usize) -> String {
+    let text = paragraphs.join(" ");
+    crate::first_n_chars!(&text, n).to_string()
+}
+
+/// Hashes the paragraphs of a document's text, so that documents with identical text (whatever
+/// their formatting or file names) hash alike.
+fn hash_text(paragraphs: &[String]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    paragraphs.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Runs `parse_docx` on a separate watchdog thread, giving up after `timeout`.
+///
+/// # Returns
+///
+/// * `Option<anyhow::Result<ParsedDoc>>` - The result of `parse_docx`, or `None` if it did not
+///   finish within `timeout`. A timed-out worker thread is detached and left to finish on its
+///   own.
+fn parse_docx_with_timeout(
+    file_like: &FileLike,
+    searcher: &Searcher,
+    opts: &SearchOptions,
+    timeout: Duration,
+) -> Option<anyhow::Result<ParsedDoc>> {
+    let (tx, rx) = mpsc::channel();
+    let file_like = Arc::clone(file_like);
+    let searcher = searcher.clone();
+    let opts = opts.clone();
+    std::thread::spawn(move || {
+        // the receiver is gone if we already timed out, so a failed send is fine
+        let _ = tx.send(parse_docx(&file_like, &searcher, &opts));
+    });
+    rx.recv_timeout(timeout).ok()
+}
+
+/// Searches a single file, honoring the per-file timeout in `opts` if one is set.
+fn search_file(file_like: &FileLike, searcher: &Searcher, opts: &SearchOptions) -> SearchResult {
+    let file_name = file_like.get_fname();
+    let (maybe_result, timed_out) = match opts.timeout {
+        None => (parse_docx(file_like, searcher, opts), false),
+        Some(timeout) => match parse_docx_with_timeout(file_like, searcher, opts, timeout) {
+            Some(result) => (result, false),
+            None => (
+                Err(anyhow::anyhow!(
+                    "Timed out after {}s searching {}",
+                    timeout.as_secs_f64(),
+                    file_name.bright_red().on_black()
+                )),
+                true,
+            ),
+        },
+    };
+    let size = if opts.verbose {
+        file_like.get_size().ok()
+    } else {
+        None
+    };
+    SearchResult {
+        file_name,
+        archive_name: file_like.get_archive_name(),
+        source_path: file_like.get_source_path(),
+        maybe_result,
+        timed_out,
+        size,
+    }
+}
+
+/// Returns the modification time of the file `fname`.
+pub(crate) fn modified_time(fname: &str) -> anyhow::Result<SystemTime> {
+    std::fs::metadata(fname)
+        .and_then(|metadata| metadata.modified())
+        .with_context(|| format!("Failed to read modification time: {}", fname))
+}
+
+/// Returns true if `file_like` is no larger than `limit` bytes, or if its size is not known
+/// before it is read. Larger files are reported on standard error as skipped.
+fn within_size_limit(file_like: &FileLike, limit: u64) -> bool {
+    match file_like.get_size() {
+        Ok(size) if size.bytes() > limit => {
+            eprintln!(
+                "Skipping {}: {} bytes exceeds --max-file-size {}\n",
+                file_like.get_fname().bright_red().on_black(),
+                size.bytes(),
+                limit
+            );
+            false
+        }
+        _ => true,
+    }
+}
+
+/// The documents found under a base directory, and the files they were found in.
+struct Documents {
+    /// Names of the loose .docx and .docx.zst files, and of those read by registered readers
+    docx_fnames: Vec<String>,
+    /// Names of the zip archives
+    zip_fnames: Vec<String>,
+    /// Names of the .tar.zst archives
+    tar_fnames: Vec<String>,
+    /// The documents to search, archive entries included
+    file_surrogates: Vec<FileLike>,
+}
+
+/// Lists the documents under `base_dir`, or the single document at its URL, that `opts` lets
+/// be searched: the files with the default extensions (see `FileFormat::DEFAULTS`) and with those
+/// of `opts.include_ext`, each read in the format of the last of those extensions that its
+/// name ends with. Zip and tar archives are left out if `opts.no_archives` is set, and loose
+/// files if `opts.archives_only` is, and so are documents larger than `opts.max_file_size` or
+/// last modified before `opts.since`. Files with an extension registered in
+/// `opts.custom_readers` are listed as loose files, with the readers their factories make.
+///
+/// # Errors
+///
+/// Will return an error if the files under `base_dir` cannot be listed, or an archive cannot
+/// be opened.
+fn list_documents(base_dir: &str, opts: &SearchOptions) -> anyhow::Result<Documents> {
+    let (docx_fnames, zip_fnames, zst_fnames, tar_fnames) = if urlfile::is_url(base_dir) {
+        (
+            vec![base_dir.to_string()],
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+        )
+    } else {
+        let extensions: Vec<(&str, FileFormat)> = FileFormat::DEFAULTS
+            .into_iter()
+            .chain(
+                opts.include_ext
+                    .iter()
+                    .map(|included| (included.extension.as_str(), included.format)),
+            )
+            .collect();
+        // a file is read in the format of the last extension it ends with
+        let format_of = |fname: &str| {
+            extensions
+                .iter()
+                .rev()
+                .find(|(extension, _)| fname.ends_with(extension))
+                .map(|&(_, format)| format)
+        };
+        let fnames = |format: FileFormat| -> anyhow::Result<Vec<String>> {
+            let wanted = if format.is_archive() {
+                !opts.no_archives
+            } else {
+                !opts.archives_only
+            };
+            let mut fnames = Vec::new();
+            if !wanted {
+                return Ok(fnames);
+            }
+            let mut listed = BTreeSet::new();
+            for (extension, _) in extensions.iter().filter(|&&(_, f)| f == format) {
+                for fname in make_fnames(base_dir, extension)?.fnames {
+                    if format_of(&fname) == Some(format) && listed.insert(fname.clone()) {
+                        fnames.push(fname);
+                    }
+                }
+            }
+            Ok(fnames)
+        };
+        (
+            fnames(FileFormat::Docx)?,
+            fnames(FileFormat::Zip)?,
+            fnames(FileFormat::DocxZst)?,
+            fnames(FileFormat::TarZst)?,
+        )
+    };
+    let mut custom_fnames = Vec::new();
+    if !urlfile::is_url(base_dir) && !opts.archives_only {
+        for (extension, _) in &opts.custom_readers.0 {
+            custom_fnames.extend(make_fnames(base_dir, extension)?.fnames);
+        }
+    }
+    custom_fnames.sort();
+    custom_fnames.dedup();
+    // each file is read by a registered reader if one claims it, and only by that reader
+    let unclaimed = |fnames: Vec<String>| -> Vec<String> {
+        fnames
+            .into_iter()
+            .filter(|fname| opts.custom_readers.factory(fname).is_none())
+            .collect()
+    };
+    let (docx_fnames, zip_fnames, zst_fnames, tar_fnames) = (
+        unclaimed(docx_fnames),
+        unclaimed(zip_fnames),
+        unclaimed(zst_fnames),
+        unclaimed(tar_fnames),
+    );
+    let mut file_surrogates: Vec<FileLike> = Vec::new();
+    for fname in &docx_fnames {
+        if urlfile::is_url(fname) {
+            file_surrogates.push(urlfile::url_surrogate(fname, opts.max_file_size)?);
+        } else {
+            file_surrogates.push(Arc::new(RegularFile {
+                fname: fname.clone(),
+            }));
+        }
+    }
+    for zip_fname in &zip_fnames {
+        let zipentries = zip_to_zipentries(zip_fname)?;
+        for ze in zipentries {
+            file_surrogates.push(Arc::new(ze));
+        }
+    }
+    for zst_fname in &zst_fnames {
+        file_surrogates.extend(zstfile::zst_surrogates(zst_fname, false)?);
+    }
+    for tar_fname in &tar_fnames {
+        file_surrogates.extend(zstfile::zst_surrogates(tar_fname, true)?);
+    }
+    for fname in &custom_fnames {
+        if let Some(factory) = opts.custom_readers.factory(fname) {
+            file_surrogates.push(Arc::from(factory(fname)));
+        }
+    }
+    if let Some(limit) = opts.max_file_size {
+        file_surrogates.retain(|file_like| within_size_limit(file_like, limit));
+    }
+    if let Some(since) = &opts.since {
+        // a document whose modification time is unknown is searched anyway
+        file_surrogates.retain(|file_like| {
+            file_like
+                .get_modified()
+                .map_or(true, |modified| since.admits(modified))
+        });
+    }
+    Ok(Documents {
+        docx_fnames: docx_fnames
+            .into_iter()
+            .chain(zst_fnames)
+            .chain(custom_fnames)
+            .collect(),
+        zip_fnames,
+        tar_fnames,
+        file_surrogates,
+    })
+}
+
+/// Processes files matching the given glob pattern, searching for text that matches the
+/// specified regular expression, and printing the results.
+///
+/// # Arguments
+///
+/// * `base_dir` - A glob base_dir to match files, or the `http://` URL of a single document.
+/// * `searcher` - The `Searcher` used to search for matching text within each file.
+/// * `opts` - The `SearchOptions` controlling the search and the output, which choose the
+///   documents that are searched, how many at once, and which results are printed (see the
+///   fields of `SearchOptions`).
+///
+/// # Returns
+///
+/// * `anyhow::Result<ScanSummary>` - The counts of files searched, matched and errored, or an
+///   error if the files to search could not be listed. If `opts.fail_fast` is set, the first
+///   document that cannot be searched cancels the rest of the scan and its error is returned.
+pub fn process_files(
+    base_dir: &str,
+    searcher: &Searcher,
+    opts: &SearchOptions,
+) -> anyhow::Result<ScanSummary> {
+    let started = Instant::now();
+    // output mutex
+    let output_mutex = Arc::new(Mutex::new(0));
+    let Documents {
+        docx_fnames,
+        zip_fnames,
+        tar_fnames,
+        mut file_surrogates,
+    } = list_documents(base_dir, opts)?;
+    let manifest = match &opts.resume {
+        Some(path) => {
+            let mut manifest = Manifest::open(path)?;
+            manifest.retain_pending(&mut file_surrogates);
+            Some(manifest)
+        }
+        None => None,
+    };
+
+    let summary = Mutex::new(ScanSummary {
+        docx_fnames,
+        zip_fnames,
+        tar_fnames,
+        extensions: opts.searched_extensions(),
+        unique_matches: (opts.count_unique == Some(UniqueScope::Corpus)).then(BTreeSet::new),
+        ..Default::default()
+    });
+    // on a terminal the results themselves show how the scan is going
+    let progress = Arc::new(Progress::default());
+    let reporter = opts
+        .progress_interval
+        .filter(|_| !std::io::stdout().is_terminal())
+        .map(|interval| {
+            ProgressReporter::start(
+                interval,
+                file_surrogates.len(),
+                progress.clone(),
+                std::io::stderr(),
+            )
+        });
+    let record = |search_result: &SearchResult| {
+        summary.lock().unwrap().record(search_result, searcher);
+        if reporter.is_some() {
+            let matches = match &search_result.maybe_result {
+                Ok(parsed) => parsed
+                    .runs
+                    .iter()
+                    .map(|run| searcher.find_spans(&run.text).len())
+                    .sum(),
+                Err(_) => 0,
+            };
+            progress.record(matches);
+        }
+    };
+    // a document goes into the manifest only once its results have been written out
+    let mark_searched = |search_result: &SearchResult| {
+        if let Some(manifest) = &manifest {
+            if let Err(e) = manifest.record(search_result) {
+                eprintln!("{:?}\n", e);
+            }
+        }
+    };
+    // set to skip the files not yet searched, by --fail-fast on an error (which is then kept in
+    // first_error) or once --max-results matches have been found
+    let cancelled = AtomicBool::new(false);
+    let first_error: Mutex<Option<anyhow::Error>> = Mutex::new(None);
+    // bounds how many documents are read and parsed at once, whatever the size of the pool
+    let semaphore = opts.parallel_files.map(Semaphore::new);
+    // fewer, larger pieces of work cost less to hand out when each document is quick to search
+    let min_len = opts.chunk_size.map_or(1, NonZeroUsize::get);
+    // the number of matches output so far, counted with `opts.max_results`
+    let matches_taken = AtomicUsize::new(0);
+    let search = |file_like: &FileLike| -> Option<SearchResult> {
+        if cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+        let permit = semaphore.as_ref().map(Semaphore::acquire);
+        let result = search_file(file_like, searcher, opts);
+        drop(permit);
+        match result.maybe_result {
+            Err(e) if opts.fail_fast => {
+                cancelled.store(true, Ordering::Relaxed);
+                first_error.lock().unwrap().get_or_insert(e);
+                None
+            }
+            maybe_result => {
+                let maybe_result = maybe_result
+                    .map(|parsed| apply_min_matches(parsed, searcher, opts.min_matches));
+                let maybe_result = match (opts.max_results, maybe_result) {
+                    (Some(max), Ok(parsed)) => Ok(take_matches(
+                        parsed,
+                        searcher,
+                        &matches_taken,
+                        max,
+                        &cancelled,
+                    )?),
+                    (_, maybe_result) => maybe_result,
+                };
+                Some(SearchResult {
+                    maybe_result,
+                    ..result
+                })
+            }
+        }
+    };
+    // browsing needs someone at the keyboard; otherwise print as usual
+    let interactive = opts.interactive && std::io::stdin().is_terminal();
+    let limited = opts.head.is_some() || opts.tail.is_some();
+    if opts.group_by_archive || opts.html.is_some() || opts.json || interactive || limited {
+        let mut results: Vec<SearchResult> = file_surrogates
+            .par_iter()
+            .with_min_len(min_len)
+            .filter_map(search)
+            .inspect(record)
+            .collect();
+        if let Some(e) = first_error.into_inner().unwrap() {
+            return Err(e);
+        }
+        // the summary has already counted every file
+        limit_reported(&mut results, opts);
+        if let Some(path) = &opts.html {
+            html::write_report(path, &results, searcher, opts)?;
+        }
+        if opts.json {
+            jsonout::write_results(std::io::stdout().lock(), &results, searcher, opts)?;
+            results.iter().for_each(mark_searched);
+        } else if interactive {
+            interactive::browse(
+                &results,
+                searcher,
+                opts,
+                std::io::stdin().lock(),
+                std::io::stdout(),
+            )?;
+            results.iter().for_each(mark_searched);
+        } else if opts.group_by_archive {
+            print_grouped_results(results, searcher, opts, mark_searched);
+        } else {
+            for result in &results {
+                print_result(result, searcher, output_mutex.clone(), opts);
+                mark_searched(result);
+            }
+        }
+    } else if opts.ordered {
+        // each result waits here until those of all the files before it have been printed
+        let reorder = Mutex::new(ReorderBuffer::default());
+        file_surrogates
+            .par_iter()
+            .with_min_len(min_len)
+            .enumerate()
+            .for_each(|(index, file_like)| {
+                let search_result = search(file_like);
+                if let Some(search_result) = &search_result {
+                    record(search_result);
+                }
+                let mut reorder = reorder.lock().unwrap();
+                for search_result in reorder.push(index, search_result).into_iter().flatten() {
+                    print_result(&search_result, searcher, output_mutex.clone(), opts);
+                    mark_searched(&search_result);
+                }
+            });
+        if let Some(e) = first_error.into_inner().unwrap() {
+            return Err(e);
+        }
+    } else {
+        file_surrogates
+            .par_iter()
+            .with_min_len(min_len)
+            .filter_map(search)
+            .for_each(|search_result| {
+                record(&search_result);
+                print_result(&search_result, searcher, output_mutex.clone(), opts);
+                mark_searched(&search_result);
+            });
+        if let Some(e) = first_error.into_inner().unwrap() {
+            return Err(e);
+        }
+    }
+    let mut summary = summary.into_inner().unwrap();
+    summary.elapsed = started.elapsed();
+    Ok(summary)
+}
+
+/// Searches the documents under `base_dir` as `process_files` does, but returns the results,
+/// in the order the documents were found, instead of printing them. Only the options that
+/// decide which documents are searched and what is found in them are used, not those that
+/// decide how the results are output or `opts.resume`.
+///
+/// # Errors
+///
+/// Will return an error if the files to search could not be listed, or, if `opts.fail_fast`
+/// is set, the error of the first document that could not be searched.
+pub fn search_documents(
+    base_dir: &str,
+    searcher: &Searcher,
+    opts: &SearchOptions,
+) -> anyhow::Result<Vec<SearchResult>> {
+    let documents = list_documents(base_dir, opts)?;
+    let mut results: Vec<SearchResult> = documents
+        .file_surrogates
+        .par_iter()
+        .map(|file_like| {
+            let result = search_file(file_like, searcher, opts);
+            SearchResult {
+                maybe_result: result
+                    .maybe_result
+                    .map(|parsed| apply_min_matches(parsed, searcher, opts.min_matches)),
+                ..result
+            }
+        })
+        .collect();
+    if opts.fail_fast {
+        if let Some(position) = results.iter().position(|r| r.maybe_result.is_err()) {
+            results.swap_remove(position).maybe_result?;
+        }
+    }
+    Ok(results)
+}
+
+/// Counts the matches of `parsed` into `taken`, the number of matches taken so far by a scan
+/// limited to `max` of them with `--max-results`, and returns it with its runs cut short so
+/// that no more than `max` are taken in all, or `None` if `max` had been reached before. Once
+/// it is reached, `cancelled` is set so that no more documents are searched. A run is never
+/// cut in two, so the last one kept may take the count past `max`.
+fn take_matches(
+    mut parsed: ParsedDoc,
+    searcher: &Searcher,
+    taken: &AtomicUsize,
+    max: usize,
+    cancelled: &AtomicBool,
+) -> Option<ParsedDoc> {
+    let counts: Vec<usize> = parsed
+        .runs
+        .iter()
+        .map(|run| searcher.find_spans(&run.text).len())
+        .collect();
+    let total: usize = counts.iter().sum();
+    let before = taken.fetch_add(total, Ordering::Relaxed);
+    if before >= max {
+        cancelled.store(true, Ordering::Relaxed);
+        return None;
+    }
+    if before + total >= max {
+        cancelled.store(true, Ordering::Relaxed);
+        let mut count = before;
+        let kept = counts
+            .into_iter()
+            .take_while(|&n| {
+                let take = count < max;
+                count += n;
+                take
+            })
+            .count();
+        parsed.runs.truncate(kept);
+    }
+    Some(parsed)
+}
+
+/// Puts items that arrive in any order back in the order of their indices, counting from 0, by
+/// holding each back until all those before it have arrived.
+#[derive(Debug)]
+struct ReorderBuffer<T> {
+    /// The index of the next item to release
+    next: usize,
+    /// The items that arrived before their turn, by index
+    pending: BTreeMap<usize, T>,
+}
+
+impl<T> Default for ReorderBuffer<T> {
+    fn default() -> Self {
+        ReorderBuffer {
+            next: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> ReorderBuffer<T> {
+    /// Adds the item at `index`, and returns, in order, the items whose turn has now come: none
+    /// if an earlier one is still missing.
+    fn push(&mut self, index: usize, item: T) -> Vec<T> {
+        self.pending.insert(index, item);
+        let mut ready = Vec::new();
+        while let Some(item) = self.pending.remove(&self.next) {
+            ready.push(item);
+            self.next += 1;
+        }
+        ready
+    }
+}
+
+impl ScanSummary {
+    /// Adds the outcome of searching a single document to the summary.
+    fn record(&mut self, result: &SearchResult, searcher: &Searcher) {
+        self.files_searched += 1;
+        let directory = result
+            .source_path
+            .parent()
+            .map(|parent| parent.display().to_string())
+            .unwrap_or_default();
+        let coverage = self.coverage.entry(directory).or_default();
+        coverage.1 += 1;
+        match &result.maybe_result {
+            Ok(parsed) => {
+                if !parsed.runs.is_empty() {
+                    self.files_matched += 1;
+                    coverage.0 += 1;
+                }
+                if parsed.below_min_matches {
+                    self.files_below_min_matches += 1;
+                }
+                if parsed.has_no_text() {
+                    self.without_text.push(result.file_name.clone());
+                }
+                self.bytes_scanned += parsed.bytes_read;
+                self.total_matches += parsed
+                    .runs
+                    .iter()
+                    .map(|run| searcher.find_spans(&run.text).len())
+                    .sum::<usize>();
+                if let Some(unique) = &mut self.unique_matches {
+                    unique.extend(unique_matches(&parsed.runs, searcher));
+                }
+                if let Some(hash) = parsed.content_hash {
+                    self.content_groups
+                        .entry(hash)
+                        .or_default()
+                        .push(result.file_name.clone());
+                }
+            }
+            Err(_) => self.files_errored += 1,
+        }
+        if result.timed_out {
+            self.timed_out.push(result.file_name.clone());
+        }
+    }
+}
+
+/// Prints the search results for a DOCX file, highlighting matches of a regular expression.
+///
+/// # Arguments
+///
+/// * `result` - A reference to a `SearchResult` struct containing the file name and potential matches.
+/// * `searcher` - A reference to the `Searcher` used for identifying matches in the text runs.
+/// * `opts` - The `SearchOptions` for this search.
+///
+/// # Behavior
+///
+/// If a `SearchResult` contains matches (`Ok` variant), the output of `format_result` is printed
+/// to standard output. If there's an error (`Err` variant), the error is printed to standard error.
+/// If `opts.line_buffered` is set, standard output is flushed after each file.
+fn print_result(
+    result: &SearchResult,
+    searcher: &Searcher,
+    output_mutex: Arc<Mutex<u32>>,
+    opts: &SearchOptions,
+) {
+    let _output_guard = output_mutex.lock().unwrap();
+    match &result.maybe_result {
+        Ok(parsed) => print!("{}", format_result(result, parsed, searcher, opts)),
+        Err(e) => eprintln!("{:?}\n", e),
+    }
+    if opts.line_buffered {
+        let _ = std::io::stdout().flush();
+    }
+}
+
+/// Formats the matched runs of a `SearchResult` for display.
+///
+/// Each match is formatted with its details, using `segment_on_regex` to divide the text into
+/// segments, unless the fields of `opts` ask for a count, a template or a list of paragraphs
+/// instead. Files without matches produce no output unless `opts.quiet`, `opts.unmatched_show`
+/// or `opts.peek` is set; those with no text at all (see `ParsedDoc::has_no_text`) are then
+/// reported as such. The output ends with the line given by `opts.group_separator()`, if any,
+/// and a blank line.
+pub(crate) fn format_result(
+    result: &SearchResult,
+    parsed: &ParsedDoc,
+    searcher: &Searcher,
+    opts: &SearchOptions,
+) -> String {
+    if let Some(template) = &opts.format {
+        return format_templated(result, parsed, searcher, opts, template);
+    }
+    let mut out = String::new();
+    let runs = &parsed.runs;
+    if runs.is_empty() && !opts.quiet && !opts.unmatched_show && opts.peek.is_none() {
+        return out;
+    }
+    out.push_str(&format_file_header(result, parsed));
+    if runs.is_empty() && parsed.has_no_text() {
+        let label = "(no extractable text — possibly image-only)";
+        out.push_str(&format!("{}\n\n", label.yellow().on_black()));
+    } else if opts.quiet || opts.first_match_only {
+        if !runs.is_empty() {
+            let found = if opts.first_match_only {
+                "Match found".to_string()
+            } else {
+                format!("Matched {} runs", runs.len())
+            };
+            out.push_str(&format!("{}\n\n", found.bright_green().on_black()));
+        } else {
+            let not_found = "No matches found".to_string().bright_red().on_black();
+            out.push_str(&format!("{not_found}\n\n"));
+        }
+    } else if let Some(top) = opts.count_per_paragraph {
+        out.push_str(&format_paragraph_counts(runs, searcher, opts, top));
+    } else {
+        let para_counts = if opts.rank_paragraphs {
+            paragraph_match_counts(runs, searcher)
+        } else {
+            HashMap::new()
+        };
+        // the number of the match in the whole document, as shown unless `opts.compound_index`
+        let mut match_number = 0;
+        for (run_index, run) in runs.iter().enumerate() {
+            let first_in_paragraph =
+                run_index == 0 || runs[run_index - 1].para_index != run.para_index;
+            if let (true, Some(count)) = (first_in_paragraph, para_counts.get(&run.para_index)) {
+                let matchword = if *count == 1 { "match" } else { "matches" };
+                let label = format!("paragraph {}: {} {}", run.para_index, count, matchword);
+                out.push_str(&format!("  {}\n", label.bright_cyan()));
+            }
+            for paragraph in &run.para_before {
+                out.push_str(&format!("    {}\n", paragraph.dimmed()));
+            }
+            let mut mtriples = run.segment(searcher, opts.context_for(&run.text));
+            if opts.trim {
+                mtriples.iter_mut().for_each(MatchTriple::trim_ends);
+            }
+            if opts.escape_output {
+                mtriples.iter_mut().for_each(MatchTriple::escape);
+            }
+            if let Some(width) = opts.align {
+                mtriples.iter_mut().for_each(|mtriple| mtriple.align(width));
+            }
+            let positions = if opts.source_positions {
+                run.source_positions(searcher)
+            } else {
+                Vec::new()
+            };
+            for (match_index, mtriple) in mtriples.iter_mut().enumerate() {
+                match_number += 1;
+                let prompt = if opts.compound_index {
+                    format!("{}-{}", run_index + 1, match_index + 1)
+                } else {
+                    match_number.to_string()
+                };
+                if let Some(width) = opts.center {
+                    // leave room for the "  N-> " in front
+                    mtriple.center(width.saturating_sub(prompt.len() + 5));
+                }
+                let mtriple = &*mtriple;
+                out.push_str(&format!(
+                    "  {}-> {}\n",
+                    prompt.bright_yellow().on_blue(),
+                    mtriple
+                ));
+                if let Some(sentence) = run.sentences.get(match_index) {
+                    let (start, end) = sentence.span;
+                    let mut in_sentence = MatchTriple {
+                        pre: sentence.text[..start].to_string(),
+                        matched: sentence.text[start..end].to_string(),
+                        post: sentence.text[end..].to_string(),
+                        alternative: None,
+                        alternative_index: mtriple.alternative_index,
+                        run_len: sentence.text.chars().count(),
+                        pre_truncated: false,
+                        post_truncated: false,
+                    };
+                    if opts.escape_output {
+                        in_sentence.escape();
+                    }
+                    out.push_str(&format!(
+                        "      {} {}\n",
+                        format!("[sentence {}]", sentence.number).bright_cyan(),
+                        in_sentence
+                    ));
+                }
+                if let (true, Some(heading)) = (opts.show_heading, &run.heading) {
+                    out.push_str(&format!("      under \"{}\"\n", heading.bright_cyan()));
+                }
+                if opts.show_formatting && !run.formatting.is_empty() {
+                    let markers: Vec<String> = run
+                        .formatting
+                        .iter()
+                        .map(|name| format!("[{}]", name))
+                        .collect();
+                    out.push_str(&format!("      {}\n", markers.join(" ").bright_cyan()));
+                }
+                if run.metadata {
+                    out.push_str("      (metadata)\n");
+                }
+                if run.text_box {
+                    out.push_str("      (text box)\n");
+                }
+                if let Some(source) = &run.source {
+                    out.push_str(&format!("      ({})\n", source.label()));
+                }
+                if let (Some(author), Some(_)) = (&run.author, &opts.by_author) {
+                    let kind = if run.comment { "comment" } else { "inserted" };
+                    out.push_str(&format!("      ({} by {})\n", kind, author.bright_cyan()));
+                }
+                if let Some(alternative) = &mtriple.alternative {
+                    out.push_str(&format!(
+                        "      matched /{}/\n",
+                        alternative.bright_magenta()
+                    ));
+                }
+                if let Some(Some((element, offset))) = positions.get(match_index) {
+                    out.push_str(&format!("      at w:t #{}, char {}\n", element, offset));
+                }
+                out.push('\n');
+            }
+            for paragraph in &run.para_after {
+                out.push_str(&format!("    {}\n", paragraph.dimmed()));
+            }
+            if !run.para_after.is_empty() {
+                out.push('\n');
+            }
+        }
+    }
+    if opts.count_unique == Some(UniqueScope::File) && !runs.is_empty() {
+        let unique = unique_matches(runs, searcher);
+        let label = format!("{} unique matches", unique.len());
+        out.push_str(&format!("  {}\n", label.bright_cyan()));
+        if opts.list_unique {
+            for text in &unique {
+                out.push_str(&format!("    {}\n", text));
+            }
+        }
+        out.push('\n');
+    }
+    if let Some(separator) = opts.group_separator() {
+        out.push_str(&format!("{}\n\n", separator));
+    }
+    out
+}
+
+/// Formats the matches of a `SearchResult` one per line, by filling in `template` with the
+/// fields of each match. There is no header, colour or separator, so that the output is easy
+/// for other programs to parse.
+fn format_templated(
+    result: &SearchResult,
+    parsed: &ParsedDoc,
+    searcher: &Searcher,
+    opts: &SearchOptions,
+    template: &Template,
+) -> String {
+    let file = result.source_path.display().to_string();
+    let archive = result.archive_name.as_deref().unwrap_or_default();
+    let mut out = String::new();
+    let mut count = 0;
+    for run in &parsed.runs {
+        for mtriple in run.segment(searcher, opts.context_for(&run.text)) {
+            count += 1;
+            out.push_str(&template.expand(&MatchFields {
+                file: &file,
+                archive,
+                para: run.para_index,
+                pre: &mtriple.pre,
+                matched: &mtriple.matched,
+                post: &mtriple.post,
+                count,
+            }));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Formats the "Searched file" header for a result, followed by its size and the beginning of
+/// its text if they were requested. Results salvaged by the raw XML fallback are labelled as
+/// such.
+fn format_file_header(result: &SearchResult, parsed: &ParsedDoc) -> String {
+    let label = if parsed.raw_fallback {
+        " (raw fallback)"
+    } else {
+        ""
+    };
+    let mut header = format!(
+        "Searched file--> {}{}\n\n",
+        result.file_name.bright_red(),
+        label
+    );
+    if let Some(size) = &result.size {
+        header.push_str(&format!("  {}\n\n", size));
+    }
+    if let Some(peek) = &parsed.peek {
+        header.push_str(&format!("  {} {}\n\n", "peek:".dimmed(), peek.dimmed()));
+    }
+    header
+}
+
+/// Groups search results by the zip archive they came from. Results for regular files are
+/// gathered under a "loose files" group, which comes first; archive groups follow in name order.
+/// Within each group, results keep their original order.
+fn group_by_archive(results: Vec<SearchResult>) -> Vec<(String, Vec<SearchResult>)> {
+    let mut loose = Vec::new();
+    let mut archives: BTreeMap<String, Vec<SearchResult>> = BTreeMap::new();
+    for result in results {
+        match &result.archive_name {
+            Some(archive_name) => archives
+                .entry(archive_name.clone())
+                .or_default()
+                .push(result),
+            None => loose.push(result),
+        }
+    }
+    let mut groups = Vec::new();
+    if !loose.is_empty() {
+        groups.push((LOOSE_FILES.to_string(), loose));
+    }
+    groups.extend(archives);
+    groups
+}
+
+/// Prints buffered search results grouped under the name of their archive, with each
+/// entry's output indented beneath it, and passes each result to `printed` once it is out.
+fn print_grouped_results(
+    results: Vec<SearchResult>,
+    searcher: &Searcher,
+    opts: &SearchOptions,
+    printed: impl Fn(&SearchResult),
+) {
+    for (group_name, group) in group_by_archive(results) {
+        println!("Archive--> {}\n", group_name.bright_blue());
+        for result in &group {
+            match &result.maybe_result {
+                Ok(parsed) => {
+                    for line in format_result(result, parsed, searcher, opts).lines() {
+                        if line.is_empty() {
+                            println!();
+                        } else {
+                            println!("    {}", line);
+                        }
+                    }
+                }
+                Err(e) => eprintln!("{:?}\n", e),
+            }
+            printed(result);
+        }
+    }
+}
+
+/// Where a run sits in the document, as tracked by `walk_runs`.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct RunLocation {
+    /// 1-based index of the paragraph containing the run
+    pub(crate) para_index: usize,
+    /// Text of the closest heading paragraph at or before the run
+    pub(crate) heading: Option<String>,
+    /// 0-based index of the run's `<w:t>` element among those of the body, or `None` for a
+    /// table row visited as a whole
+    pub(crate) element_index: Option<usize>,
+    /// Direct formatting of the run, as given by `run_formatting`
+    pub(crate) formatting: Vec<&'static str>,
+    /// True if the run is inside a text box or shape
+    pub(crate) text_box: bool,
+    /// The author of the tracked insertion the run is in, if it is in one
+    pub(crate) author: Option<String>,
+}
+
+/// An inclusive, 1-based range of paragraphs to search, parsed from `START:END`. Either end may
+/// be omitted to leave the range open on that side; a `START` of 0 is treated as 1, and an `END`
+/// beyond the last paragraph simply runs to the end of the document.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParaRange {
+    start: usize,
+    end: usize,
+}
+
+impl ParaRange {
+    fn contains(&self, para_index: usize) -> bool {
+        self.start <= para_index && para_index <= self.end
+    }
+}
+
+impl FromStr for ParaRange {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> anyhow::Result<Self> {
+        let invalid = || format!("Invalid paragraph range '{}': expected START:END", spec);
+        let (start, end) = spec.split_once(':').with_context(invalid)?;
+        let start = match start.trim() {
+            "" => 1,
+            n => n.parse::<usize>().with_context(invalid)?.max(1),
+        };
+        let end = match end.trim() {
+            "" => usize::MAX,
+            n => n.parse::<usize>().with_context(invalid)?,
+        };
+        if start > end {
+            anyhow::bail!("Invalid paragraph range '{}': START is after END", spec);
+        }
+        Ok(ParaRange { start, end })
+    }
+}
+
+/// Returns true if `paragraph` uses one of the built-in heading styles (`Heading1` to
+/// `Heading9`, or `Title`).
+fn is_heading(paragraph: &Value) -> bool {
+    paragraph["data"]["property"]["style"]
+        .as_str()
+        .is_some_and(|style| style.starts_with("Heading") || style == "Title")
+}
+
+/// Returns the nodes directly below `node`: its `data.children`, or the rows of a table, or the
+/// cells of a table row, or the text box of a drawing.
+fn child_nodes(node: &Value) -> &[Value] {
+    let data = &node["data"];
+    if node["type"] == "drawing" && data["type"] == "textBox" {
+        return std::slice::from_ref(data);
+    }
+    ["children", "rows", "cells"]
+        .iter()
+        .find_map(|key| data[key].as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+/// Returns the names of the kinds of direct formatting that the run node `run` has: any of
+/// "bold", "italic", "underline" and "strike", in that order. Formatting that the run only
+/// inherits from a paragraph or character style is not included.
+fn run_formatting(run: &Value) -> Vec<&'static str> {
+    let properties = &run["data"]["runProperty"];
+    let mut formatting = Vec::new();
+    for (key, name) in [("bold", "bold"), ("italic", "italic")] {
+        if properties[key] == true {
+            formatting.push(name);
+        }
+    }
+    if properties["underline"]
+        .as_str()
+        .is_some_and(|underline| underline != "none")
+    {
+        formatting.push("underline");
+    }
+    if properties["strike"] == true {
+        formatting.push("strike");
+    }
+    formatting
+}
+
+/// Concatenates the text of all the runs below `node`.
+fn node_text(node: &Value) -> String {
+    if node["type"] == "text" {
+        return node["data"]["text"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+    }
+    child_nodes(node).iter().map(node_text).collect()
+}
+
+/// Returns the text of each paragraph of the comments on the document whose JSON
+/// representation is `root`, in document order, with the index of the body paragraph where the
+/// commented range starts and the author of the comment. Paragraphs without text are left out.
+fn xtract_comments(root: &Value) -> Vec<(usize, String, String)> {
+    /// Walks `node`, which is in a text box if `in_text_box` is set.
+    fn walk(
+        node: &Value,
+        in_text_box: bool,
+        para_index: &mut usize,
+        comments: &mut Vec<(usize, String, String)>,
+    ) {
+        // the paragraphs of a text box are not counted (see `walk_runs`)
+        if node["type"] == "paragraph" && !in_text_box {
+            *para_index += 1;
+        }
+        if node["type"] == "commentRangeStart" {
+            let comment = &node["data"]["comment"];
+            let author = comment["author"].as_str().unwrap_or_default();
+            for paragraph in comment["children"].as_array().into_iter().flatten() {
+                let text = node_text(paragraph);
+                if !text.is_empty() {
+                    comments.push((*para_index, author.to_string(), text));
+                }
+            }
+        }
+        let in_text_box = in_text_box || node["type"] == "textBox";
+        for child in child_nodes(node) {
+            walk(child, in_text_box, para_index, comments);
+        }
+    }
+    let mut comments = Vec::new();
+    let mut para_index = 0;
+    for child in root["document"]["children"]
+        .as_array()
+        .into_iter()
+        .flatten()
+    {
+        walk(child, false, &mut para_index, &mut comments);
+    }
+    comments
+}
+
+/// Searches the comments on the document whose JSON representation is `root` (see
+/// `xtract_comments`) that are by `opts.by_author`, returning each matching paragraph of them
+/// as a run marked as a comment, at the paragraph where the commented range starts.
+fn search_comments(root: &Value, searcher: &Searcher, opts: &SearchOptions) -> Runs {
+    let limit = if opts.first_match_only { 1 } else { usize::MAX };
+    xtract_comments(root)
+        .into_iter()
+        .filter(|(_, author, _)| opts.is_by_author(Some(author)))
+        .map(|(para_index, author, text)| (para_index, author, opts.match_text(&text).into_owned()))
+        .filter(|(_, _, text)| searcher.is_match(text))
+        .take(limit)
+        .map(|(para_index, author, text)| Run {
+            text,
+            para_index,
+            author: Some(author),
+            comment: true,
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Separates the text of the cells of a table row searched with `--table-row-context`.
+const CELL_SEPARATOR: &str = " | ";
+
+/// Returns the text of each cell of the table row `row`, joined by `CELL_SEPARATOR`.
+fn row_text(row: &Value) -> String {
+    child_nodes(row)
+        .iter()
+        .map(node_text)
+        .collect::<Vec<_>>()
+        .join(CELL_SEPARATOR)
+}
+
+/// Counts the nodes of type `node_type` below `node`.
+fn count_nodes(node: &Value, node_type: &str) -> usize {
+    child_nodes(node)
+        .iter()
+        .map(|child| usize::from(child["type"] == node_type) + count_nodes(child, node_type))
+        .sum()
+}
+
+/// Counts the paragraphs below `node` that advance the paragraph index (see `walk_runs`): all
+/// but those of text boxes.
+fn count_paragraphs(node: &Value) -> usize {
+    child_nodes(node)
+        .iter()
+        .filter(|child| child["type"] != "textBox")
+        .map(|child| usize::from(child["type"] == "paragraph") + count_paragraphs(child))
+        .sum()
+}
+
+/// Walks the JSON representation of a DOCX file depth-first, in document order, calling `visit`
+/// with the text of each run and its `RunLocation`. The walk stops as soon as `visit` returns
+/// false. Every node with `data.children` is descended into, so the text inside structured
+/// document tags (content controls such as form fields and placeholders), whether they wrap
+/// paragraphs or runs, is visited along with the rest; so are the rows and cells of tables, and
+/// the paragraphs of text boxes and shapes, which are visited where they are anchored.
+///
+/// If `table_rows` is set, each table row is visited once instead, with the text of its cells
+/// joined by `CELL_SEPARATOR`, at the location of its first paragraph.
+///
+/// If `list_numbers` is set, the first run of each list item is visited with the item's marker,
+/// as computed by `ListNumbering`, in front of its text, and with no element index.
+///
+/// Runs inside a tracked insertion are visited with its author; deleted text is not visited,
+/// and neither are comments. The runs of a text box are located in the paragraph it is anchored
+/// in, as its paragraphs are not counted, and once the box has been walked the run formatting
+/// goes back to what it was before it, for the rest of the host run.
+///
+/// If `dehyphenate` is set, a word that was broken across a line with a hyphen and a text
+/// wrapping break (see `is_broken_word`) is visited whole, without the hyphen, at the location
+/// of its first half but with no element index, in place of the two runs.
+fn walk_runs(
+    root: &Value,
+    table_rows: bool,
+    list_numbers: bool,
+    dehyphenate: bool,
+    mut visit: impl FnMut(&str, &RunLocation) -> bool,
+) {
+    /// A step of the walk: a node to visit, or the end of a text box.
+    enum Step<'a> {
+        /// A node, whether it is inside a text box, and the author of the insertion it is in
+        Node(&'a Value, bool, Option<&'a str>),
+        /// The end of a text box, with the run formatting to go back to
+        LeaveTextBox(Vec<&'static str>),
+    }
+    let mut stack: Vec<Step> = Vec::new();
+    if let Some(children) = root["document"]["children"].as_array() {
+        stack.extend(
+            children
+                .iter()
+                .rev()
+                .map(|child| Step::Node(child, false, None)),
+        );
+    }
+    let mut location = RunLocation::default();
+    let mut elements = 0;
+    let mut numbering = list_numbers.then(|| ListNumbering::new(root));
+    // the marker of the current paragraph, until its first run takes it
+    let mut marker: Option<String> = None;
+    // a run ending in a hyphen, held back with `dehyphenate` in case the rest of its last word
+    // follows a line break, and whether that break has been passed
+    let mut held: Option<(String, RunLocation)> = None;
+    let mut after_break = false;
+    while let Some(step) = stack.pop() {
+        let (child, in_text_box, author) = match step {
+            Step::Node(child, in_text_box, author) => (child, in_text_box, author),
+            Step::LeaveTextBox(formatting) => {
+                location.formatting = formatting;
+                continue;
+            }
+        };
+        location.text_box = in_text_box;
+        if location.author.as_deref() != author {
+            location.author = author.map(str::to_string);
+        }
+        let is_wrap = child["type"] == "break" && child["data"]["breakType"] == "textWrapping";
+        let mut joined = None;
+        if let Some((first, held_location)) = held.take() {
+            if child["type"] == "run" || (is_wrap && !after_break) {
+                // the break, and the rest of the word, may be in runs of their own
+                after_break |= is_wrap;
+                held = Some((first, held_location));
+            } else if after_break
+                && child["type"] == "text"
+                && is_broken_word(&first, child["data"]["text"].as_str().unwrap())
+            {
+                joined = Some((first, held_location));
+            } else if !visit(&first, &held_location) {
+                // anything else between the hyphen and the next text leaves the word broken
+                return;
+            }
+        }
+        if held.is_none() {
+            after_break = false;
+        }
+        if child["type"] == "text" {
+            let mut text = Cow::Borrowed(child["data"]["text"].as_str().unwrap());
+            location.element_index = Some(elements);
+            elements += 1;
+            if let Some(marker) = marker.take() {
+                text = Cow::Owned(marker + &text);
+                location.element_index = None;
+            }
+            let word_location;
+            let (text, text_location) = match joined {
+                Some((first, held_location)) => {
+                    word_location = RunLocation {
+                        element_index: None,
+                        ..held_location
+                    };
+                    let word = format!("{}{}", &first[..first.len() - 1], text);
+                    (Cow::Owned(word), &word_location)
+                }
+                None => (text, &location),
+            };
+            if dehyphenate && text.ends_with('-') {
+                held = Some((text.into_owned(), text_location.clone()));
+            } else if !visit(&text, text_location) {
+                return;
+            }
+        } else if table_rows && child["type"] == "tableRow" {
+            let row_location = RunLocation {
+                para_index: location.para_index + 1,
+                heading: location.heading.clone(),
+                element_index: None,
+                formatting: Vec::new(),
+                text_box: in_text_box,
+                author: location.author.clone(),
+            };
+            location.para_index += count_paragraphs(child);
+            elements += count_nodes(child, "text");
+            if !visit(&row_text(child), &row_location) {
+                return;
+            }
+        } else {
+            if child["type"] == "run" {
+                location.formatting = run_formatting(child);
+            }
+            if child["type"] == "paragraph" {
+                if !in_text_box {
+                    location.para_index += 1;
+                }
+                marker = numbering
+                    .as_mut()
+                    .and_then(|numbering| numbering.marker(child));
+                if is_heading(child) {
+                    let heading = node_text(child);
+                    if !heading.is_empty() {
+                        location.heading = Some(heading);
+                    }
+                }
+            }
+            if child["type"] == "textBox" {
+                stack.push(Step::LeaveTextBox(location.formatting.clone()));
+            }
+            let in_text_box = in_text_box || child["type"] == "textBox";
+            let author = match child["type"].as_str() {
+                Some("insert") => child["data"]["author"].as_str(),
+                _ => author,
+            };
+            stack.extend(
+                child_nodes(child)
+                    .iter()
+                    .rev()
+                    .map(|grandchild| Step::Node(grandchild, in_text_box, author)),
+            );
+        }
+    }
+    if let Some((text, location)) = held {
+        visit(&text, &location);
+    }
+}
+
+/// Returns true if `first`, which ends in a hyphen just before a line break, and `rest`, the
+/// text after the break, are the two halves of one word that was hyphenated to fit the line,
+/// as `--dehyphenate` takes them: the hyphen follows two or more letters, with no other hyphen
+/// or apostrophe, back to the start of the word, and `rest` starts with a lowercase letter.
+/// A compound such as "well-known" broken at its own hyphen cannot be told from a hyphenated
+/// word this way, but one with more than one hyphen, or whose second half is capitalized, as
+/// in "anti-Stokes", is left alone.
+fn is_broken_word(first: &str, rest: &str) -> bool {
+    let Some(stem) = first.strip_suffix('-') else {
+        return false;
+    };
+    let word = stem
+        .rsplit(|c: char| !c.is_alphabetic())
+        .next()
+        .unwrap_or_default();
+    let before = &stem[..stem.len() - word.len()];
+    word.chars().count() >= 2
+        && !before.ends_with(['-', '\'', '\u{2019}'])
+        && rest.chars().next().is_some_and(char::is_lowercase)
+}
+
+/// Traverse the JSON representation of a DOCX file, extracting all text runs that match
+/// the given `searcher`.
+///
+/// # Arguments
+///
+/// * `root` - The JSON representation of the DOCX file, as a `serde_json::Value`.
+/// * `searcher` - A reference to the `Searcher` used to find matching text within the DOCX file.
+/// * `opts` - The `SearchOptions` for this search. If `opts.first_match_only` is set, the traversal
+///   stops at the first matching run. If `opts.para_range` is set, runs in paragraphs outside it
+///   are skipped, and the traversal stops once past its end. If `opts.cross_run_context` is set,
+///   each matching run keeps the text of the runs on either side of it in the same paragraph.
+///   If `opts.normalize` is set, the text of each run is normalized before it is matched, and
+///   if `opts.strip_invisibles` is set its invisible characters are removed first. If
+///   `opts.table_row_context` is set, each table row is matched as a whole, its cells joined.
+///   If `opts.list_numbers` is set, list items are matched with their markers in front. If
+///   `opts.dehyphenate` is set, words hyphenated at a line break are matched whole.
+///
+/// # Returns
+///
+/// * `(Runs, usize)` - A vector of text runs that match the regular expression, in document
+///   order, and the number of runs with text visited, matched or not (see `has_text`).
+fn xtract_text_from_doctree(
+    root: &Value,
+    searcher: &Searcher,
+    opts: &SearchOptions,
+) -> (Runs, usize) {
+    let runs = xtract_all_runs(root, opts);
+    collect_matching_runs(searcher, opts, |visit| {
+        for (text, location) in &runs {
+            if !visit(text, location) {
+                break;
+            }
+        }
+    })
+}
+
+/// Traverse the JSON representation of a DOCX file, collecting the text of every run, whether
+/// or not it would match a search, with its location, in document order. Table rows, list
+/// numbers and hyphenated words are walked as `opts` says (see `walk_runs`).
+fn xtract_all_runs(root: &Value, opts: &SearchOptions) -> Vec<(String, RunLocation)> {
+    let mut runs = Vec::new();
+    walk_runs(
+        root,
+        opts.table_row_context,
+        opts.list_numbers,
+        opts.dehyphenate,
+        |text, location| {
+            runs.push((text.to_string(), location.clone()));
+            true
+        },
+    );
+    runs
+}
+
+/// Collects the runs that match `searcher`, in the way `xtract_text_from_doctree` describes,
+/// from the walk over the runs of a document that `walk` makes: it is called once, with the
+/// function to call for each run, which returns false when the walk should stop. The number of
+/// runs with text that the walk visited (see `has_text`) is returned along with them.
+fn collect_matching_runs(
+    searcher: &Searcher,
+    opts: &SearchOptions,
+    walk: impl FnOnce(&mut dyn FnMut(&str, &RunLocation) -> bool),
+) -> (Runs, usize) {
+    let mut matching_runs: Runs = Vec::new();
+    let mut text_runs = 0;
+    // paragraph and text of the previous run, and whether it matched (for cross-run context)
+    let mut previous: Option<(usize, String, bool)> = None;
+    // paragraph of the previous run, and the length of that paragraph's text up to its end
+    let mut paragraph_so_far = (0, 0);
+    walk(&mut |text, location| {
+        if has_text(text) {
+            text_runs += 1;
+        }
+        let text = &*opts.match_text(text);
+        if paragraph_so_far.0 != location.para_index {
+            paragraph_so_far = (location.para_index, 0);
+        }
+        let para_offset = paragraph_so_far.1;
+        paragraph_so_far.1 += text.len();
+        let mut before = String::new();
+        if opts.cross_run_context {
+            if let Some((para_index, previous_text, matched)) = previous.take() {
+                if para_index == location.para_index {
+                    if matched {
+                        matching_runs.last_mut().unwrap().after = text.to_string();
+                    }
+                    before = previous_text;
+                }
+            }
+            previous = Some((location.para_index, text.to_string(), false));
+        }
+        if let Some(range) = opts.para_range {
+            if location.para_index > range.end {
+                return false;
+            }
+            if !range.contains(location.para_index) {
+                return true;
+            }
+        }
+        if searcher.is_match(text) && opts.is_by_author(location.author.as_deref()) {
+            matching_runs.push(Run {
+                text: text.to_string(),
+                heading: location.heading.clone(),
+                para_index: location.para_index,
+                before,
+                element_index: location.element_index,
+                formatting: location.formatting.clone(),
+                text_box: location.text_box,
+                para_offset,
+                author: location.author.clone(),
+                ..Default::default()
+            });
+            if let Some(previous) = &mut previous {
+                previous.2 = true;
+            }
+            if opts.first_match_only {
+                return false;
+            }
+        }
+        true
+    });
+    (matching_runs, text_runs)
+}
+
+/// Returns true if `text` has any characters other than white space.
+fn has_text(text: &str) -> bool {
+    !text.trim().is_empty()
+}
+
+/// Searches the body of the document held in `buffer` like `xtract_text_from_doctree`, but
+/// streaming it with `streaming::walk_runs` rather than building its JSON tree, and returns the
+/// matching runs with the number of runs with text.
+///
+/// # Errors
+///
+/// Will return an error if the body of the document cannot be read (see
+/// `streaming::walk_runs`).
+fn stream_matching_runs(
+    buffer: &[u8],
+    searcher: &Searcher,
+    opts: &SearchOptions,
+) -> anyhow::Result<(Runs, usize)> {
+    let mut result = Ok(());
+    let runs = collect_matching_runs(searcher, opts, |visit| {
+        result = streaming::walk_runs(buffer, visit);
+    });
+    result.map(|()| runs)
+}
+
+/// Returns the text of every run in the DOCX document held in `buffer`, in document order,
+/// whether or not it would match a search. This is the same traversal that searching uses.
+///
+/// # Errors
+///
+/// Will return an error if `buffer` is not a DOCX document that `read_docx` can parse.
+pub fn extract_all_text(buffer: &[u8]) -> anyhow::Result<Vec<String>> {
+    let docx = read_docx_catching_panics(buffer).context("Error decoding document")?;
+    let data: Value = serde_json::from_str(&docx.json())?;
+    Ok(xtract_all_runs(&data, &SearchOptions::default())
+        .into_iter()
+        .map(|(text, _)| text)
+        .collect())
+}
+
+/// Traverse the JSON representation of a DOCX file, collecting the full text of every paragraph
+/// that has any, in document order.
+fn xtract_paragraphs_from_doctree(root: &Value) -> Vec<String> {
+    xtract_indexed_paragraphs(root)
+        .into_iter()
+        .map(|(_, text)| text)
+        .collect()
+}
+
+/// Like `xtract_paragraphs_from_doctree`, but with the 1-based index of each paragraph.
+fn xtract_indexed_paragraphs(root: &Value) -> Vec<(usize, String)> {
+    let mut paragraphs: Vec<(usize, String)> = Vec::new();
+    walk_runs(root, false, false, false, |text, location| {
+        match paragraphs.last_mut() {
+            Some((para_index, paragraph)) if *para_index == location.para_index => {
+                paragraph.push_str(text)
+            }
+            _ => paragraphs.push((location.para_index, text.to_string())),
+        }
+        true
+    });
+    paragraphs
+}
+
+/// Fills in the `para_before` and `para_after` of each of `runs` with the text of up to
+/// `before` and `after` paragraphs on either side of its own, from `paragraphs` as returned by
+/// `xtract_indexed_paragraphs`. Paragraphs without text are passed over, and runs whose
+/// paragraph is not known are left alone.
+fn add_neighbouring_paragraphs(
+    runs: &mut Runs,
+    paragraphs: &[(usize, String)],
+    before: usize,
+    after: usize,
+) {
+    for run in runs.iter_mut() {
+        let Ok(position) = paragraphs.binary_search_by_key(&run.para_index, |(index, _)| *index)
+        else {
+            continue;
+        };
+        let texts =
+            |range: &[(usize, String)]| range.iter().map(|(_, text)| text.clone()).collect();
+        run.para_before = texts(&paragraphs[position.saturating_sub(before)..position]);
+        run.para_after =
+            texts(&paragraphs[position + 1..(position + 1 + after).min(paragraphs.len())]);
+    }
+}
+
+/// Returns the paragraphs of the document whose JSON representation is `root` that have a match
+/// of every alternative of `searcher`'s pattern (see `Searcher::matches_all_alternatives`), each
+/// as a single run of the paragraph's whole text, as matched, in document order. A paragraph
+/// takes the heading of the first of `runs`, the runs matched one by one, in it, if any.
+/// Paragraphs outside `opts.para_range`, if set, are passed over, and only the first paragraph
+/// is returned if `opts.first_match_only` is set.
+fn paragraphs_matching_all(
+    root: &Value,
+    runs: &[Run],
+    searcher: &Searcher,
+    opts: &SearchOptions,
+) -> Runs {
+    let limit = if opts.first_match_only { 1 } else { usize::MAX };
+    xtract_indexed_paragraphs(root)
+        .into_iter()
+        .filter(|(para_index, _)| {
+            opts.para_range
+                .is_none_or(|range| range.contains(*para_index))
+        })
+        .map(|(para_index, text)| (para_index, opts.match_text(&text).into_owned()))
+        .filter(|(_, text)| searcher.matches_all_alternatives(text))
+        .take(limit)
+        .map(|(para_index, text)| {
+            let first_run = runs.iter().find(|run| run.para_index == para_index);
+            Run {
+                text,
+                para_index,
+                heading: first_run.and_then(|run| run.heading.clone()),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// Fills in the `sentences` of each of `runs` with the sentence (see `sentence_spans`) that
+/// each match of `searcher` in it is in, numbered through the whole document, from
+/// `paragraphs` as returned by `xtract_indexed_paragraphs` and then brought to the form the
+/// runs were matched in. A match that runs on past the end of its sentence takes the following
+/// ones in too. Runs whose text is not found in their paragraph at their `para_offset`, such
+/// as table rows matched whole or list items matched with their markers, are left alone.
+fn add_sentences(runs: &mut Runs, paragraphs: &[(usize, String)], searcher: &Searcher) {
+    // the sentences of each paragraph, and the number of the first
+    let mut first_number = 1;
+    let mut numbered = Vec::new();
+    for (_, text) in paragraphs {
+        let spans = sentence_spans(text);
+        let count = spans.len();
+        numbered.push((first_number, spans));
+        first_number += count;
+    }
+    for run in runs.iter_mut() {
+        let Ok(position) = paragraphs.binary_search_by_key(&run.para_index, |(index, _)| *index)
+        else {
+            continue;
+        };
+        let paragraph = &paragraphs[position].1;
+        let (first_number, spans) = &numbered[position];
+        let offset = run.para_offset;
+        if spans.is_empty()
+            || !paragraph
+                .get(offset..)
+                .is_some_and(|rest| rest.starts_with(&run.text))
+        {
+            continue;
+        }
+        run.sentences = searcher
+            .find_spans(&run.text)
+            .into_iter()
+            .map(|(start, end)| {
+                let (start, end) = (offset + start, offset + end);
+                let first = spans
+                    .iter()
+                    .position(|&(_, sentence_end)| sentence_end > start)
+                    .unwrap_or(spans.len() - 1);
+                let last = spans[first..]
+                    .iter()
+                    .position(|&(_, sentence_end)| sentence_end >= end)
+                    .map_or(spans.len() - 1, |n| first + n);
+                let from = spans[first].0.min(start);
+                let to = spans[last].1.max(end);
+                Sentence {
+                    number: first_number + first,
+                    text: paragraph[from..to].to_string(),
+                    span: (start - from, end - from),
+                }
+            })
+            .collect();
+    }
+}
+
+/// Returns where `--extract-text` (or `--dump-json-tree`) writes what it extracts from the
+/// document at `source_path`: the same relative path under `out_dir`, with the extension
+/// `extension`. So that the result always lies inside `out_dir`, and documents at different
+/// paths are never written to the same file, a root becomes a `_root` directory, a Windows
+/// prefix such as `C:` a `_C` directory and each `..` a `_up` directory.
+fn output_path(out_dir: &Path, source_path: &Path, extension: &str) -> PathBuf {
+    let mut path = out_dir.to_path_buf();
+    for component in source_path.components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::RootDir => path.push("_root"),
+            Component::ParentDir => path.push("_up"),
+            Component::Prefix(prefix) => {
+                let prefix = prefix.as_os_str().to_string_lossy();
+                let name: String = prefix
+                    .chars()
+                    .map(|c| if c.is_alphanumeric() { c } else { '_' })
+                    .collect();
+                path.push(format!("_{}", name.trim_matches('_')));
+            }
+            Component::CurDir => {}
+        }
+    }
+    path.set_extension(extension);
+    path
+}
+
+/// Writes `paragraphs`, one per line, to the `--extract-text` output file for `file_like`,
+/// creating any missing directories.
+fn write_extracted_text(
+    out_dir: &Path,
+    file_like: &FileLike,
+    paragraphs: &[String],
+) -> anyhow::Result<()> {
+    let path = output_path(out_dir, &file_like.get_source_path(), "txt");
+    create_parent_dir(&path)?;
+    let mut text = paragraphs.join("\n");
+    text.push('\n');
+    std::fs::write(&path, text)
+        .with_context(|| format!("Failed to write extracted text: {}", path.display()))
+}
+
+/// Creates the directory that `path` is in, and any missing directories above it.
+fn create_parent_dir(path: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    Ok(())
+}
+
+/// Writes `tree`, the JSON representation of the document `file_like` as `read_docx` gives it,
+/// pretty-printed, for `--dump-json-tree`: to a `.json` file under `out_dir` that mirrors the
+/// document's path, or, if `out_dir` is `None`, to stdout after a line naming the document.
+fn dump_json_tree(
+    out_dir: Option<&Path>,
+    file_like: &FileLike,
+    tree: &Value,
+) -> anyhow::Result<()> {
+    let mut json = serde_json::to_string_pretty(tree)?;
+    json.push('\n');
+    match out_dir {
+        Some(out_dir) => {
+            let path = output_path(out_dir, &file_like.get_source_path(), "json");
+            create_parent_dir(&path)?;
+            std::fs::write(&path, json)
+                .with_context(|| format!("Failed to write JSON tree: {}", path.display()))
+        }
+        None => {
+            // one write under the lock, so that the trees of documents parsed at once stay whole
+            let mut stdout = std::io::stdout().lock();
+            write!(stdout, "== {} ==\n{}", file_like.get_fname(), json)?;
+            stdout.flush()?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    #[test]
+    fn test_xtract_text_from_doctree() {
+        let data = r#"
+        {
+            "document": {
+                "children": [
+                    {
+                        "type": "text",
+                        "data": {
+                            "text": "Hello, world!"
+                        }
+                    }
+                ]
+            }
+        }
+        "#;
+        let root: Value = serde_json::from_str(data).unwrap();
+        let search_re = Searcher::from(Regex::new(r"[Hh]ello").unwrap());
+        let (runs, text_runs) =
+            xtract_text_from_doctree(&root, &search_re, &SearchOptions::default());
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0], "Hello, world!");
+        assert_eq!(text_runs, 1);
+    }
+
+    #[test]
+    fn test_xtract_paragraphs_from_doctree() {
+        let data = r#"
+        {
+            "document": {
+                "children": [
+                    { "type": "paragraph", "data": { "children": [
+                        { "type": "run", "data": { "children": [
+                            { "type": "text", "data": { "text": "This is " } } ] } },
+                        { "type": "run", "data": { "children": [
+                            { "type": "text", "data": { "text": "one." } } ] } } ] } },
+                    { "type": "paragraph", "data": { "children": [] } },
+                    { "type": "paragraph", "data": { "children": [
+                        { "type": "run", "data": { "children": [
+                            { "type": "text", "data": { "text": "Another." } } ] } } ] } }
+                ]
+            }
+        }
+        "#;
+        let root: Value = serde_json::from_str(data).unwrap();
+        assert_eq!(
+            xtract_paragraphs_from_doctree(&root),
+            vec!["This is one.", "Another."]
+        );
+    }
+
+    /// Packs a document built with `docx_rs` into an in-memory file.
+    fn make_docx(docx: Docx) -> FileLike {
+        let mut cursor = std::io::Cursor::new(Vec::new());
+        docx.build().pack(&mut cursor).unwrap();
+        Arc::new(MemFile(cursor.into_inner()))
+    }
+
+    /// Builds a paragraph holding a single run of `text`.
+    fn text_paragraph(text: &str) -> Paragraph {
+        Paragraph::new().add_run(docx_rs::Run::new().add_text(text))
+    }
+
+    #[test]
+    fn test_parse_docx_heading() {
+        let file_like = make_docx(
+            Docx::new()
+                .add_paragraph(text_paragraph("Preamble mentions liability"))
+                .add_paragraph(text_paragraph("Section 3: Liability").style("Heading1"))
+                .add_paragraph(text_paragraph("The liability is limited."))
+                .add_paragraph(text_paragraph("3.1 Caps").style("Heading2"))
+                .add_paragraph(text_paragraph("No liability beyond the cap.")),
+        );
+        let re = Searcher::from(Regex::new("liability").unwrap());
+        let parsed = parse_docx(&file_like, &re, &SearchOptions::default()).unwrap();
+        let headings: Vec<Option<&str>> = parsed
+            .runs
+            .iter()
+            .map(|run| run.heading.as_deref())
+            .collect();
+        assert_eq!(
+            headings,
+            vec![None, Some("Section 3: Liability"), Some("3.1 Caps")]
+        );
+    }
+
+    #[test]
+    fn test_parse_docx_cross_run_context() {
+        let file_like = make_docx(
+            Docx::new()
+                .add_paragraph(
+                    Paragraph::new()
+                        .add_run(docx_rs::Run::new().add_text("The quick brown "))
+                        .add_run(docx_rs::Run::new().add_text("fox").bold())
+                        .add_run(docx_rs::Run::new().add_text(" jumps over")),
+                )
+                .add_paragraph(text_paragraph("Next paragraph")),
+        );
+        let re = Searcher::from(Regex::new("fox|over").unwrap());
+        let parsed = parse_docx(&file_like, &re, &SearchOptions::default()).unwrap();
+        let mtriples = parsed.runs[0].segment(&re, ContextSpec::Words(2));
+        assert_eq!(
+            (mtriples[0].pre.as_str(), mtriples[0].post.as_str()),
+            ("", "")
+        );
+
+        let opts = SearchOptions {
+            cross_run_context: true,
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts).unwrap();
+        let mtriples = parsed.runs[0].segment(&re, ContextSpec::Words(2));
+        assert_eq!(mtriples[0].pre, "quick brown ");
+        assert_eq!(mtriples[0].matched, "fox");
+        assert_eq!(mtriples[0].post, " jumps over");
+        // the last run of a paragraph takes no context from the next paragraph
+        assert_eq!(parsed.runs[1].before, "fox");
+        assert_eq!(parsed.runs[1].after, "");
+    }
+
+    #[test]
+    fn test_parse_docx_source_positions() {
+        let file_like = make_docx(
+            Docx::new()
+                .add_paragraph(text_paragraph("Terms"))
+                .add_paragraph(
+                    Paragraph::new()
+                        .add_run(docx_rs::Run::new().add_text("Fees & "))
+                        .add_run(docx_rs::Run::new().add_text("café fees, late fees").bold()),
+                ),
+        );
+        let re = Searcher::from(Regex::new("fees").unwrap());
+        let parsed = parse_docx(&file_like, &re, &SearchOptions::default()).unwrap();
+        assert_eq!(parsed.runs.len(), 1);
+        assert_eq!(parsed.runs[0].element_index, Some(2));
+        // offsets count characters, not bytes
+        assert_eq!(
+            parsed.runs[0].source_positions(&re),
+            vec![Some((2, 5)), Some((2, 16))]
+        );
+        assert_eq!(Run::from("fees").source_positions(&re), vec![None]);
+    }
+
+    #[test]
+    fn test_parse_docx_no_text() {
+        let empty = make_docx(Docx::new().add_paragraph(text_paragraph("  ")));
+        let re = Searcher::from(Regex::new("fees").unwrap());
+        for streaming in [false, true] {
+            let mut opts = SearchOptions {
+                streaming,
+                ..Default::default()
+            };
+            let result = SearchResult {
+                maybe_result: Ok(parse_docx(&empty, &re, &opts).unwrap()),
+                ..make_result("scan.docx", None)
+            };
+            let parsed = result.maybe_result.as_ref().unwrap();
+            assert_eq!(parsed.text_runs, Some(0));
+            assert!(parsed.has_no_text());
+            // shown only where files without matches are
+            assert_eq!(format_result(&result, parsed, &re, &opts), "");
+            assert!(!is_reported(&result, &opts));
+            opts.unmatched_show = true;
+            assert!(is_reported(&result, &opts));
+            let out = format_result(&result, parsed, &re, &opts);
+            assert!(out.contains("scan.docx"));
+            assert!(out.contains("(no extractable text — possibly image-only)"));
+        }
+        let file_like = make_docx(Docx::new().add_paragraph(text_paragraph("No match here")));
+        let parsed = parse_docx(&file_like, &re, &SearchOptions::default()).unwrap();
+        assert_eq!(parsed.text_runs, Some(1));
+        assert!(!parsed.has_no_text());
+        let opts = SearchOptions::default();
+        assert_eq!(
+            format_result(&make_result("a.docx", None), &parsed, &re, &opts),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_parse_docx_formatting() {
+        let file_like = make_docx(
+            Docx::new().add_paragraph(
+                Paragraph::new()
+                    .add_run(docx_rs::Run::new().add_text("the "))
+                    .add_run(docx_rs::Run::new().add_text("Licensee").bold())
+                    .add_run(docx_rs::Run::new().add_text(" and the Licensee's agents")),
+            ),
+        );
+        let re = Searcher::from(Regex::new("Licensee").unwrap());
+        let opts = SearchOptions {
+            show_formatting: true,
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts).unwrap();
+        let formatting: Vec<&[&str]> = parsed
+            .runs
+            .iter()
+            .map(|run| run.formatting.as_slice())
+            .collect();
+        assert_eq!(formatting, vec![&["bold"][..], &[][..]]);
+        let result = make_result("terms.docx", None);
+        let out = format_result(&result, &parsed, &re, &opts);
+        assert_eq!(out.matches("[bold]").count(), 1);
+    }
+
+    #[test]
+    fn test_parse_docx_list_numbers() {
+        let item = |text: &str, level: usize| {
+            text_paragraph(text).numbering(NumberingId::new(2), IndentLevel::new(level))
+        };
+        let file_like = make_docx(
+            Docx::new()
+                .add_abstract_numbering(
+                    AbstractNumbering::new(2)
+                        .add_level(Level::new(
+                            0,
+                            Start::new(1),
+                            NumberFormat::new("decimal"),
+                            LevelText::new("%1."),
+                            LevelJc::new("left"),
+                        ))
+                        .add_level(Level::new(
+                            1,
+                            Start::new(1),
+                            NumberFormat::new("lowerLetter"),
+                            LevelText::new("%2)"),
+                            LevelJc::new("left"),
+                        )),
+                )
+                .add_numbering(Numbering::new(2, 2))
+                .add_paragraph(item("Introduction", 0))
+                .add_paragraph(item("Background", 1))
+                .add_paragraph(item("Scope", 0))
+                .add_paragraph(text_paragraph("2. Not a list item")),
+        );
+        let re = Searcher::from(Regex::new(r"^(\d\.|[a-z]\)) \w+").unwrap());
+        let opts = SearchOptions {
+            list_numbers: true,
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts).unwrap();
+        assert_eq!(
+            parsed.runs,
+            vec![
+                "1. Introduction",
+                "a) Background",
+                "2. Scope",
+                "2. Not a list item"
+            ]
+        );
+        assert_eq!(parsed.runs[0].element_index, None);
+        assert_eq!(parsed.runs[3].element_index, Some(3));
+        let parsed = parse_docx(&file_like, &re, &SearchOptions::default()).unwrap();
+        assert_eq!(parsed.runs, vec!["2. Not a list item"]);
+    }
+
+    #[test]
+    fn test_parse_docx_rank_paragraphs() {
+        let file_like = make_docx(
+            Docx::new()
+                .add_paragraph(text_paragraph("one fee"))
+                .add_paragraph(
+                    Paragraph::new()
+                        .add_run(docx_rs::Run::new().add_text("fee and fee, "))
+                        .add_run(docx_rs::Run::new().add_text("then a fee")),
+                )
+                .add_paragraph(text_paragraph("no match"))
+                .add_paragraph(text_paragraph("fee, fee")),
+        );
+        let re = Searcher::from(Regex::new("fee").unwrap());
+        let opts = SearchOptions {
+            rank_paragraphs: true,
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts).unwrap();
+        let order: Vec<usize> = parsed.runs.iter().map(|run| run.para_index).collect();
+        assert_eq!(order, vec![2, 2, 4, 1]);
+        let result = make_result("fees.docx", None);
+        let out = format_result(&result, &parsed, &re, &opts);
+        assert_eq!(out.matches("paragraph 2: 3 matches").count(), 1);
+        assert!(out.contains("paragraph 1: 1 match\n"));
+    }
+
+    #[test]
+    fn test_format_paragraph_counts() {
+        let file_like = make_docx(
+            Docx::new()
+                .add_paragraph(text_paragraph("one fee"))
+                .add_paragraph(
+                    Paragraph::new()
+                        .add_run(docx_rs::Run::new().add_text("no fee here, "))
+                        .add_run(docx_rs::Run::new().add_text("then a fee and a fee")),
+                )
+                .add_paragraph(text_paragraph("fee, fee")),
+        );
+        let re = Searcher::from(Regex::new("fee").unwrap());
+        let opts = SearchOptions {
+            count_per_paragraph: Some(2),
+            context: ContextSpec::Chars(3),
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts).unwrap();
+        let out = format_result(&make_result("fees.docx", None), &parsed, &re, &opts);
+        let lines: Vec<&str> = out.lines().filter(|line| line.contains("para ")).collect();
+        // the top two paragraphs, with the first match of each and 3 characters around it
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("para 2: 3 matches") && lines[0].contains("no "));
+        assert!(lines[1].contains("para 3: 2 matches") && lines[1].contains(", \""));
+    }
+
+    #[test]
+    fn test_parse_docx_neighbouring_paragraphs() {
+        let file_like = make_docx(
+            Docx::new()
+                .add_paragraph(text_paragraph("Recitals."))
+                .add_paragraph(Paragraph::new())
+                .add_paragraph(text_paragraph("The term is three years."))
+                .add_paragraph(text_paragraph("Either party may terminate.")),
+        );
+        let re = Searcher::from(Regex::new("term ").unwrap());
+        let opts = SearchOptions {
+            para_before: 1,
+            para_after: 2,
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts).unwrap();
+        assert_eq!(parsed.runs, vec!["The term is three years."]);
+        // the empty paragraph is passed over, and there is only one paragraph after
+        assert_eq!(parsed.runs[0].para_before, vec!["Recitals."]);
+        assert_eq!(
+            parsed.runs[0].para_after,
+            vec!["Either party may terminate."]
+        );
+        let out = format_result(&make_result("a.docx", None), &parsed, &re, &opts);
+        let recitals = out.find("Recitals.").unwrap();
+        let matched = out.find("term ").unwrap();
+        let terminate = out.find("Either party").unwrap();
+        assert!(recitals < matched && matched < terminate);
+    }
+
+    #[test]
+    fn test_parse_docx_sentence_index() {
+        let file_like = make_docx(
+            Docx::new()
+                .add_paragraph(text_paragraph("Recitals. The parties agree."))
+                .add_paragraph(
+                    Paragraph::new()
+                        .add_run(docx_rs::Run::new().add_text("Fees are due. Late fees "))
+                        .add_run(docx_rs::Run::new().add_text("accrue interest! Fees").bold())
+                        .add_run(docx_rs::Run::new().add_text(" stop. Done")),
+                ),
+        );
+        let re = Searcher::from(Regex::new("(?i)fees").unwrap());
+        let opts = SearchOptions {
+            sentence_index: true,
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts).unwrap();
+        let sentences: Vec<Vec<(usize, &str)>> = parsed
+            .runs
+            .iter()
+            .map(|run| {
+                run.sentences
+                    .iter()
+                    .map(|sentence| (sentence.number, sentence.text.as_str()))
+                    .collect()
+            })
+            .collect();
+        // the first paragraph's two sentences come before those of the second
+        assert_eq!(
+            sentences,
+            vec![
+                vec![(3, "Fees are due."), (4, "Late fees accrue interest!")],
+                vec![(5, "Fees stop.")],
+            ]
+        );
+        let late = &parsed.runs[0].sentences[1];
+        assert_eq!(&late.text[late.span.0..late.span.1], "fees");
+        let out = format_result(&make_result("a.docx", None), &parsed, &re, &opts);
+        assert!(out.contains("[sentence 4]"));
+    }
+
+    #[test]
+    fn test_parse_docx_dehyphenate() {
+        let file_like = make_docx(
+            Docx::new()
+                .add_paragraph(
+                    Paragraph::new()
+                        .add_run(
+                            docx_rs::Run::new()
+                                .add_text("the inter-")
+                                .add_break(BreakType::TextWrapping),
+                        )
+                        .add_run(docx_rs::Run::new().add_text("national anti-"))
+                        .add_run(docx_rs::Run::new().add_break(BreakType::TextWrapping))
+                        .add_run(docx_rs::Run::new().add_text("Stokes line")),
+                )
+                .add_paragraph(
+                    Paragraph::new()
+                        .add_run(docx_rs::Run::new().add_text("a well-"))
+                        .add_run(docx_rs::Run::new().add_text("known well-known case")),
+                ),
+        );
+        let re = Searcher::from(Regex::new("international|anti-|well-?known").unwrap());
+        let opts = SearchOptions {
+            dehyphenate: true,
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts).unwrap();
+        assert_eq!(
+            parsed.runs,
+            vec!["the international anti-", "known well-known case"]
+        );
+        assert_eq!(parsed.runs[0].element_index, None);
+        let parsed = parse_docx(&file_like, &re, &SearchOptions::default()).unwrap();
+        assert_eq!(parsed.runs, vec!["national anti-", "known well-known case"]);
+    }
+
+    #[test]
+    fn test_parse_docx_require_all_in_paragraph() {
+        let file_like = make_docx(
+            Docx::new()
+                .add_paragraph(text_paragraph("The indemnity is set out below."))
+                .add_paragraph(text_paragraph("Liability is capped."))
+                .add_paragraph(
+                    Paragraph::new()
+                        .add_run(docx_rs::Run::new().add_text("Indemnity and liabil"))
+                        .add_run(docx_rs::Run::new().add_text("ity go together.").bold()),
+                ),
+        );
+        let searcher =
+            crate::matcher::build_alternatives("(?i)indemnity|liability", None, true).unwrap();
+        let opts = SearchOptions {
+            require_all_in_paragraph: true,
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &searcher, &opts).unwrap();
+        // the terms of the first two paragraphs are a paragraph apart, and the second term of
+        // the third is split across runs
+        assert_eq!(parsed.runs, vec!["Indemnity and liability go together."]);
+        assert_eq!(parsed.runs[0].para_index, 3);
+        let out = format_result(&make_result("a.docx", None), &parsed, &searcher, &opts);
+        assert!(out.contains("indemnity/") && out.contains("liability/"));
+        let parsed = parse_docx(&file_like, &searcher, &SearchOptions::default()).unwrap();
+        assert_eq!(parsed.runs.len(), 3);
+    }
+
+    #[test]
+    fn test_parse_docx_normalize() {
+        let file_like = make_docx(Docx::new().add_paragraph(text_paragraph("Cafe\u{301} noir")));
+        let re = Searcher::from(Regex::new("Caf\u{e9}").unwrap());
+        let parsed = parse_docx(&file_like, &re, &SearchOptions::default()).unwrap();
+        assert!(parsed.runs.is_empty());
+        let opts = SearchOptions {
+            normalize: Some(Normalization::Nfc),
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts).unwrap();
+        assert_eq!(parsed.runs, vec!["Caf\u{e9} noir"]);
+    }
+
+    #[test]
+    fn test_parse_docx_strip_invisibles() {
+        let file_like =
+            make_docx(Docx::new().add_paragraph(text_paragraph("An inter\u{ad}national treaty")));
+        let re = Searcher::from(Regex::new("international").unwrap());
+        let parsed = parse_docx(&file_like, &re, &SearchOptions::default()).unwrap();
+        assert!(parsed.runs.is_empty());
+        let opts = SearchOptions {
+            strip_invisibles: true,
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts).unwrap();
+        assert_eq!(parsed.runs, vec!["An international treaty"]);
+    }
+
+    /// Returns a copy of the package `base` with the parts `parts` added to it, each in place of
+    /// any part of `base` with the same name.
+    fn package_with_parts(base: &[u8], parts: &[(&str, &[u8])]) -> anyhow::Result<Vec<u8>> {
+        use std::io::Write;
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(base))?;
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for i in 0..archive.len() {
+            let entry = archive.by_index(i)?;
+            if !parts.iter().any(|(name, _)| *name == entry.name()) {
+                zip.raw_copy_file(entry)?;
+            }
+        }
+        for (name, content) in parts {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default())?;
+            zip.write_all(content)?;
+        }
+        Ok(zip.finish()?.into_inner())
+    }
+
+    #[test]
+    fn test_parse_docx_embedded() -> anyhow::Result<()> {
+        // copy testdoc.docx with a spreadsheet added under word/embeddings
+        let file_like: FileLike = Arc::new(MemFile(package_with_parts(
+            &read_to_vec("resources/testdoc.docx")?,
+            &[(
+                "word/embeddings/Sheet.xlsx",
+                &crate::embedded::tests::make_xlsx(),
+            )],
+        )?));
+
+        let re = Searcher::from(Regex::new("Revenue|plain").unwrap());
+        let parsed = parse_docx(&file_like, &re, &SearchOptions::default())?;
+        assert_eq!(parsed.runs.len(), 1);
+        let opts = SearchOptions {
+            embedded: true,
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts)?;
+        assert_eq!(parsed.runs.len(), 2);
+        assert_eq!(parsed.runs[1], "Revenue & costs");
+        assert_eq!(
+            parsed.runs[1].source,
+            Some(RunSource::Embedded(
+                "word/embeddings/Sheet.xlsx".to_string()
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_docx_custom_xml() -> anyhow::Result<()> {
+        // copy testdoc.docx with a custom XML data part, and the part describing it, added
+        let file_like: FileLike = Arc::new(MemFile(package_with_parts(
+            &read_to_vec("resources/testdoc.docx")?,
+            &[
+                (
+                    "customXml/item1.xml",
+                    b"<contract xmlns=\"urn:acme\"><customer code=\"ACME-12345\">Acme Corp</customer></contract>",
+                ),
+                (
+                    "customXml/itemProps1.xml",
+                    b"<ds:datastoreItem ds:itemID=\"{ACME-12345}\" xmlns:ds=\"urn:ds\"/>",
+                ),
+            ],
+        )?));
+
+        let re = Searcher::from(Regex::new("ACME-\\d+").unwrap());
+        assert!(parse_docx(&file_like, &re, &SearchOptions::default())?
+            .runs
+            .is_empty());
+        let opts = SearchOptions {
+            search_custom_xml: true,
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts)?;
+        assert_eq!(parsed.runs, vec!["ACME-12345"]);
+        assert_eq!(
+            parsed.runs[0].source,
+            Some(RunSource::CustomXml("customXml/item1.xml".to_string()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_docx_all_document_parts() -> anyhow::Result<()> {
+        // copy testdoc.docx with a glossary holding a building block the body does not mention
+        let file_like: FileLike = Arc::new(MemFile(package_with_parts(
+            &read_to_vec("resources/testdoc.docx")?,
+            &[(
+                "word/glossary/document.xml",
+                br#"<w:glossaryDocument xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:docParts><w:docPart><w:docPartBody><w:p><w:r><w:t>Confidential &amp; proprietary</w:t></w:r></w:p></w:docPartBody></w:docPart></w:docParts></w:glossaryDocument>"#,
+            )],
+        )?));
+
+        let re = Searcher::from(Regex::new("[Cc]onfidential").unwrap());
+        assert!(parse_docx(&file_like, &re, &SearchOptions::default())?
+            .runs
+            .is_empty());
+        let opts = SearchOptions {
+            all_document_parts: true,
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts)?;
+        assert_eq!(parsed.runs, vec!["Confidential & proprietary"]);
+        assert_eq!(
+            parsed.runs[0].source,
+            Some(RunSource::DocumentPart(
+                "word/glossary/document.xml".to_string()
+            ))
+        );
+        let out = format_result(&make_result("a.docx", None), &parsed, &re, &opts);
+        assert!(out.contains("(document part word/glossary/document.xml)"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_docx_search_diagrams() -> anyhow::Result<()> {
+        // copy testdoc.docx with a chart titled "Q4 Revenue" and a diagram that is cut off
+        let file_like: FileLike = Arc::new(MemFile(package_with_parts(
+            &read_to_vec("resources/testdoc.docx")?,
+            &[
+                (
+                    "word/charts/chart1.xml",
+                    b"<c:chartSpace><c:chart><c:title><c:tx><c:rich><a:p><a:r><a:t>Q4 Revenue</a:t></a:r></a:p></c:rich></c:tx></c:title></c:chart></c:chartSpace>",
+                ),
+                (
+                    "word/diagrams/data1.xml",
+                    b"<dgm:dataModel><dgm:ptLst><dgm:pt><dgm:t><a:p><a:r><a:t>Revenue",
+                ),
+            ],
+        )?));
+
+        let re = Searcher::from(Regex::new("Revenue").unwrap());
+        assert!(parse_docx(&file_like, &re, &SearchOptions::default())?
+            .runs
+            .is_empty());
+        let opts = SearchOptions {
+            search_diagrams: true,
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts)?;
+        assert_eq!(parsed.runs, vec!["Q4 Revenue"]);
+        assert_eq!(
+            parsed.runs[0].source,
+            Some(RunSource::Diagram("word/charts/chart1.xml".to_string()))
+        );
+        let out = format_result(&make_result("a.docx", None), &parsed, &re, &opts);
+        assert!(out.contains("(chart word/charts/chart1.xml)"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_docx_by_author() {
+        use docx_rs::{Comment, Insert};
+        let file_like = make_docx(
+            Docx::new()
+                .add_paragraph(text_paragraph("The fee is due on signing."))
+                .add_paragraph(
+                    Paragraph::new()
+                        .add_insert(
+                            Insert::new(docx_rs::Run::new().add_text("A late fee applies."))
+                                .author("Ann"),
+                        )
+                        .add_insert(
+                            Insert::new(docx_rs::Run::new().add_text("The fee is waived."))
+                                .author("Bob"),
+                        ),
+                )
+                .add_paragraph(
+                    Paragraph::new()
+                        .add_comment_start(
+                            Comment::new(1)
+                                .author("ann")
+                                .add_paragraph(text_paragraph("Is this fee too high?")),
+                        )
+                        .add_run(docx_rs::Run::new().add_text("Fees are in euros."))
+                        .add_comment_end(1),
+                ),
+        );
+        let re = Searcher::from(Regex::new("fee").unwrap());
+        let parsed = parse_docx(&file_like, &re, &SearchOptions::default()).unwrap();
+        assert_eq!(parsed.runs.len(), 3);
+        assert!(parsed.runs.iter().all(|run| !run.comment));
+        let opts = SearchOptions {
+            by_author: Some("ANN".to_string()),
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts).unwrap();
+        assert_eq!(
+            parsed.runs,
+            vec!["A late fee applies.", "Is this fee too high?"]
+        );
+        assert!(!parsed.runs[0].comment);
+        assert!(parsed.runs[1].comment);
+        assert_eq!(parsed.runs[1].author.as_deref(), Some("ann"));
+        let out = format_result(&make_result("a.docx", None), &parsed, &re, &opts);
+        assert!(out.contains("(inserted by "));
+        assert!(out.contains("(comment by "));
+    }
+
+    /// Packs a document built with `docx_rs`, adding a subdocument relationship to `target`.
+    fn make_master_docx(docx: Docx, target: &str) -> anyhow::Result<Vec<u8>> {
+        let buffer = make_docx(docx).read_into_buf()?;
+        let rels_part = "word/_rels/document.xml.rels";
+        let rels = rawxml::read_part(&buffer, rels_part)?.replace(
+            "</Relationships>",
+            &format!(
+                "<Relationship Id=\"rIdSub\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/subDocument\" Target=\"{}\" TargetMode=\"External\"/></Relationships>",
+                target
+            ),
+        );
+        package_with_parts(&buffer, &[(rels_part, rels.as_bytes())])
+    }
+
+    #[test]
+    fn test_parse_docx_follow_rels() -> anyhow::Result<()> {
+        // a master document and its subdocument, which links back to it
+        let dir = tempfile::tempdir()?;
+        let master = dir.path().join("master.docx");
+        std::fs::write(
+            &master,
+            make_master_docx(
+                Docx::new().add_paragraph(text_paragraph("Contents")),
+                "chapter%201.docx",
+            )?,
+        )?;
+        std::fs::write(
+            dir.path().join("chapter 1.docx"),
+            make_master_docx(
+                Docx::new().add_paragraph(text_paragraph("The chapter on liability.")),
+                "master.docx",
+            )?,
+        )?;
+        let file_like: FileLike = Arc::new(RegularFile::from(master.to_str().unwrap()));
+
+        let re = Searcher::from(Regex::new("liability|Contents").unwrap());
+        let parsed = parse_docx(&file_like, &re, &SearchOptions::default())?;
+        assert_eq!(parsed.runs, vec!["Contents"]);
+        let opts = SearchOptions {
+            follow_rels: true,
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts)?;
+        assert_eq!(parsed.runs, vec!["Contents", "The chapter on liability."]);
+        assert_eq!(parsed.runs[0].source, None);
+        assert_eq!(
+            parsed.runs[1].source.as_ref().map(RunSource::name),
+            Some("subdocument chapter%201.docx")
+        );
+        let out = format_result(&make_result("master.docx", None), &parsed, &re, &opts);
+        assert!(out.contains("(subdocument chapter%201.docx)"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_docx_peek() {
+        let file_like = make_docx(
+            Docx::new()
+                .add_paragraph(text_paragraph("Quarterly report"))
+                .add_paragraph(text_paragraph("for the third quarter")),
+        );
+        let re = Searcher::from(Regex::new("nomatch").unwrap());
+        let parsed = parse_docx(&file_like, &re, &SearchOptions::default()).unwrap();
+        assert_eq!(parsed.peek, None);
+        let opts = SearchOptions {
+            peek: Some(24),
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts).unwrap();
+        assert_eq!(parsed.peek.as_deref(), Some("Quarterly report for the"));
+        // the file is shown even though it did not match
+        let output = format_result(&make_result("a.docx", None), &parsed, &re, &opts);
+        assert!(output.contains("Quarterly report for the"));
+    }
+
+    #[test]
+    fn test_parse_docx_metadata() -> anyhow::Result<()> {
+        let file_like: FileLike = Arc::new(RegularFile::from("resources/testdoc.docx"));
+        let re = Searcher::from(Regex::new("Microsoft|plain").unwrap());
+        let opts = SearchOptions {
+            search_metadata: true,
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts)?;
+        assert_eq!(parsed.runs.len(), 2);
+        assert_eq!(parsed.runs[1], "Application: Microsoft Office Word");
+        assert!(parsed.runs[1].metadata);
+
+        // testdoc.docx is at revision 1
+        let opts = SearchOptions {
+            filter_revisions: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(parse_docx(&file_like, &re, &opts)?.runs.len(), 1);
+        let opts = SearchOptions {
+            filter_revisions: Some(1),
+            ..Default::default()
+        };
+        assert!(parse_docx(&file_like, &re, &opts)?.runs.is_empty());
+        Ok(())
+    }
+
+    /// Packs `docx`, with its `word/document.xml` rewritten by `edit`, as an in-memory file.
+    fn with_document_xml(
+        docx: Docx,
+        edit: impl FnOnce(&str) -> String,
+    ) -> anyhow::Result<FileLike> {
+        let mut cursor = std::io::Cursor::new(Vec::new());
+        docx.build().pack(&mut cursor)?;
+        let mut archive = zip::ZipArchive::new(cursor)?;
+        let mut parts = Vec::new();
+        let mut edit = Some(edit);
+        for i in 0..archive.len() {
+            let mut part = archive.by_index(i)?;
+            let mut content = String::new();
+            part.read_to_string(&mut content)?;
+            if part.name() == "word/document.xml" {
+                if let Some(edit) = edit.take() {
+                    content = edit(&content);
+                }
+            }
+            parts.push((part.name().to_string(), content));
+        }
+        let parts: Vec<(&str, &str)> = parts
+            .iter()
+            .map(|(name, content)| (name.as_str(), content.as_str()))
+            .collect();
+        Ok(Arc::new(MemFile(make_package(&parts))))
+    }
+
+    /// Builds a document whose second paragraph holds a text box with a bold run, followed by
+    /// more text of its own in the same run.
+    fn text_box_docx() -> anyhow::Result<FileLike> {
+        let docx = Docx::new()
+            .add_paragraph(text_paragraph("Spring catalogue"))
+            .add_paragraph(text_paragraph("BOX"))
+            .add_paragraph(text_paragraph("Terms of the offer apply"));
+        // docx-rs cannot write text boxes, so put one in place of the second paragraph's text
+        // with more text of the host paragraph after it, in the same run
+        let drawing = r#"<w:drawing><wp:inline><a:graphic xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main"><a:graphicData><wps:wsp><wps:txbx><w:txbxContent><w:p><w:r><w:rPr><w:b/></w:rPr><w:t>Our special offer</w:t></w:r></w:p></w:txbxContent></wps:txbx></wps:wsp></a:graphicData></a:graphic></wp:inline></w:drawing><w:t xml:space="preserve">The offer ends soon</w:t>"#;
+        with_document_xml(docx, |xml| {
+            xml.replace(r#"<w:t xml:space="preserve">BOX</w:t>"#, drawing)
+        })
+    }
+
+    #[test]
+    fn test_parse_docx_text_box() -> anyhow::Result<()> {
+        let file_like = text_box_docx()?;
+        let re = Searcher::from(Regex::new("offer").unwrap());
+        let parsed = parse_docx(&file_like, &re, &SearchOptions::default())?;
+        assert_eq!(
+            parsed.runs,
+            vec![
+                "Our special offer",
+                "The offer ends soon",
+                "Terms of the offer apply"
+            ]
+        );
+        assert!(parsed.runs[0].text_box);
+        assert!(!parsed.runs[1].text_box);
+        // the box is in the paragraph it is anchored in, which goes on after it with the
+        // formatting of its own run
+        assert_eq!(parsed.runs[0].para_index, 2);
+        assert_eq!(parsed.runs[0].formatting, ["bold"]);
+        assert_eq!(parsed.runs[1].para_index, 2);
+        assert!(parsed.runs[1].formatting.is_empty());
+        assert_eq!(parsed.runs[2].para_index, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_docx_streaming() -> anyhow::Result<()> {
+        // every run with any text, with everything that locates it
+        let re = Searcher::from(Regex::new(r"\S").unwrap());
+        let mut documents: Vec<(&str, FileLike)> =
+            ["resources/testdoc.docx", "resources/BookNotes.docx"]
+                .into_iter()
+                .map(|fname| -> (&str, FileLike) { (fname, Arc::new(RegularFile::from(fname))) })
+                .collect();
+        // with runs in and after a text box
+        documents.push(("text box", text_box_docx()?));
+        for (fname, file_like) in documents {
+            let parsed = parse_docx(&file_like, &re, &SearchOptions::default())?;
+            let opts = SearchOptions {
+                streaming: true,
+                ..Default::default()
+            };
+            let streamed = parse_docx(&file_like, &re, &opts)?;
+            assert!(!parsed.runs.is_empty());
+            assert_eq!(streamed.runs.len(), parsed.runs.len(), "{}", fname);
+            for (streamed, parsed) in streamed.runs.iter().zip(&parsed.runs) {
+                assert_eq!(streamed.text, parsed.text);
+                assert_eq!(streamed.para_index, parsed.para_index);
+                assert_eq!(streamed.heading, parsed.heading);
+                assert_eq!(streamed.element_index, parsed.element_index);
+                assert_eq!(streamed.formatting, parsed.formatting);
+                assert_eq!(streamed.text_box, parsed.text_box);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_docx_reader_panic() -> anyhow::Result<()> {
+        let docx = Docx::new().add_paragraph(text_paragraph("Underlined"));
+        // docx-rs indexes the attributes of <w:u> without checking that there are any
+        let file_like = with_document_xml(docx, |xml| {
+            xml.replace("<w:r>", "<w:r><w:rPr><w:u/></w:rPr>")
+        })?;
+
+        let re = Searcher::from(Regex::new("Underlined").unwrap());
+        let err = parse_docx(&file_like, &re, &SearchOptions::default()).unwrap_err();
+        assert!(format!("{:?}", err).contains("The document reader panicked"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_all_text() -> anyhow::Result<()> {
+        let buffer = read_to_vec("resources/testdoc.docx")?;
+        let texts = extract_all_text(&buffer)?;
+        assert!(texts.len() > 1);
+        assert!(texts.contains(&"This is a plain paragraph.".to_string()));
+        assert!(extract_all_text(b"not a docx").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_docx_structured_data_tag() {
+        let file_like = make_docx(
+            Docx::new()
+                .add_structured_data_tag(
+                    StructuredDataTag::new()
+                        .alias("Client")
+                        .add_paragraph(text_paragraph("John Smith")),
+                )
+                .add_paragraph(text_paragraph("Countersigned by ").add_structured_data_tag(
+                    StructuredDataTag::new().add_run(docx_rs::Run::new().add_text("Jane Smith")),
+                )),
+        );
+        let re = Searcher::from(Regex::new("Smith").unwrap());
+        let parsed = parse_docx(&file_like, &re, &SearchOptions::default()).unwrap();
+        assert_eq!(parsed.runs, vec!["John Smith", "Jane Smith"]);
+    }
+
+    #[test]
+    fn test_parse_docx_table_row_context() {
+        let row = |name: &str, amount: &str| {
+            TableRow::new(vec![
+                TableCell::new().add_paragraph(text_paragraph(name)),
+                TableCell::new().add_paragraph(text_paragraph(amount)),
+            ])
+        };
+        let file_like = make_docx(
+            Docx::new()
+                .add_paragraph(text_paragraph("Payments"))
+                .add_table(Table::new(vec![row("Jones", "$120"), row("Smith", "$500")]))
+                .add_paragraph(text_paragraph("Smith signed off")),
+        );
+        let re = Searcher::from(Regex::new(r"Smith.*\$\d+").unwrap());
+        let parsed = parse_docx(&file_like, &re, &SearchOptions::default()).unwrap();
+        assert!(parsed.runs.is_empty());
+        let opts = SearchOptions {
+            table_row_context: true,
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts).unwrap();
+        assert_eq!(parsed.runs, vec!["Smith | $500"]);
+        assert_eq!(parsed.runs[0].para_index, 4);
+        // cells are still searched one run at a time without the option
+        let re = Searcher::from(Regex::new("Smith").unwrap());
+        let parsed = parse_docx(&file_like, &re, &SearchOptions::default()).unwrap();
+        assert_eq!(parsed.runs, vec!["Smith", "Smith signed off"]);
+        assert_eq!(parsed.runs[1].para_index, 6);
+    }
+
+    #[test]
+    fn test_parse_docx_para_range() {
+        let docx = (1..=10).fold(Docx::new(), |docx, n| {
+            docx.add_paragraph(text_paragraph(&format!("Paragraph {n}")))
+        });
+        let file_like = make_docx(docx);
+        let re = Searcher::from(Regex::new("Paragraph").unwrap());
+        let opts = SearchOptions {
+            para_range: Some("3:5".parse().unwrap()),
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts).unwrap();
+        assert_eq!(
+            parsed.runs,
+            vec!["Paragraph 3", "Paragraph 4", "Paragraph 5"]
+        );
+        let opts = SearchOptions {
+            para_range: Some("0:99".parse().unwrap()),
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts).unwrap();
+        assert_eq!(parsed.runs.len(), 10);
+    }
+
+    #[test]
+    fn test_para_range_from_str() {
+        let range: ParaRange = "8:".parse().unwrap();
+        assert_eq!(
+            range,
+            ParaRange {
+                start: 8,
+                end: usize::MAX
+            }
+        );
+        let range: ParaRange = ":5".parse().unwrap();
+        assert_eq!(range, ParaRange { start: 1, end: 5 });
+        assert!("5:3".parse::<ParaRange>().is_err());
+        assert!("5".parse::<ParaRange>().is_err());
+        assert!("a:b".parse::<ParaRange>().is_err());
+    }
+
+    #[test]
+    fn test_included_extension_from_str() {
+        let included: IncludedExtension = "docm".parse().unwrap();
+        assert_eq!(
+            included,
+            IncludedExtension {
+                extension: ".docm".to_string(),
+                format: FileFormat::Docx,
+            }
+        );
+        let included: IncludedExtension = ".tzst=tar.zst".parse().unwrap();
+        assert_eq!(included.extension, ".tzst");
+        assert_eq!(included.format, FileFormat::TarZst);
+        let err = ".pdf".parse::<IncludedExtension>().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("No handler is known for .pdf files"));
+        let err = ".pdf=pdf".parse::<IncludedExtension>().unwrap_err();
+        assert!(err.to_string().contains("Unknown handler 'pdf'"));
+        assert!("=docx".parse::<IncludedExtension>().is_err());
+        assert!("DOCM".parse::<IncludedExtension>().is_err());
+    }
+
+    #[test]
+    fn test_text_output_path() {
+        let out_dir = Path::new("out");
+        assert_eq!(
+            output_path(out_dir, Path::new("docs/a.docx"), "txt"),
+            Path::new("out/docs/a.txt")
+        );
+        assert_eq!(
+            output_path(out_dir, Path::new("/abs/../b.zip/c.docx"), "txt"),
+            Path::new("out/_root/abs/_up/b.zip/c.txt")
+        );
+        assert_ne!(
+            output_path(out_dir, Path::new("a/../x.docx"), "txt"),
+            output_path(out_dir, Path::new("x.docx"), "txt")
+        );
+        assert_ne!(
+            output_path(out_dir, Path::new("/abs/x.docx"), "txt"),
+            output_path(out_dir, Path::new("abs/x.docx"), "txt")
+        );
+    }
+
+    #[test]
+    fn test_parse_docx_extract_text() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file_like: FileLike = Arc::new(RegularFile::from("resources/testdoc.docx"));
+        let opts = SearchOptions {
+            extract_text: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        let re = Searcher::from(Regex::new("nomatch").unwrap());
+        parse_docx(&file_like, &re, &opts)?;
+        let text = std::fs::read_to_string(dir.path().join("resources/testdoc.txt"))?;
+        assert!(
+            text.starts_with("This is a plain paragraph.\nThis is a more complicated paragraph.\n")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_docx_dump_json_tree() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file_like: FileLike = Arc::new(RegularFile::from("resources/testdoc.docx"));
+        let opts = SearchOptions {
+            dump_json_tree: Some(Some(dir.path().to_path_buf())),
+            ..Default::default()
+        };
+        let re = Searcher::from(Regex::new("plain").unwrap());
+        assert!(parse_docx(&file_like, &re, &opts)?.runs.is_empty());
+        let json = std::fs::read_to_string(dir.path().join("resources/testdoc.json"))?;
+        let tree: Value = serde_json::from_str(&json)?;
+        assert!(tree["document"]["children"].is_array());
+        Ok(())
+    }
+
+    #[test]
+    fn test_xtract_text_first_match_only() {
+        let data = r#"
+        {
+            "document": {
+                "children": [
+                    { "type": "text", "data": { "text": "Hello, world!" } },
+                    { "type": "text", "data": { "text": "Goodbye" } },
+                    { "type": "text", "data": { "text": "hello again" } }
+                ]
+            }
+        }
+        "#;
+        let root: Value = serde_json::from_str(data).unwrap();
+        let search_re = Searcher::from(Regex::new(r"[Hh]ello").unwrap());
+        let (runs, _) = xtract_text_from_doctree(&root, &search_re, &SearchOptions::default());
+        assert_eq!(runs.len(), 2);
+        let opts = SearchOptions {
+            first_match_only: true,
+            ..Default::default()
+        };
+        let (runs, _) = xtract_text_from_doctree(&root, &search_re, &opts);
+        assert_eq!(runs, vec!["Hello, world!"]);
+    }
+
+    #[test]
+    fn test_zip_entry_name() {
+        let zip_entry = ZipEntry {
+            archive_name: "test.zip".to_string(),
+            entry_name: "test.docx".to_string(),
+            compressed_size: 0,
+            uncompressed_size: 0,
+            last_modified: None,
+        };
         assert_eq!(zip_entry.get_fname(), "File: test.docx in test.zip");
     }
 
+    struct SlowFile;
+
+    impl ReadIntoBuf for SlowFile {
+        fn read_into_buf(&self) -> anyhow::Result<Vec<u8>> {
+            std::thread::sleep(Duration::from_secs(2));
+            Ok(Vec::new())
+        }
+
+        fn get_fname(&self) -> String {
+            "slow.docx".to_string()
+        }
+
+        fn get_size(&self) -> anyhow::Result<FileSize> {
+            Ok(FileSize::OnDisk(0))
+        }
+
+        fn get_modified(&self) -> anyhow::Result<SystemTime> {
+            Ok(SystemTime::UNIX_EPOCH)
+        }
+
+        fn get_archive_name(&self) -> Option<String> {
+            None
+        }
+
+        fn get_source_path(&self) -> PathBuf {
+            PathBuf::from(self.get_fname())
+        }
+    }
+
+    fn make_result(file_name: &str, archive_name: Option<&str>) -> SearchResult {
+        SearchResult {
+            file_name: file_name.to_string(),
+            archive_name: archive_name.map(|s| s.to_string()),
+            source_path: PathBuf::from(archive_name.unwrap_or_default()).join(file_name),
+            maybe_result: Ok(ParsedDoc::default()),
+            timed_out: false,
+            size: None,
+        }
+    }
+
+    #[test]
+    fn test_reorder_buffer() {
+        let mut reorder = ReorderBuffer::default();
+        assert!(reorder.push(2, 'c').is_empty());
+        assert!(reorder.push(1, 'b').is_empty());
+        assert_eq!(reorder.push(0, 'a'), ['a', 'b', 'c']);
+        assert_eq!(reorder.push(3, 'd'), ['d']);
+        assert!(reorder.push(5, 'f').is_empty());
+        assert_eq!(reorder.push(4, 'e'), ['e', 'f']);
+    }
+
+    #[test]
+    fn test_apply_min_matches() {
+        let re = Searcher::from(Regex::new("cat").unwrap());
+        let parsed = |texts: &[&str]| ParsedDoc {
+            runs: texts.iter().map(|&text| Run::from(text)).collect(),
+            ..Default::default()
+        };
+        // three matches across two runs
+        let kept = apply_min_matches(parsed(&["cat and cat", "a cat"]), &re, Some(3));
+        assert_eq!(kept.runs.len(), 2);
+        assert!(!kept.below_min_matches);
+        let dropped = apply_min_matches(parsed(&["cat and cat", "a cat"]), &re, Some(4));
+        assert!(dropped.runs.is_empty());
+        assert!(dropped.below_min_matches);
+        assert!(!apply_min_matches(parsed(&[]), &re, Some(4)).below_min_matches);
+
+        let mut summary = ScanSummary::default();
+        let mut result = make_result("a.docx", None);
+        result.maybe_result = Ok(dropped);
+        summary.record(&result, &re);
+        assert_eq!(summary.files_matched, 0);
+        assert_eq!(summary.files_below_min_matches, 1);
+    }
+
+    #[test]
+    fn test_limit_reported() {
+        let results = || -> Vec<SearchResult> {
+            ["a", "b", "c", "d", "e"]
+                .iter()
+                .map(|name| {
+                    let mut result = make_result(name, None);
+                    // b and d have no matches
+                    if !matches!(*name, "b" | "d") {
+                        result.maybe_result = Ok(ParsedDoc {
+                            runs: vec![Run::from("match")],
+                            ..Default::default()
+                        });
+                    }
+                    result
+                })
+                .collect()
+        };
+        let names = |opts: SearchOptions| -> Vec<String> {
+            let mut results = results();
+            limit_reported(&mut results, &opts);
+            results.into_iter().map(|result| result.file_name).collect()
+        };
+        let limit = |head: Option<usize>, tail: Option<usize>| SearchOptions {
+            head,
+            tail,
+            ..Default::default()
+        };
+        assert_eq!(names(limit(Some(2), None)), vec!["a", "b", "c"]);
+        assert_eq!(names(limit(None, Some(2))), vec!["c", "d", "e"]);
+        assert_eq!(names(limit(Some(9), None)).len(), 5);
+        assert_eq!(names(limit(None, Some(9))).len(), 5);
+        assert!(names(limit(Some(0), None)).is_empty());
+        assert!(names(limit(None, Some(0))).is_empty());
+        let opts = SearchOptions {
+            unmatched_show: true,
+            ..limit(Some(2), None)
+        };
+        assert_eq!(names(opts), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_process_files_summary() -> anyhow::Result<()> {
+        let re = Searcher::from(Regex::new("plain").unwrap());
+        let summary = process_files("resources", &re, &SearchOptions::default())?;
+        assert_eq!(summary.docx_fnames.len(), 2);
+        assert_eq!(summary.zip_fnames.len(), 1);
+        assert_eq!(summary.files_searched, 4);
+        assert_eq!(summary.files_matched, 2);
+        assert_eq!(summary.files_errored, 0);
+        assert_eq!(summary.total_matches, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_files_dedupe() -> anyhow::Result<()> {
+        let re = Searcher::from(Regex::new("plain").unwrap());
+        let opts = SearchOptions {
+            dedupe: true,
+            ..Default::default()
+        };
+        let summary = process_files("resources", &re, &opts)?;
+        // the archive holds copies of both loose documents
+        assert_eq!(
+            summary.duplicate_groups(),
+            vec![
+                vec![
+                    "File: BookNotes.docx in resources/TestArchive.zip".to_string(),
+                    "resources/BookNotes.docx".to_string()
+                ],
+                vec![
+                    "File: testdoc.docx in resources/TestArchive.zip".to_string(),
+                    "resources/testdoc.docx".to_string()
+                ]
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_coverage() {
+        let re = Searcher::from(Regex::new("plain").unwrap());
+        let mut summary = ScanSummary::default();
+        let mut matched = make_result("docs/a.docx", None);
+        matched.maybe_result = Ok(ParsedDoc {
+            runs: vec![Run::from("plain text")],
+            ..Default::default()
+        });
+        summary.record(&matched, &re);
+        summary.record(&make_result("docs/b.docx", None), &re);
+        summary.record(&make_result("c.docx", Some("docs/c.zip")), &re);
+        assert_eq!(
+            summary.coverage_by_directory(),
+            vec![("docs/c.zip".to_string(), 0, 1), ("docs".to_string(), 1, 2)]
+        );
+    }
+
+    #[test]
+    fn test_process_files_fail_fast() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::copy("resources/testdoc.docx", dir.path().join("good.docx"))?;
+        std::fs::write(dir.path().join("bad.docx"), "not a docx")?;
+        let base_dir = dir.path().to_str().unwrap();
+        let re = Searcher::from(Regex::new("plain").unwrap());
+        let summary = process_files(base_dir, &re, &SearchOptions::default())?;
+        assert_eq!(summary.files_errored, 1);
+        let opts = SearchOptions {
+            fail_fast: true,
+            ..Default::default()
+        };
+        let err = process_files(base_dir, &re, &opts).unwrap_err();
+        assert!(err.to_string().contains("bad.docx"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_by_archive() {
+        let results = vec![
+            make_result("b1.docx", Some("b.zip")),
+            make_result("loose.docx", None),
+            make_result("a1.docx", Some("a.zip")),
+            make_result("b2.docx", Some("b.zip")),
+        ];
+        let groups = group_by_archive(results);
+        let names: Vec<(&str, Vec<&str>)> = groups
+            .iter()
+            .map(|(name, group)| {
+                (
+                    name.as_str(),
+                    group.iter().map(|r| r.file_name.as_str()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                (LOOSE_FILES, vec!["loose.docx"]),
+                ("a.zip", vec!["a1.docx"]),
+                ("b.zip", vec!["b1.docx", "b2.docx"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_result_template() {
+        let result = make_result("a.docx", Some("docs.zip"));
+        let parsed = ParsedDoc {
+            runs: vec![Run {
+                text: "cat and cat".to_string(),
+                para_index: 4,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let opts = SearchOptions {
+            format: Some(
+                "{file}|{archive}|{para}|{count}|{pre}[{match}]{post}"
+                    .parse()
+                    .unwrap(),
+            ),
+            ..Default::default()
+        };
+        let re = Searcher::from(Regex::new("cat").unwrap());
+        assert_eq!(
+            format_result(&result, &parsed, &re, &opts),
+            "docs.zip/a.docx|docs.zip|4|1|[cat] and \ndocs.zip/a.docx|docs.zip|4|2| and [cat]\n"
+        );
+    }
+
+    #[test]
+    fn test_format_result_match_numbers() {
+        let run = |text: &str| Run {
+            text: text.to_string(),
+            ..Default::default()
+        };
+        let parsed = ParsedDoc {
+            runs: vec![run("cat and cat"), run("a cat")],
+            ..Default::default()
+        };
+        let re = Searcher::from(Regex::new("cat").unwrap());
+        let prompts = |opts: &SearchOptions| -> Vec<String> {
+            format_result(&make_result("a.docx", None), &parsed, &re, opts)
+                .lines()
+                .filter_map(|line| Some(line.strip_prefix("  ")?.split_once("-> ")?.0.to_string()))
+                .collect()
+        };
+        assert_eq!(prompts(&SearchOptions::default()), ["1", "2", "3"]);
+        let opts = SearchOptions {
+            compound_index: true,
+            ..Default::default()
+        };
+        assert_eq!(prompts(&opts), ["1-1", "1-2", "2-1"]);
+    }
+
+    #[test]
+    fn test_format_result_group_separator() {
+        let parsed = ParsedDoc {
+            runs: vec![Run::from("a cat")],
+            ..Default::default()
+        };
+        let re = Searcher::from(Regex::new("cat").unwrap());
+        let format =
+            |opts: &SearchOptions| format_result(&make_result("a.docx", None), &parsed, &re, opts);
+        assert!(format(&SearchOptions::default()).ends_with("\n===\n\n"));
+        let opts = SearchOptions {
+            group_separator: Some("--".to_string()),
+            ..Default::default()
+        };
+        assert!(format(&opts).ends_with("\n--\n\n"));
+        let opts = SearchOptions {
+            group_separator: Some("--".to_string()),
+            no_group_separator: true,
+            ..Default::default()
+        };
+        let out = format(&opts);
+        assert!(!out.contains("--\n") && !out.contains("===") && out.ends_with("\n\n"));
+    }
+
+    #[test]
+    fn test_unique_matches() -> anyhow::Result<()> {
+        let run = |text: &str| Run {
+            text: text.to_string(),
+            ..Default::default()
+        };
+        let re = Searcher::from(Regex::new("(?i)cat").unwrap());
+        let unique = unique_matches(&[run("Cat and cat"), run("a cat")], &re);
+        assert_eq!(
+            unique,
+            BTreeSet::from(["Cat".to_string(), "cat".to_string()])
+        );
+
+        let re = Searcher::from(Regex::new("plain").unwrap());
+        let opts = SearchOptions {
+            count_unique: Some(UniqueScope::Corpus),
+            ..Default::default()
+        };
+        let summary = process_files("resources", &re, &opts)?;
+        assert_eq!(
+            summary.unique_matches,
+            Some(BTreeSet::from(["plain".to_string()]))
+        );
+        let summary = process_files("resources", &re, &SearchOptions::default())?;
+        assert_eq!(summary.unique_matches, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_bounded() {
+        let data = [0u8; 100];
+        assert_eq!(read_bounded(&data[..], 100, "a.docx").unwrap().len(), 100);
+        // a small overrun is tolerated
+        assert!(read_bounded(&data[..], 60, "a.docx").is_ok());
+        let bomb = std::io::repeat(0).take(10 * INFLATE_MARGIN);
+        let err = read_bounded(bomb, 10, "bomb.docx").unwrap_err();
+        assert!(err.to_string().starts_with("Refusing to read bomb.docx"));
+    }
+
+    #[test]
+    fn test_process_files_max_file_size() -> anyhow::Result<()> {
+        let re = Searcher::from(Regex::new("plain").unwrap());
+        // testdoc.docx, loose and archived, is the only document under 15000 bytes
+        let opts = SearchOptions {
+            max_file_size: Some(15000),
+            ..Default::default()
+        };
+        let summary = process_files("resources", &re, &opts)?;
+        assert_eq!(summary.files_searched, 2);
+        assert_eq!(summary.files_matched, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_reader() -> anyhow::Result<()> {
+        // a .docx package behind a header of its own
+        struct Wrapped(String);
+        impl ReadIntoBuf for Wrapped {
+            fn read_into_buf(&self) -> anyhow::Result<Vec<u8>> {
+                let buf = read_to_vec(&self.0)?;
+                Ok(buf.strip_prefix(b"WRAP").context("Not wrapped")?.to_vec())
+            }
+            fn get_fname(&self) -> String {
+                self.0.clone()
+            }
+            fn get_size(&self) -> anyhow::Result<FileSize> {
+                Ok(FileSize::OnDisk(std::fs::metadata(&self.0)?.len()))
+            }
+            fn get_modified(&self) -> anyhow::Result<SystemTime> {
+                modified_time(&self.0)
+            }
+            fn get_archive_name(&self) -> Option<String> {
+                None
+            }
+            fn get_source_path(&self) -> PathBuf {
+                PathBuf::from(&self.0)
+            }
+        }
+
+        let dir = tempfile::tempdir()?;
+        let mut wrapped = b"WRAP".to_vec();
+        wrapped.extend(std::fs::read("resources/testdoc.docx")?);
+        std::fs::write(dir.path().join("a.wrap"), &wrapped)?;
+        std::fs::write(dir.path().join("b.wrap.docx"), &wrapped)?;
+        std::fs::copy("resources/testdoc.docx", dir.path().join("c.docx"))?;
+        let mut opts = SearchOptions::default();
+        opts.register_reader(
+            "wrap",
+            Arc::new(|fname| Box::new(Wrapped(fname.to_string()))),
+        );
+        opts.register_reader(
+            ".wrap.docx",
+            Arc::new(|fname| Box::new(Wrapped(fname.to_string()))),
+        );
+        let re = Searcher::from(Regex::new("plain")?);
+        let results = search_documents(dir.path().to_str().unwrap(), &re, &opts)?;
+        let mut matched: Vec<(String, usize)> = results
+            .iter()
+            .map(|result| {
+                let name = result.source_path.file_name().unwrap();
+                let parsed = result.maybe_result.as_ref().unwrap();
+                (name.to_string_lossy().into_owned(), parsed.runs.len())
+            })
+            .collect();
+        matched.sort();
+        assert_eq!(
+            matched,
+            [
+                ("a.wrap".to_string(), 1),
+                ("b.wrap.docx".to_string(), 1),
+                ("c.docx".to_string(), 1)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_take_matches() {
+        let re = Searcher::from(Regex::new("a").unwrap());
+        let parsed = || ParsedDoc {
+            runs: vec!["a".into(), "aa".into(), "a".into()],
+            ..Default::default()
+        };
+        let (taken, cancelled) = (AtomicUsize::new(0), AtomicBool::new(false));
+        let first = take_matches(parsed(), &re, &taken, 6, &cancelled).unwrap();
+        assert_eq!(first.runs.len(), 3);
+        assert!(!cancelled.load(Ordering::Relaxed));
+        // the run that reaches the limit is kept whole
+        let second = take_matches(parsed(), &re, &taken, 6, &cancelled).unwrap();
+        assert_eq!(second.runs, vec!["a", "aa"]);
+        assert!(cancelled.load(Ordering::Relaxed));
+        assert!(take_matches(parsed(), &re, &taken, 6, &cancelled).is_none());
+    }
+
+    #[test]
+    fn test_process_files_max_results() -> anyhow::Result<()> {
+        let re = Searcher::from(Regex::new("plain")?);
+        let opts = SearchOptions {
+            max_results: Some(1),
+            ..Default::default()
+        };
+        let summary = process_files("resources", &re, &opts)?;
+        assert_eq!(summary.total_matches, 1);
+        assert_eq!(summary.files_matched, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_files_since() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        for name in ["old.docx", "new.docx"] {
+            std::fs::copy("resources/testdoc.docx", dir.path().join(name))?;
+        }
+        std::fs::File::options()
+            .write(true)
+            .open(dir.path().join("old.docx"))?
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_500_000_000))?;
+        let re = Searcher::from(Regex::new("plain").unwrap());
+        let opts = SearchOptions {
+            since: Some("2020-01-01".parse()?),
+            ..Default::default()
+        };
+        let summary = process_files(dir.path().to_str().unwrap(), &re, &opts)?;
+        assert_eq!(summary.files_searched, 1);
+        assert_eq!(summary.docx_fnames.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_files_include_ext() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::copy("resources/testdoc.docx", dir.path().join("a.docx"))?;
+        std::fs::copy("resources/testdoc.docx", dir.path().join("b.docm"))?;
+        std::fs::copy("resources/TestArchive.zip", dir.path().join("c.docpack"))?;
+        std::fs::copy("resources/testdoc.docx", dir.path().join("d.doc[1]"))?;
+        let base_dir = dir.path().to_str().unwrap();
+        let re = Searcher::from(Regex::new("plain").unwrap());
+        let summary = process_files(base_dir, &re, &SearchOptions::default())?;
+        assert_eq!(summary.docx_fnames.len(), 1);
+        assert!(summary.zip_fnames.is_empty());
+        let opts = SearchOptions {
+            include_ext: vec![
+                ".docm".parse()?,
+                "docpack=zip".parse()?,
+                "doc[1]=docx".parse()?,
+            ],
+            ..Default::default()
+        };
+        let summary = process_files(base_dir, &re, &opts)?;
+        assert_eq!(summary.docx_fnames.len(), 3);
+        assert_eq!(summary.zip_fnames.len(), 1);
+        let opts = SearchOptions {
+            archives_only: true,
+            ..opts
+        };
+        let summary = process_files(base_dir, &re, &opts)?;
+        assert!(summary.docx_fnames.is_empty());
+        assert_eq!(summary.zip_fnames.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_files_resume() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        for name in ["a.docx", "b.docx"] {
+            std::fs::copy("resources/testdoc.docx", dir.path().join(name))?;
+        }
+        let manifest = dir.path().join("manifest.txt");
+        let re = Searcher::from(Regex::new("plain").unwrap());
+        let opts = SearchOptions {
+            resume: Some(manifest.clone()),
+            ..Default::default()
+        };
+        let base_dir = dir.path().to_str().unwrap();
+        assert_eq!(process_files(base_dir, &re, &opts)?.files_searched, 2);
+        assert_eq!(std::fs::read_to_string(&manifest)?.lines().count(), 2);
+        assert_eq!(process_files(base_dir, &re, &opts)?.files_searched, 0);
+        // a document changed since it was recorded is searched again
+        std::fs::File::options()
+            .write(true)
+            .open(dir.path().join("b.docx"))?
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_500_000_000))?;
+        assert_eq!(process_files(base_dir, &re, &opts)?.files_searched, 1);
+        assert_eq!(process_files(base_dir, &re, &opts)?.files_searched, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_files_archive_scope() -> anyhow::Result<()> {
+        let re = Searcher::from(Regex::new("plain").unwrap());
+        let opts = SearchOptions {
+            no_archives: true,
+            ..Default::default()
+        };
+        let summary = process_files("resources", &re, &opts)?;
+        assert_eq!(summary.files_searched, 2);
+        assert_eq!(summary.docx_fnames.len(), 2);
+        assert!(summary.zip_fnames.is_empty());
+        let opts = SearchOptions {
+            archives_only: true,
+            ..Default::default()
+        };
+        let summary = process_files("resources", &re, &opts)?;
+        assert_eq!(summary.files_searched, 2);
+        assert!(summary.docx_fnames.is_empty());
+        assert_eq!(summary.zip_fnames, vec!["resources/TestArchive.zip"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_size_display() {
+        assert_eq!(FileSize::OnDisk(42).to_string(), "size: 42 bytes");
+        let archived = FileSize::Archived {
+            compressed: 25,
+            uncompressed: 100,
+        };
+        assert_eq!(
+            archived.to_string(),
+            "compressed: 25 bytes, uncompressed: 100 bytes (ratio 0.25)"
+        );
+    }
+
+    /// An in-memory document, for feeding hand-built packages to `parse_docx`.
+    struct MemFile(Vec<u8>);
+
+    impl ReadIntoBuf for MemFile {
+        fn read_into_buf(&self) -> anyhow::Result<Vec<u8>> {
+            Ok(self.0.clone())
+        }
+
+        fn get_fname(&self) -> String {
+            "mem.docx".to_string()
+        }
+
+        fn get_size(&self) -> anyhow::Result<FileSize> {
+            Ok(FileSize::OnDisk(self.0.len() as u64))
+        }
+
+        fn get_modified(&self) -> anyhow::Result<SystemTime> {
+            Ok(SystemTime::UNIX_EPOCH)
+        }
+
+        fn get_archive_name(&self) -> Option<String> {
+            None
+        }
+
+        fn get_source_path(&self) -> PathBuf {
+            PathBuf::from(self.get_fname())
+        }
+    }
+
+    /// Builds a zip package containing the given parts.
+    fn make_package(parts: &[(&str, &str)]) -> Vec<u8> {
+        use std::io::Write;
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, content) in parts {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_parse_docx_raw_fallback() {
+        // a bare document.xml with no content types or relationships is rejected by read_docx
+        let file_like: FileLike = Arc::new(MemFile(make_package(&[(
+            "word/document.xml",
+            "<w:document><w:body><w:p><w:r><w:t>rescued text</w:t></w:r></w:p></w:body></w:document>",
+        )])));
+        let re = Searcher::from(Regex::new("rescued").unwrap());
+        assert!(parse_docx(&file_like, &re, &SearchOptions::default()).is_err());
+        let opts = SearchOptions {
+            raw_fallback: true,
+            ..Default::default()
+        };
+        let parsed = parse_docx(&file_like, &re, &opts).unwrap();
+        assert!(parsed.raw_fallback);
+        assert_eq!(parsed.runs, vec!["rescued text"]);
+        assert_eq!(parsed.runs[0].element_index, Some(0));
+
+        // a byte order mark and a namespace prefix other than w:
+        let file_like: FileLike = Arc::new(MemFile(make_package(&[(
+            "word/document.xml",
+            "\u{feff}<wp:document xmlns:wp=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\">\
+             <wp:body><wp:p><wp:r><wp:t>rescued text</wp:t></wp:r></wp:p></wp:body></wp:document>",
+        )])));
+        let parsed = parse_docx(&file_like, &re, &opts).unwrap();
+        assert_eq!(parsed.runs, vec!["rescued text"]);
+
+        // the text hashes alike however the document is read
+        let document = "<w:document><w:body><w:p><w:r><w:t>rescued </w:t></w:r>\
+                        <w:r><w:t>text</w:t></w:r></w:p></w:body></w:document>";
+        let opts = SearchOptions {
+            raw_fallback: true,
+            dedupe: true,
+            ..Default::default()
+        };
+        let raw = Arc::new(MemFile(make_package(&[("word/document.xml", document)]))) as FileLike;
+        let docx = Docx::new().add_paragraph(
+            Paragraph::new()
+                .add_run(docx_rs::Run::new().add_text("rescued "))
+                .add_run(docx_rs::Run::new().add_text("text")),
+        );
+        let tree = with_document_xml(docx, |xml| xml.to_string()).unwrap();
+        let raw_hash = parse_docx(&raw, &re, &opts).unwrap().content_hash;
+        assert!(raw_hash.is_some());
+        assert_eq!(
+            raw_hash,
+            parse_docx(&tree, &re, &opts).unwrap().content_hash
+        );
+    }
+
+    #[test]
+    fn test_search_file_timeout() {
+        let file_like: FileLike = Arc::new(SlowFile);
+        let opts = SearchOptions {
+            timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let result = search_file(&file_like, &Searcher::from(Regex::new("x").unwrap()), &opts);
+        assert!(result.timed_out);
+        assert!(result.maybe_result.is_err());
+    }
+
     #[test]
     fn test_read_to_vec_error() {
         let _: Vec<u8> = Vec::new();
@@ -1,8 +1,8 @@
-use glob::glob;
+use glob::{glob, Pattern};
 
 #[derive(Debug)]
 pub(crate) struct Fnames {
-    pub fnames: Vec<String>,
+    pub(crate) fnames: Vec<String>,
 }
 
 impl TryFrom<&str> for Fnames {
@@ -26,16 +26,25 @@ impl TryFrom<&str> for Fnames {
 /// find all matching files, and the resulting paths are converted to `String`s
 /// and stored in the `fnames` member of the returned `Fnames` struct.
 ///
+/// `base_dir` and `suffix` are taken literally: any glob metacharacters in them
+/// (such as `[` or `*`) are escaped, so only the `/**/*` wildcard between them is
+/// active.
+///
 /// # Errors
 ///
 /// Will return an error if the glob pattern is invalid or if the glob
 /// pattern fails to match any files.
-pub fn make_fnames(base_dir: &str, suffix: &str) -> anyhow::Result<Fnames> {
-    let mut fpath = base_dir.trim_end_matches("/").to_string();
-    let extension = format!("/**/*{}", suffix);
-    fpath.push_str(extension.as_str());
+pub(crate) fn make_fnames(base_dir: &str, suffix: &str) -> anyhow::Result<Fnames> {
+    Fnames::try_from(glob_pattern(base_dir, suffix).as_str())
+}
 
-    Fnames::try_from(fpath.as_str())
+/// Returns the glob pattern that `make_fnames` matches files against: `base_dir`, escaped,
+/// followed by `/**/*suffix`, with `suffix` escaped as well.
+pub(crate) fn glob_pattern(base_dir: &str, suffix: &str) -> String {
+    let mut fpath = Pattern::escape(base_dir.trim_end_matches("/"));
+    let extension = format!("/**/*{}", Pattern::escape(suffix));
+    fpath.push_str(extension.as_str());
+    fpath
 }
 
 #[cfg(test)]
@@ -49,4 +58,15 @@ mod tests {
         let f = make_fnames(base_dir, suffix).unwrap();
         assert_eq!(f.fnames.len(), 2);
     }
+
+    #[test]
+    fn test_make_fnames_literal_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("weird[name]");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::copy("resources/testdoc.docx", dir.join("testdoc.docx")).unwrap();
+        let f = make_fnames(dir.to_str().unwrap(), ".docx").unwrap();
+        assert_eq!(f.fnames.len(), 1);
+        assert!(f.fnames[0].ends_with("weird[name]/testdoc.docx"));
+    }
 }
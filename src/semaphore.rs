@@ -0,0 +1,70 @@
+use std::num::NonZeroUsize;
+use std::sync::{Condvar, Mutex};
+
+/// A counting semaphore that lets at most a fixed number of holders through at once. It is
+/// used to bound how many documents are held in memory and parsed at the same time,
+/// independently of the size of the thread pool doing the work.
+#[derive(Debug)]
+pub(crate) struct Semaphore {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+/// A permit from a `Semaphore`, which is given back when it is dropped.
+#[derive(Debug)]
+pub(crate) struct Permit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Semaphore {
+    pub(crate) fn new(permits: NonZeroUsize) -> Self {
+        Semaphore {
+            available: Mutex::new(permits.get()),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Blocks until a permit is available, and takes it.
+    pub(crate) fn acquire(&self) -> Permit<'_> {
+        let mut available = self.available.lock().unwrap();
+        while *available == 0 {
+            available = self.released.wait(available).unwrap();
+        }
+        *available -= 1;
+        Permit { semaphore: self }
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self.semaphore.available.lock().unwrap() += 1;
+        self.semaphore.released.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_semaphore_bounds_holders() {
+        let semaphore = Semaphore::new(NonZeroUsize::new(2).unwrap());
+        let holding = AtomicUsize::new(0);
+        let most_holding = AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    let _permit = semaphore.acquire();
+                    let now = holding.fetch_add(1, Ordering::SeqCst) + 1;
+                    most_holding.fetch_max(now, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(10));
+                    holding.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+        assert!(most_holding.load(Ordering::SeqCst) <= 2);
+        assert_eq!(*semaphore.available.lock().unwrap(), 2);
+    }
+}
@@ -0,0 +1,149 @@
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The cutoff of `--since`: only documents modified at or after it are searched. It is parsed
+/// either from an absolute date, `YYYY-MM-DD` or `YYYY-MM-DDTHH:MM[:SS]` (taken as UTC), or from
+/// a duration back from now, a number followed by `m` (minutes), `h` (hours), `d` (days) or `w`
+/// (weeks), such as `7d`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Since {
+    cutoff: SystemTime,
+}
+
+impl Since {
+    /// Returns true if a document last modified at `modified` is recent enough to be searched.
+    pub fn admits(&self, modified: SystemTime) -> bool {
+        modified >= self.cutoff
+    }
+}
+
+impl FromStr for Since {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> anyhow::Result<Self> {
+        let invalid = || {
+            anyhow::anyhow!(
+                "Invalid --since '{}': expected YYYY-MM-DD, YYYY-MM-DDTHH:MM[:SS] or a duration such as 7d",
+                spec
+            )
+        };
+        let spec = spec.trim();
+        if let Some(unit) = spec.chars().last().filter(char::is_ascii_alphabetic) {
+            let seconds = match unit {
+                'm' => 60,
+                'h' => 60 * 60,
+                'd' => 24 * 60 * 60,
+                'w' => 7 * 24 * 60 * 60,
+                _ => return Err(invalid()),
+            };
+            let count: u64 = spec[..spec.len() - 1].parse().map_err(|_| invalid())?;
+            let cutoff = SystemTime::now()
+                .checked_sub(Duration::from_secs(count.saturating_mul(seconds)))
+                .unwrap_or(UNIX_EPOCH);
+            return Ok(Since { cutoff });
+        }
+        let (date, time) = spec.split_once('T').unwrap_or((spec, "00:00"));
+        let date: Vec<&str> = date.split('-').collect();
+        let time: Vec<&str> = time.split(':').collect();
+        if date.len() != 3 || !(2..=3).contains(&time.len()) {
+            return Err(invalid());
+        }
+        let number = |field: &str| field.parse::<u32>().map_err(|_| invalid());
+        let second = match time.get(2) {
+            Some(second) => number(second)?,
+            None => 0,
+        };
+        let cutoff = utc_time(
+            number(date[0])?,
+            number(date[1])?,
+            number(date[2])?,
+            number(time[0])?,
+            number(time[1])?,
+            second,
+        )
+        .ok_or_else(invalid)?;
+        Ok(Since { cutoff })
+    }
+}
+
+fn is_leap_year(year: u32) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+/// Returns the moment of the given UTC calendar date and time of day, or `None` if it is not a
+/// valid date and time at or after 1970.
+pub(crate) fn utc_time(
+    year: u32,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+) -> Option<SystemTime> {
+    if year < 1970 || !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let days = (1970..year)
+        .map(|y| if is_leap_year(y) { 366 } else { 365 })
+        .sum::<u64>()
+        + (1..month)
+            .map(|m| u64::from(days_in_month(year, m)))
+            .sum::<u64>()
+        + u64::from(day - 1);
+    let seconds = days * 86_400 + u64::from(hour * 3600 + minute * 60 + second);
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utc_time() {
+        let seconds = |time: Option<SystemTime>| {
+            time.map(|time| time.duration_since(UNIX_EPOCH).unwrap().as_secs())
+        };
+        assert_eq!(seconds(utc_time(1970, 1, 1, 0, 0, 0)), Some(0));
+        assert_eq!(seconds(utc_time(2000, 3, 1, 12, 30, 5)), Some(951_913_805));
+        assert_eq!(seconds(utc_time(2024, 2, 29, 0, 0, 0)), Some(1_709_164_800));
+        assert_eq!(utc_time(2023, 2, 29, 0, 0, 0), None);
+        assert_eq!(utc_time(2024, 13, 1, 0, 0, 0), None);
+        assert_eq!(utc_time(1969, 12, 31, 0, 0, 0), None);
+    }
+
+    #[test]
+    fn test_since_from_str() {
+        let since: Since = "2024-05-01".parse().unwrap();
+        assert!(since.admits(utc_time(2024, 5, 1, 0, 0, 0).unwrap()));
+        assert!(!since.admits(utc_time(2024, 4, 30, 23, 59, 59).unwrap()));
+        let since: Since = "2024-05-01T09:15".parse().unwrap();
+        assert!(!since.admits(utc_time(2024, 5, 1, 9, 14, 59).unwrap()));
+        let since: Since = "7d".parse().unwrap();
+        let now = SystemTime::now();
+        assert!(since.admits(now - Duration::from_secs(6 * 86_400)));
+        assert!(!since.admits(now - Duration::from_secs(8 * 86_400)));
+        for invalid in [
+            "",
+            "7y",
+            "d",
+            "2024-05",
+            "2024-02-30",
+            "2024-05-01T25:00",
+            "yesterday",
+        ] {
+            assert!(invalid.parse::<Since>().is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use anyhow::Context;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, XmlVersion};
+use std::io::{BufRead, BufReader, Cursor};
+use zip::ZipArchive;
+
+use crate::reader::RunLocation;
+
+/// The part of a .docx package that holds the main document body.
+const DOCUMENT_PART: &str = "word/document.xml";
+
+/// The kinds of direct run formatting, in the order `run_formatting` gives them.
+const FORMATTING: [&str; 4] = ["bold", "italic", "underline", "strike"];
+
+/// Returns the uncompressed size of the `word/document.xml` part of the .docx package held in
+/// `buffer`, or `None` if it cannot be read.
+pub(crate) fn document_size(buffer: &[u8]) -> Option<u64> {
+    let mut archive = ZipArchive::new(Cursor::new(buffer)).ok()?;
+    let part = archive.by_name(DOCUMENT_PART).ok()?;
+    Some(part.size())
+}
+
+/// Walks the `word/document.xml` part of the .docx package held in `buffer` like `walk_runs`,
+/// but by streaming it through an XML event reader as it is unzipped, rather than by building
+/// the whole document tree first. Only the runs of the paragraph being read are held in memory,
+/// however large the document: the runs of a paragraph, and of the text boxes anchored in it,
+/// are visited when it ends, since its heading text is not known before then.
+///
+/// The runs and their locations are the same as those `walk_runs` visits without table row or
+/// list marker options, as far as `read_docx` and this reader agree on what a document holds:
+/// like `read_docx`, the `mc:Fallback` copies of drawings are skipped, and so are empty `<w:t>`
+/// elements.
+///
+/// # Errors
+///
+/// Will return an error if `buffer` is not a zip archive, if it has no readable
+/// `word/document.xml` part, or if that part is not well-formed XML.
+pub(crate) fn walk_runs(
+    buffer: &[u8],
+    visit: impl FnMut(&str, &RunLocation) -> bool,
+) -> anyhow::Result<()> {
+    let mut archive = ZipArchive::new(Cursor::new(buffer))?;
+    let part = archive
+        .by_name(DOCUMENT_PART)
+        .with_context(|| format!("No {} in package", DOCUMENT_PART))?;
+    walk_xml(BufReader::new(part), visit)
+}
+
+/// A paragraph whose end has not been read yet.
+struct OpenParagraph {
+    /// The index, among the runs waiting to be visited, of the paragraph's first run
+    first_run: usize,
+    /// The text of the paragraph so far, including that of its text boxes
+    text: String,
+    /// True if the paragraph has a heading style (see `is_heading`)
+    is_heading: bool,
+}
+
+/// Walks the runs of the WordprocessingML document read from `xml`, as `walk_runs` does.
+fn walk_xml(
+    xml: impl BufRead,
+    mut visit: impl FnMut(&str, &RunLocation) -> bool,
+) -> anyhow::Result<()> {
+    let mut reader = Reader::from_reader(xml);
+    let mut buf = Vec::new();
+    // the qualified names of the elements enclosing the current event
+    let mut names: Vec<Vec<u8>> = Vec::new();
+    let mut para_index = 0;
+    let mut elements = 0;
+    let mut heading: Option<String> = None;
+    let mut formatting = [false; 4];
+    // the run formatting in force where each open text box starts, to go back to at its end
+    let mut text_boxes: Vec<[bool; 4]> = Vec::new();
+    let mut fallbacks = 0;
+    // the text of the `<w:t>` element being read, if any
+    let mut text: Option<String> = None;
+    let mut paragraphs: Vec<OpenParagraph> = Vec::new();
+    // the runs of the open paragraphs, to be visited when the outermost one ends
+    let mut pending: Vec<(String, RunLocation)> = Vec::new();
+    loop {
+        let event = reader.read_event_into(&mut buf)?;
+        match &event {
+            Event::Start(e) | Event::Empty(e) if fallbacks == 0 => {
+                let parent = names.last().map(Vec::as_slice);
+                let grandparent = names.len().checked_sub(2).map(|i| names[i].as_slice());
+                let is_start = matches!(event, Event::Start(_));
+                match e.name().as_ref() {
+                    b"w:p" => {
+                        // the runs of a text box are in the paragraph it is anchored in
+                        if text_boxes.is_empty() {
+                            para_index += 1;
+                        }
+                        if is_start {
+                            paragraphs.push(OpenParagraph {
+                                first_run: pending.len(),
+                                text: String::new(),
+                                is_heading: false,
+                            });
+                        }
+                    }
+                    b"w:r" => formatting = [false; 4],
+                    b"w:t" if is_start && parent == Some(b"w:r") => text = Some(String::new()),
+                    b"w:txbxContent" if is_start => text_boxes.push(formatting),
+                    b"mc:Fallback" if is_start => fallbacks += 1,
+                    b"w:pStyle" if parent == Some(b"w:pPr") && grandparent == Some(b"w:p") => {
+                        let style = value(e)?.unwrap_or_default();
+                        if let Some(paragraph) = paragraphs.last_mut() {
+                            paragraph.is_heading = style.starts_with("Heading") || style == "Title";
+                        }
+                    }
+                    name if parent == Some(b"w:rPr") && grandparent == Some(b"w:r") => {
+                        let val = value(e)?;
+                        let on = !matches!(val.as_deref(), Some("0" | "false"));
+                        match name {
+                            b"w:b" => formatting[0] = on,
+                            b"w:i" => formatting[1] = on,
+                            b"w:u" => formatting[2] = val.is_some_and(|val| val != "none"),
+                            b"w:strike" => formatting[3] = on,
+                            _ => {}
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::Start(e) if e.name().as_ref() == b"mc:Fallback" => fallbacks += 1,
+            Event::Text(e) => {
+                if let Some(text) = &mut text {
+                    text.push_str(&e.xml10_content()?);
+                }
+            }
+            Event::CData(e) => {
+                if let Some(text) = &mut text {
+                    text.push_str(&e.decode()?);
+                }
+            }
+            Event::GeneralRef(e) => {
+                if let Some(text) = &mut text {
+                    let name = e.decode()?;
+                    match e.resolve_char_ref()? {
+                        Some(c) => text.push(c),
+                        None => text.push_str(
+                            quick_xml::escape::resolve_predefined_entity(&name)
+                                .with_context(|| format!("Unknown entity &{};", name))?,
+                        ),
+                    }
+                }
+            }
+            Event::End(e) => match e.name().as_ref() {
+                b"w:t" if fallbacks == 0 => {
+                    if let Some(text) = text.take().filter(|text| !text.is_empty()) {
+                        let location = RunLocation {
+                            para_index,
+                            heading: heading.clone(),
+                            element_index: Some(elements),
+                            formatting: FORMATTING
+                                .into_iter()
+                                .zip(formatting)
+                                .filter_map(|(name, on)| on.then_some(name))
+                                .collect(),
+                            text_box: !text_boxes.is_empty(),
+                            author: None,
+                        };
+                        elements += 1;
+                        for paragraph in &mut paragraphs {
+                            paragraph.text.push_str(&text);
+                        }
+                        if paragraphs.is_empty() {
+                            if !visit(&text, &location) {
+                                return Ok(());
+                            }
+                        } else {
+                            pending.push((text, location));
+                        }
+                    }
+                }
+                b"w:p" if fallbacks == 0 => {
+                    if let Some(paragraph) = paragraphs.pop() {
+                        if paragraph.is_heading && !paragraph.text.is_empty() {
+                            for (_, location) in &mut pending[paragraph.first_run..] {
+                                location.heading = Some(paragraph.text.clone());
+                            }
+                            heading = Some(paragraph.text);
+                        }
+                    }
+                    if paragraphs.is_empty() {
+                        for (text, location) in pending.drain(..) {
+                            if !visit(&text, &location) {
+                                return Ok(());
+                            }
+                        }
+                    }
+                }
+                b"w:txbxContent" if fallbacks == 0 => {
+                    formatting = text_boxes.pop().unwrap_or_default();
+                }
+                b"mc:Fallback" => fallbacks -= 1,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        match event {
+            Event::Start(e) => names.push(e.name().as_ref().to_vec()),
+            Event::End(_) => {
+                names.pop();
+            }
+            _ => {}
+        }
+        buf.clear();
+    }
+    for (text, location) in pending {
+        if !visit(&text, &location) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the `w:val` attribute of the element `e`, if it has one.
+fn value(e: &BytesStart) -> anyhow::Result<Option<String>> {
+    Ok(match e.try_get_attribute("w:val")? {
+        Some(attr) => Some(attr.normalized_value(XmlVersion::Implicit1_0)?.into_owned()),
+        None => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn walk(xml: &str) -> Vec<(String, RunLocation)> {
+        let mut runs = Vec::new();
+        walk_xml(xml.as_bytes(), |text, location| {
+            runs.push((text.to_string(), location.clone()));
+            true
+        })
+        .unwrap();
+        runs
+    }
+
+    #[test]
+    fn test_walk_xml() {
+        let runs = walk(
+            r#"<w:document><w:body>
+            <w:p><w:pPr><w:pStyle w:val="Heading1"/><w:rPr><w:b/></w:rPr></w:pPr>
+                <w:r><w:t>Intro</w:t></w:r><w:r><w:t>duction</w:t></w:r></w:p>
+            <w:p><w:r><w:rPr><w:b/><w:i w:val="0"/><w:u w:val="single"/></w:rPr>
+                <w:t xml:space="preserve">Tom &amp; Jerry&#x21; </w:t><w:t/></w:r>
+                <w:r><w:drawing><mc:AlternateContent><mc:Choice><w:txbxContent>
+                    <w:p><w:r><w:t>boxed</w:t></w:r></w:p>
+                </w:txbxContent></mc:Choice><mc:Fallback><w:txbxContent>
+                    <w:p><w:r><w:t>boxed</w:t></w:r></w:p>
+                </w:txbxContent></mc:Fallback></mc:AlternateContent></w:drawing></w:r>
+                <w:r><w:t>after</w:t></w:r></w:p>
+            </w:body></w:document>"#,
+        );
+        let texts: Vec<&str> = runs.iter().map(|(text, _)| text.as_str()).collect();
+        assert_eq!(
+            texts,
+            ["Intro", "duction", "Tom & Jerry! ", "boxed", "after"]
+        );
+        let heading = Some("Introduction".to_string());
+        assert!(runs.iter().all(|(_, location)| location.heading == heading));
+        let (_, location) = &runs[2];
+        assert_eq!((location.para_index, location.element_index), (2, Some(2)));
+        assert_eq!(location.formatting, ["bold", "underline"]);
+        assert!(!location.text_box);
+        let (_, location) = &runs[3];
+        assert_eq!(location.para_index, 2);
+        assert!(location.text_box);
+        assert!(location.formatting.is_empty());
+        let (_, location) = &runs[4];
+        assert_eq!((location.para_index, location.element_index), (2, Some(4)));
+        assert!(!location.text_box);
+    }
+}
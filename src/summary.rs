@@ -0,0 +1,346 @@
+use anyhow::Context;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::matcher::Searcher;
+use crate::selector::glob_pattern;
+
+/// Where `--count-unique` counts distinct matched strings.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum UniqueScope {
+    /// Once across all the documents searched, in the footer
+    Corpus,
+    /// For each document, under its matches
+    File,
+}
+
+/// Counts and file lists gathered while searching, returned by `process_files` so the
+/// caller can decide how to report them.
+#[derive(Debug, Default)]
+pub struct ScanSummary {
+    /// Names of the loose .docx (and .docx.zst) files that were found
+    pub docx_fnames: Vec<String>,
+    /// Names of the zip archives that were found
+    pub zip_fnames: Vec<String>,
+    /// Names of the .tar.zst archives that were found
+    pub tar_fnames: Vec<String>,
+    /// The extensions of the files looked for (see `SearchOptions::searched_extensions`)
+    pub extensions: Vec<String>,
+    /// Number of documents searched, counting each .docx entry of an archive separately
+    pub files_searched: usize,
+    /// Number of documents with at least one matching run
+    pub files_matched: usize,
+    /// Number of documents that could not be searched
+    pub files_errored: usize,
+    /// Number of documents left out for having fewer matches than `--min-matches`
+    pub files_below_min_matches: usize,
+    /// Total number of regex matches across all documents
+    pub total_matches: usize,
+    /// Names of the documents that exceeded the per-file timeout
+    pub timed_out: Vec<String>,
+    /// Names of the documents searched that have no text, such as scans saved as images
+    pub without_text: Vec<String>,
+    /// Wall-clock time taken by the scan
+    pub elapsed: Duration,
+    /// Total length of the .docx packages searched, once taken out of any archives they are in
+    pub bytes_scanned: u64,
+    /// Names of the documents searched, keyed by a hash of their text (only with `--dedupe`)
+    pub content_groups: BTreeMap<u64, Vec<String>>,
+    /// Number of matched and of searched documents in each directory, counting the entries of
+    /// an archive as a directory of their own
+    pub coverage: BTreeMap<String, (usize, usize)>,
+    /// The distinct strings matched across all documents, sorted (only with `--count-unique`)
+    pub unique_matches: Option<BTreeSet<String>>,
+}
+
+impl ScanSummary {
+    /// Returns the groups of two or more documents whose text is identical, each sorted by name.
+    pub fn duplicate_groups(&self) -> Vec<Vec<String>> {
+        let mut groups: Vec<Vec<String>> = self
+            .content_groups
+            .values()
+            .filter(|names| names.len() > 1)
+            .cloned()
+            .collect();
+        groups.iter_mut().for_each(|names| names.sort());
+        groups.sort();
+        groups
+    }
+
+    /// Returns the summary, and the parameters of the search it describes, as a JSON object.
+    /// Its `unique_matches` is null unless distinct matches were counted across all documents.
+    pub fn to_json(&self, searcher: &Searcher, base_dir: &str) -> Value {
+        json!({
+            "docx_files": self.docx_fnames.len(),
+            "zip_archives": self.zip_fnames.len(),
+            "tar_archives": self.tar_fnames.len(),
+            "files_searched": self.files_searched,
+            "files_matched": self.files_matched,
+            "files_errored": self.files_errored,
+            "files_below_min_matches": self.files_below_min_matches,
+            "files_timed_out": self.timed_out.len(),
+            "files_without_text": self.without_text.len(),
+            "total_matches": self.total_matches,
+            "unique_matches": self.unique_matches.as_ref().map(BTreeSet::len),
+            "elapsed_secs": self.elapsed.as_secs_f64(),
+            "bytes_scanned": self.bytes_scanned,
+            "megabytes_per_sec": self.throughput(),
+            "parameters": {
+                "pattern": searcher.to_string(),
+                "base_dir": base_dir,
+            },
+        })
+    }
+
+    /// Returns the number of megabytes (of 10^6 bytes) of documents searched per second of
+    /// the scan, or `None` if it took no measurable time.
+    pub fn throughput(&self) -> Option<f64> {
+        let secs = self.elapsed.as_secs_f64();
+        (secs > 0.0).then(|| self.bytes_scanned as f64 / 1e6 / secs)
+    }
+
+    /// Prints the number of bytes of documents searched and the rate they were searched at,
+    /// for `--verbose`.
+    pub fn print_throughput(&self) {
+        let rate = match self.throughput() {
+            Some(rate) => format!("{:.2} MB/s", rate),
+            None => "-".to_string(),
+        };
+        println!(
+            "  Scanned {} bytes in {:.3}s ({})\n",
+            self.bytes_scanned,
+            self.elapsed.as_secs_f64(),
+            rate
+        );
+    }
+
+    /// Writes the JSON form of the summary (see `to_json`) to the file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the file cannot be written.
+    pub fn write_json(
+        &self,
+        path: &Path,
+        searcher: &Searcher,
+        base_dir: &str,
+    ) -> anyhow::Result<()> {
+        let json = self.to_json(searcher, base_dir).to_string();
+        std::fs::write(path, json + "\n")
+            .with_context(|| format!("Failed to write stats: {}", path.display()))
+    }
+
+    /// Returns `(directory, matched, searched)` for each directory that documents were searched
+    /// in, least covered first: by the fraction of its documents that matched, then by name.
+    pub fn coverage_by_directory(&self) -> Vec<(String, usize, usize)> {
+        let mut rows: Vec<(String, usize, usize)> = self
+            .coverage
+            .iter()
+            .map(|(dir, &(matched, searched))| (dir.clone(), matched, searched))
+            .collect();
+        // compare matched/searched fractions without dividing
+        rows.sort_by(|a, b| (a.1 * b.2).cmp(&(b.1 * a.2)).then_with(|| a.0.cmp(&b.0)));
+        rows
+    }
+
+    /// Prints a table of the fraction of documents in each directory that matched, least
+    /// covered first.
+    pub fn print_coverage(&self) {
+        println!("Coverage by directory:\n");
+        for (dir, matched, searched) in self.coverage_by_directory() {
+            let percent = 100.0 * matched as f64 / searched as f64;
+            let dir = if dir.is_empty() { "." } else { dir.as_str() };
+            println!(
+                "  {:>5.1}%  {:>5}/{:<5}  {}",
+                percent, matched, searched, dir
+            );
+        }
+        println!();
+    }
+
+    /// Prints the distinct strings matched across all documents, in sorted order, if they were
+    /// collected.
+    pub fn print_unique_matches(&self) {
+        if let Some(unique) = &self.unique_matches {
+            println!("Unique matches:\n");
+            for text in unique {
+                println!("  {}", text);
+            }
+            println!();
+        }
+    }
+
+    /// Returns the message to show when not a single document or archive was found under
+    /// `base_dir`, naming the glob patterns of `extensions` that were tried, or `None` if
+    /// anything was found.
+    pub fn no_files_message(&self, base_dir: &str) -> Option<String> {
+        if !self.docx_fnames.is_empty()
+            || !self.zip_fnames.is_empty()
+            || !self.tar_fnames.is_empty()
+        {
+            return None;
+        }
+        let (last, others) = self.extensions.split_last()?;
+        let kinds = if others.is_empty() {
+            last.clone()
+        } else {
+            format!("{} or {}", others.join(", "), last)
+        };
+        let patterns: Vec<String> = self
+            .extensions
+            .iter()
+            .map(|ext| glob_pattern(base_dir, ext))
+            .collect();
+        Some(format!(
+            "No {} files found under {} (searched patterns: {})\n\
+             Check that the path given to --dir is right, that --no-archives or \
+             --archives-only are not leaving out the files you meant to search, and that \
+             --include-ext names the extensions of any others.",
+            kinds,
+            base_dir,
+            patterns.join(", ")
+        ))
+    }
+
+    /// Prints the footer that follows the search results.
+    ///
+    /// # Arguments
+    ///
+    /// * `searcher` - The `Searcher` that was searched with.
+    /// * `base_dir` - The directory or file name the search started from.
+    /// * `list_files` - If true, the name of every file and archive searched is listed as well.
+    pub fn print_footer(&self, searcher: &Searcher, base_dir: &str, list_files: bool) {
+        let nfiles = self.docx_fnames.len();
+        let nzips = self.zip_fnames.len();
+        let fileword = if nfiles == 1 { "file" } else { "files" };
+        let zipword = if nzips == 1 {
+            "zip archive"
+        } else {
+            "zip archives"
+        };
+        println!("Searched {nfiles} {fileword} amd {nzips} {zipword}\n");
+        if !self.tar_fnames.is_empty() {
+            println!("Searched {} tar.zst archive(s)\n", self.tar_fnames.len());
+        }
+        println!(
+            "  Matches: {} in {} of {} documents ({} errors)\n",
+            self.total_matches, self.files_matched, self.files_searched, self.files_errored
+        );
+        if self.files_below_min_matches > 0 {
+            let documentword = if self.files_below_min_matches == 1 {
+                "document"
+            } else {
+                "documents"
+            };
+            println!(
+                "  Left out {} {} with fewer matches than --min-matches\n",
+                self.files_below_min_matches, documentword
+            );
+        }
+        if !self.without_text.is_empty() {
+            let documentword = if self.without_text.len() == 1 {
+                "document"
+            } else {
+                "documents"
+            };
+            println!(
+                "  No extractable text in {} {} (possibly image-only)\n",
+                self.without_text.len(),
+                documentword
+            );
+        }
+        if let Some(unique) = &self.unique_matches {
+            println!("  Unique matches: {}\n", unique.len());
+        }
+        println!(
+            "  Search parameters: regex: {}, base_path={:#?}\n\n",
+            searcher, base_dir
+        );
+        if list_files {
+            for fname in &self.docx_fnames {
+                println!("Searched docx file  {}", fname);
+            }
+            for fname in &self.zip_fnames {
+                println!("Searched zip archive  {}", fname);
+            }
+            for fname in &self.tar_fnames {
+                println!("Searched tar.zst archive  {}", fname);
+            }
+        }
+        for fname in &self.timed_out {
+            println!("Timed out  {}", fname);
+        }
+        for names in self.duplicate_groups() {
+            println!("\nIdentical text in {} documents:", names.len());
+            for name in names {
+                println!("    {}", name);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::SearchOptions;
+    use regex::Regex;
+
+    #[test]
+    fn test_to_json() {
+        let summary = ScanSummary {
+            docx_fnames: vec!["a.docx".to_string(), "b.docx".to_string()],
+            zip_fnames: vec!["c.zip".to_string()],
+            files_searched: 3,
+            files_matched: 1,
+            files_errored: 1,
+            total_matches: 7,
+            elapsed: Duration::from_millis(1500),
+            bytes_scanned: 3_000_000,
+            ..Default::default()
+        };
+        let searcher = Searcher::from(Regex::new("[Hh]ello").unwrap());
+        let json = summary.to_json(&searcher, "docs");
+        assert_eq!(json["files_searched"], 3);
+        assert_eq!(json["files_matched"], 1);
+        assert_eq!(json["files_errored"], 1);
+        assert_eq!(json["total_matches"], 7);
+        assert_eq!(json["zip_archives"], 1);
+        assert_eq!(json["elapsed_secs"], 1.5);
+        assert_eq!(json["bytes_scanned"], 3_000_000);
+        assert_eq!(json["megabytes_per_sec"], 2.0);
+        assert_eq!(json["parameters"]["pattern"], "[Hh]ello");
+        assert_eq!(json["parameters"]["base_dir"], "docs");
+    }
+
+    #[test]
+    fn test_no_files_message() {
+        let opts = SearchOptions {
+            include_ext: vec![".dotx".parse().unwrap()],
+            ..Default::default()
+        };
+        let summary = ScanSummary {
+            extensions: opts.searched_extensions(),
+            ..Default::default()
+        };
+        let message = summary.no_files_message("docs/").unwrap();
+        assert!(message.starts_with(
+            "No .docx, .zip, .docx.zst, .tar.zst or .dotx files found under docs/ (searched \
+             patterns: docs/**/*.docx, docs/**/*.zip, docs/**/*.docx.zst, docs/**/*.tar.zst, \
+             docs/**/*.dotx)"
+        ));
+        assert!(message.contains("--include-ext"));
+        let summary = ScanSummary {
+            extensions: vec![".zip".to_string()],
+            ..Default::default()
+        };
+        let message = summary.no_files_message("docs").unwrap();
+        assert!(message.starts_with("No .zip files found under docs (searched patterns: "));
+        let summary = ScanSummary {
+            tar_fnames: vec!["a.tar.zst".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(summary.no_files_message("docs"), None);
+    }
+}
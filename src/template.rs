@@ -0,0 +1,172 @@
+use std::str::FromStr;
+
+/// A field of a match that a `--format` template can refer to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    File,
+    Archive,
+    Para,
+    Pre,
+    Match,
+    Post,
+    Count,
+}
+
+impl Field {
+    const NAMES: [(&'static str, Field); 7] = [
+        ("file", Field::File),
+        ("archive", Field::Archive),
+        ("para", Field::Para),
+        ("pre", Field::Pre),
+        ("match", Field::Match),
+        ("post", Field::Post),
+        ("count", Field::Count),
+    ];
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Literal(String),
+    Field(Field),
+}
+
+/// A template for the line printed for each match, parsed from `--format`. Placeholders in
+/// braces, such as `{file}` or `{match}`, are replaced by the fields of the match; `{{` and `}}`
+/// stand for literal braces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+/// The values that a `Template` fills in for one match.
+#[derive(Debug, Default)]
+pub(crate) struct MatchFields<'a> {
+    /// Path of the document, with archive entries under their archive
+    pub(crate) file: &'a str,
+    /// Name of the archive holding the document, or "" for a loose file
+    pub(crate) archive: &'a str,
+    /// 1-based index of the paragraph holding the match, or 0 if it is not known
+    pub(crate) para: usize,
+    pub(crate) pre: &'a str,
+    pub(crate) matched: &'a str,
+    pub(crate) post: &'a str,
+    /// 1-based number of the match within its document
+    pub(crate) count: usize,
+}
+
+impl FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> anyhow::Result<Self> {
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut rest = spec;
+        while let Some(i) = rest.find(['{', '}']) {
+            literal.push_str(&rest[..i]);
+            rest = &rest[i..];
+            if let Some(after) = rest.strip_prefix("{{") {
+                literal.push('{');
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix("}}") {
+                literal.push('}');
+                rest = after;
+            } else if rest.starts_with('}') {
+                anyhow::bail!("Invalid format '{}': unmatched '}}' (use '}}}}')", spec);
+            } else {
+                let close = rest.find('}').ok_or_else(|| {
+                    anyhow::anyhow!("Invalid format '{}': unclosed '{{' (use '{{{{')", spec)
+                })?;
+                let name = &rest[1..close];
+                let field = Field::NAMES
+                    .iter()
+                    .find(|(known, _)| *known == name)
+                    .map(|&(_, field)| field)
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "Invalid format '{}': unknown placeholder '{{{}}}', expected one of {}",
+                            spec,
+                            name,
+                            Field::NAMES
+                                .iter()
+                                .map(|(known, _)| format!("{{{}}}", known))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )
+                    })?;
+                if !literal.is_empty() {
+                    pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                }
+                pieces.push(Piece::Field(field));
+                rest = &rest[close + 1..];
+            }
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+        Ok(Template { pieces })
+    }
+}
+
+impl Template {
+    /// Returns the template with its placeholders replaced by the values in `fields`.
+    pub(crate) fn expand(&self, fields: &MatchFields) -> String {
+        let mut out = String::new();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Literal(text) => out.push_str(text),
+                Piece::Field(Field::File) => out.push_str(fields.file),
+                Piece::Field(Field::Archive) => out.push_str(fields.archive),
+                Piece::Field(Field::Para) => out.push_str(&fields.para.to_string()),
+                Piece::Field(Field::Pre) => out.push_str(fields.pre),
+                Piece::Field(Field::Match) => out.push_str(fields.matched),
+                Piece::Field(Field::Post) => out.push_str(fields.post),
+                Piece::Field(Field::Count) => out.push_str(&fields.count.to_string()),
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let template: Template = "{file}:{para}: {{{match}}} #{count}{archive}"
+            .parse()
+            .unwrap();
+        let fields = MatchFields {
+            file: "docs/a.docx",
+            para: 12,
+            matched: "hello",
+            count: 3,
+            ..Default::default()
+        };
+        assert_eq!(template.expand(&fields), "docs/a.docx:12: {hello} #3");
+        let template: Template = "{pre}[{match}]{post}".parse().unwrap();
+        let fields = MatchFields {
+            pre: "say ",
+            matched: "hi",
+            post: " now",
+            ..Default::default()
+        };
+        assert_eq!(template.expand(&fields), "say [hi] now");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = "{file}:{line}".parse::<Template>().unwrap_err();
+        assert!(err.to_string().contains("unknown placeholder '{line}'"));
+        assert!("{file".parse::<Template>().is_err());
+        assert!("file}".parse::<Template>().is_err());
+        assert_eq!(
+            "plain"
+                .parse::<Template>()
+                .unwrap()
+                .expand(&MatchFields::default()),
+            "plain"
+        );
+    }
+}
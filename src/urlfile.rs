@@ -0,0 +1,209 @@
+use crate::reader::FileLike;
+
+/// Returns true if `target` names an HTTP(S) resource rather than a local path.
+pub fn is_url(target: &str) -> bool {
+    target.starts_with("http://") || target.starts_with("https://")
+}
+
+/// The most that is read of the response to a request when `--max-file-size` is not given.
+#[cfg(feature = "http")]
+const MAX_RESPONSE_SIZE: u64 = 1 << 30;
+
+/// Creates the file surrogate for the document at `url`, which is not read beyond `max_size`
+/// bytes, or `MAX_RESPONSE_SIZE` if it is `None`.
+///
+/// # Errors
+///
+/// Will return an error if docread was built without the `http` feature.
+#[cfg(feature = "http")]
+pub(crate) fn url_surrogate(url: &str, max_size: Option<u64>) -> anyhow::Result<FileLike> {
+    Ok(std::sync::Arc::new(http::UrlFile {
+        url: url.to_string(),
+        max_size: max_size.unwrap_or(MAX_RESPONSE_SIZE),
+    }))
+}
+
+#[cfg(not(feature = "http"))]
+pub(crate) fn url_surrogate(url: &str, _max_size: Option<u64>) -> anyhow::Result<FileLike> {
+    anyhow::bail!(
+        "Cannot search {}: docread was built without the `http` feature",
+        url
+    )
+}
+
+#[cfg(feature = "http")]
+mod http {
+    use anyhow::Context;
+    use std::io::{Read, Write};
+    use std::net::{TcpStream, ToSocketAddrs};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use crate::reader::{FileSize, ReadIntoBuf};
+
+    /// How long to wait for a connection, and for each read or write on it.
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// The most that is read of the status line and headers of a response, besides its body.
+    const MAX_HEAD_SIZE: u64 = 64 * 1024;
+
+    /// A .docx document fetched over HTTP. Requests are plain HTTP/1.0 over a `TcpStream`, so
+    /// that the feature adds no dependencies; `https://` URLs are rejected since there is no TLS.
+    #[derive(Debug)]
+    pub(crate) struct UrlFile {
+        pub url: String,
+        /// The largest body that is read; a longer one is an error
+        pub max_size: u64,
+    }
+
+    impl ReadIntoBuf for UrlFile {
+        fn read_into_buf(&self) -> anyhow::Result<Vec<u8>> {
+            fetch(&self.url, self.max_size)
+        }
+
+        fn get_fname(&self) -> String {
+            self.url.clone()
+        }
+
+        fn get_size(&self) -> anyhow::Result<FileSize> {
+            anyhow::bail!("Size of {} is not known before it is fetched", self.url)
+        }
+
+        fn get_archive_name(&self) -> Option<String> {
+            None
+        }
+
+        fn get_source_path(&self) -> PathBuf {
+            PathBuf::from(
+                self.url
+                    .split_once("://")
+                    .map_or(&*self.url, |(_, rest)| rest),
+            )
+        }
+    }
+
+    /// Splits an `http://` URL into its host, port and path.
+    fn split_url(url: &str) -> anyhow::Result<(String, u16, String)> {
+        if url.starts_with("https://") {
+            anyhow::bail!("Cannot fetch {}: https is not supported, only http", url);
+        }
+        let rest = url
+            .strip_prefix("http://")
+            .with_context(|| format!("Not an http URL: {}", url))?;
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => (&rest[..slash], &rest[slash..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse::<u16>()
+                    .with_context(|| format!("Invalid port in URL: {}", url))?,
+            ),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            anyhow::bail!("Missing host in URL: {}", url);
+        }
+        Ok((host.to_string(), port, path.to_string()))
+    }
+
+    /// Fetches the body of `url` with a GET request, reading no more than `max_size` bytes of
+    /// it.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the host cannot be reached, the request times out, the
+    /// response status is anything other than 200, or the body is longer than `max_size`.
+    fn fetch(url: &str, max_size: u64) -> anyhow::Result<Vec<u8>> {
+        let (host, port, path) = split_url(url)?;
+        let addr = (host.as_str(), port)
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to resolve host: {}", host))?
+            .next()
+            .with_context(|| format!("No address found for host: {}", host))?;
+        let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)
+            .with_context(|| format!("Failed to connect to {}", url))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        write!(
+            stream,
+            "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: docread/{}\r\nConnection: close\r\n\r\n",
+            path,
+            host,
+            env!("CARGO_PKG_VERSION")
+        )?;
+        let mut response = Vec::new();
+        // one byte over the limit is enough to tell that the body is too long
+        stream
+            .take(MAX_HEAD_SIZE + max_size + 1)
+            .read_to_end(&mut response)
+            .with_context(|| format!("Failed to read response from {}", url))?;
+        let header_end = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .with_context(|| format!("Malformed HTTP response from {}", url))?;
+        let head = String::from_utf8_lossy(&response[..header_end]);
+        let status = head
+            .lines()
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .with_context(|| format!("Malformed HTTP status line from {}", url))?;
+        if status != "200" {
+            anyhow::bail!("GET {} returned HTTP status {}", url, status);
+        }
+        let body = &response[header_end + 4..];
+        if body.len() as u64 > max_size {
+            anyhow::bail!(
+                "Response from {} is larger than {} bytes (see --max-file-size)",
+                url,
+                max_size
+            );
+        }
+        Ok(body.to_vec())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::net::TcpListener;
+
+        /// Serves a single canned HTTP response on a local port, returning the URL to fetch.
+        fn serve_once(response: &'static [u8]) -> String {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            std::thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request);
+                stream.write_all(response).unwrap();
+            });
+            format!("http://127.0.0.1:{}/doc.docx", port)
+        }
+
+        #[test]
+        fn test_split_url() {
+            assert_eq!(
+                split_url("http://example.com:8080/a/b.docx").unwrap(),
+                ("example.com".to_string(), 8080, "/a/b.docx".to_string())
+            );
+            assert_eq!(
+                split_url("http://example.com").unwrap(),
+                ("example.com".to_string(), 80, "/".to_string())
+            );
+            assert!(split_url("https://example.com/doc.docx").is_err());
+        }
+
+        #[test]
+        fn test_fetch() {
+            let url = serve_once(b"HTTP/1.0 200 OK\r\nContent-Length: 5\r\n\r\nhello");
+            assert_eq!(fetch(&url, 5).unwrap(), b"hello");
+            let url = serve_once(b"HTTP/1.0 200 OK\r\nContent-Length: 5\r\n\r\nhello");
+            let err = fetch(&url, 4).unwrap_err();
+            assert!(err.to_string().contains("is larger than 4 bytes"));
+            let url = serve_once(b"HTTP/1.0 404 Not Found\r\n\r\n");
+            let err = fetch(&url, 5).unwrap_err();
+            assert!(err.to_string().ends_with("returned HTTP status 404"));
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+use crate::selector::make_fnames;
+
+/// How often the directory tree is polled for changes.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The modification time and length of each file searched under a directory.
+type Snapshot = BTreeMap<String, (Option<SystemTime>, u64)>;
+
+/// Records the modification time and length of every file in `base_dir` and its
+/// subdirectories whose name ends with one of `extensions`. Files that vanish while the
+/// snapshot is taken are left out.
+fn snapshot(base_dir: &str, extensions: &[String]) -> anyhow::Result<Snapshot> {
+    let mut files = Snapshot::new();
+    for suffix in extensions {
+        for fname in make_fnames(base_dir, suffix)?.fnames {
+            if let Ok(metadata) = std::fs::metadata(&fname) {
+                files.insert(fname, (metadata.modified().ok(), metadata.len()));
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Blocks until a file with one of `extensions` in `base_dir` or its subdirectories is added,
+/// removed or modified, polling every `poll`. Once a change is seen, waits for the tree to stay unchanged
+/// for a further `poll`, so that a burst of changes (such as a save that writes several files)
+/// is reported once.
+///
+/// # Errors
+///
+/// Will return an error if the directory tree cannot be listed.
+pub(crate) fn wait_for_change(
+    base_dir: &str,
+    extensions: &[String],
+    poll: Duration,
+) -> anyhow::Result<()> {
+    let before = snapshot(base_dir, extensions)?;
+    let mut last = loop {
+        std::thread::sleep(poll);
+        let now = snapshot(base_dir, extensions)?;
+        if now != before {
+            break now;
+        }
+    };
+    loop {
+        std::thread::sleep(poll);
+        let now = snapshot(base_dir, extensions)?;
+        if now == last {
+            return Ok(());
+        }
+        last = now;
+    }
+}
+
+/// Calls `run`, then calls it again each time the files in `base_dir` with one of
+/// `extensions`, those searched (see `SearchOptions::searched_extensions`), change, clearing
+/// the screen before each run if `clear_screen` is set. An error from `run` is printed and
+/// watching carries on. The note that it is watching goes to standard error, so
+/// that without clearing, as with `--json`, standard output holds only what `run` writes.
+/// Only returns if the directory tree cannot be listed; otherwise it runs until the process
+/// is interrupted.
+///
+/// # Errors
+///
+/// Will return an error if the directory tree cannot be listed.
+pub fn watch(
+    base_dir: &str,
+    extensions: &[String],
+    clear_screen: bool,
+    mut run: impl FnMut() -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    loop {
+        if clear_screen {
+            // clear the screen and move the cursor to the top left
+            print!("\x1b[2J\x1b[H");
+        }
+        if let Err(e) = run() {
+            eprintln!("{:?}", e);
+        }
+        eprintln!("Watching {} for changes (Ctrl-C to exit)", base_dir);
+        wait_for_change(base_dir, extensions, POLL_INTERVAL)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::SearchOptions;
+
+    #[test]
+    fn test_snapshot() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let base_dir = dir.path().to_str().unwrap();
+        let opts = SearchOptions {
+            include_ext: vec![".dotx".parse()?],
+            ..Default::default()
+        };
+        let extensions = opts.searched_extensions();
+        std::fs::write(dir.path().join("a.docx"), "a")?;
+        std::fs::write(dir.path().join("notes.txt"), "ignored")?;
+        let mut files = snapshot(base_dir, &extensions)?;
+        assert_eq!(files.len(), 1);
+        for name in ["b.zip", "c.tar.zst", "d.docx.zst", "e.dotx"] {
+            std::fs::write(dir.path().join(name), "new")?;
+            let now = snapshot(base_dir, &extensions)?;
+            assert_ne!(now, files, "{}", name);
+            files = now;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_wait_for_change() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("new.docx");
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            std::fs::write(path, "new").unwrap();
+        });
+        let extensions = [".docx".to_string()];
+        wait_for_change(
+            dir.path().to_str().unwrap(),
+            &extensions,
+            Duration::from_millis(20),
+        )?;
+        writer.join().unwrap();
+        Ok(())
+    }
+}
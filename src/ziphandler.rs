@@ -1,19 +1,27 @@
 use std::fs::File;
+use std::time::SystemTime;
 
 use zip::ZipArchive;
 
+use crate::since::utc_time;
+
 #[derive(Debug)]
 pub(crate) struct ZipEntry {
     pub(crate) archive_name: String,
     pub(crate) entry_name: String,
+    pub(crate) compressed_size: u64,
+    pub(crate) uncompressed_size: u64,
+    /// Modification time recorded for the entry in the archive, if it is a valid date
+    pub(crate) last_modified: Option<SystemTime>,
 }
 
 /// Returns a vector of `ZipEntry` objects, each representing a .docx file within
 /// the given zip archive. This function reads the zip archive and extracts the
 /// file names of all .docx files within it, and builds a vector of `ZipEntry`
-/// objects, each containing the name of the archive and the name of the .docx
-/// file. The result is a vector of `ZipEntry` objects, which can be used to
-/// process the .docx files within the archive.
+/// objects, each containing the name of the archive, the name of the .docx
+/// file and its compressed and uncompressed sizes as recorded in the archive. The
+/// result is a vector of `ZipEntry` objects, which can be used to process the .docx
+/// files within the archive.
 ///
 /// # Arguments
 ///
@@ -37,6 +45,18 @@ pub(crate) fn zip_to_zipentries(zip_path: &str) -> anyhow::Result<Vec<ZipEntry>>
             let zip_entry = ZipEntry {
                 archive_name: zip_path.to_string(),
                 entry_name: file_name.to_string(),
+                compressed_size: file.compressed_size(),
+                uncompressed_size: file.size(),
+                last_modified: file.last_modified().and_then(|time| {
+                    utc_time(
+                        time.year().into(),
+                        time.month().into(),
+                        time.day().into(),
+                        time.hour().into(),
+                        time.minute().into(),
+                        time.second().into(),
+                    )
+                }),
             };
             zipentries.push(zip_entry);
         }
@@ -44,8 +64,8 @@ pub(crate) fn zip_to_zipentries(zip_path: &str) -> anyhow::Result<Vec<ZipEntry>>
 
     Ok(zipentries)
 }
-#[cfg(test)]
 
+#[cfg(test)]
 mod tests {
     use super::*;
     use std::io::Write;
@@ -70,7 +90,9 @@ mod tests {
         let options =
             SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
 
-        zip.start_file("test1.docx", options)?;
+        let dated = options
+            .last_modified_time(zip::DateTime::from_date_and_time(2024, 5, 1, 9, 30, 0).unwrap());
+        zip.start_file("test1.docx", dated)?;
         zip.write_all(b"Hello, world!")?;
 
         zip.start_file("test2.txt", options)?;
@@ -86,6 +108,9 @@ mod tests {
         assert_eq!(docx_files.len(), 2);
         assert_eq!(docx_files[0].entry_name, "test1.docx");
         assert_eq!(docx_files[1].entry_name, "test3.docx");
+        assert_eq!(docx_files[0].uncompressed_size, 13);
+        assert_eq!(docx_files[0].compressed_size, 13);
+        assert_eq!(docx_files[0].last_modified, utc_time(2024, 5, 1, 9, 30, 0));
 
         Ok(())
     }
@@ -0,0 +1,261 @@
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::reader::{modified_time, FileLike, FileSize, ReadIntoBuf};
+
+/// Size of a tar header, and the unit in which entry data is padded.
+const TAR_BLOCK: usize = 512;
+
+/// A zstandard-compressed single .docx file (`doc.docx.zst`).
+#[derive(Debug)]
+pub(crate) struct ZstFile {
+    fname: String,
+}
+
+impl ReadIntoBuf for ZstFile {
+    fn read_into_buf(&self) -> anyhow::Result<Vec<u8>> {
+        decompress(&self.fname)
+    }
+
+    fn get_fname(&self) -> String {
+        self.fname.clone()
+    }
+
+    fn get_size(&self) -> anyhow::Result<FileSize> {
+        let metadata = std::fs::metadata(&self.fname)
+            .with_context(|| format!("Failed to read metadata: {}", self.fname))?;
+        Ok(FileSize::OnDisk(metadata.len()))
+    }
+
+    fn get_modified(&self) -> anyhow::Result<SystemTime> {
+        modified_time(&self.fname)
+    }
+
+    fn get_archive_name(&self) -> Option<String> {
+        None
+    }
+
+    fn get_source_path(&self) -> PathBuf {
+        PathBuf::from(&self.fname)
+    }
+}
+
+/// A .docx entry of a zstandard-compressed tar archive (`docs.tar.zst`). The archive is
+/// decompressed once, and its entries share the result.
+#[derive(Debug)]
+pub(crate) struct TarZstEntry {
+    archive_name: String,
+    /// The whole decompressed archive
+    tar: Arc<[u8]>,
+    entry: TarEntry,
+}
+
+impl ReadIntoBuf for TarZstEntry {
+    fn read_into_buf(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(self.tar[self.entry.start..self.entry.start + self.entry.size].to_vec())
+    }
+
+    fn get_fname(&self) -> String {
+        format!("File: {} in {}", self.entry.name, self.archive_name)
+    }
+
+    fn get_size(&self) -> anyhow::Result<FileSize> {
+        Ok(FileSize::OnDisk(self.entry.size as u64))
+    }
+
+    fn get_modified(&self) -> anyhow::Result<SystemTime> {
+        Ok(self.entry.modified)
+    }
+
+    fn get_archive_name(&self) -> Option<String> {
+        Some(self.archive_name.clone())
+    }
+
+    fn get_source_path(&self) -> PathBuf {
+        Path::new(&self.archive_name).join(&self.entry.name)
+    }
+}
+
+/// Creates the file surrogates for a zstandard-compressed .docx file such as `doc.docx.zst`, or,
+/// if `is_tar` is set, for each .docx entry of a zstandard-compressed tar archive such as
+/// `docs.tar.zst`. Without the `zstd` feature, a single surrogate is returned whose contents
+/// cannot be read, so that the file is reported as an error rather than silently skipped.
+///
+/// # Errors
+///
+/// Will return an error if a `.tar.zst` archive cannot be decompressed or is not a valid tar.
+pub(crate) fn zst_surrogates(fname: &str, is_tar: bool) -> anyhow::Result<Vec<FileLike>> {
+    if cfg!(feature = "zstd") && is_tar {
+        let tar: Arc<[u8]> = decompress(fname)?.into();
+        let entries = tar_docx_entries(&tar).with_context(|| format!("Invalid tar: {}", fname))?;
+        return Ok(entries
+            .into_iter()
+            .map(|entry| -> FileLike {
+                Arc::new(TarZstEntry {
+                    archive_name: fname.to_string(),
+                    tar: Arc::clone(&tar),
+                    entry,
+                })
+            })
+            .collect());
+    }
+    Ok(vec![Arc::new(ZstFile {
+        fname: fname.to_string(),
+    })])
+}
+
+#[cfg(feature = "zstd")]
+fn decompress(fname: &str) -> anyhow::Result<Vec<u8>> {
+    let file =
+        std::fs::File::open(fname).with_context(|| format!("Failed to open file: {}", fname))?;
+    zstd::decode_all(file).with_context(|| format!("Failed to decompress: {}", fname))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn decompress(fname: &str) -> anyhow::Result<Vec<u8>> {
+    anyhow::bail!(
+        "Cannot search {}: docread was built without the `zstd` feature",
+        fname
+    )
+}
+
+/// A regular file stored in a tar archive.
+#[derive(Debug, PartialEq)]
+struct TarEntry {
+    name: String,
+    /// Offset of the entry's data in the archive
+    start: usize,
+    size: usize,
+    /// Modification time recorded in the entry's header
+    modified: SystemTime,
+}
+
+/// Lists the .docx files in the uncompressed tar archive `tar`. Both ustar name prefixes and
+/// GNU long names are understood; other extended headers are skipped.
+fn tar_docx_entries(tar: &[u8]) -> anyhow::Result<Vec<TarEntry>> {
+    let mut entries = Vec::new();
+    let mut long_name: Option<String> = None;
+    let mut offset = 0;
+    while offset + TAR_BLOCK <= tar.len() {
+        let header = &tar[offset..offset + TAR_BLOCK];
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        let size = parse_octal(&header[124..136])
+            .with_context(|| format!("Invalid size in tar header at offset {}", offset))?;
+        let modified = parse_octal(&header[136..148]).map_or(UNIX_EPOCH, |seconds| {
+            UNIX_EPOCH + Duration::from_secs(seconds as u64)
+        });
+        let start = offset + TAR_BLOCK;
+        if start + size > tar.len() {
+            anyhow::bail!("Truncated tar entry at offset {}", offset);
+        }
+        let name = match long_name.take() {
+            Some(name) => name,
+            None if &header[257..262] == b"ustar" && header[345] != 0 => {
+                format!("{}/{}", cstr(&header[345..500]), cstr(&header[..100]))
+            }
+            None => cstr(&header[..100]),
+        };
+        match header[156] {
+            b'0' | 0 if name.ends_with(".docx") && !name.contains("__MACOSX") => {
+                entries.push(TarEntry {
+                    name,
+                    start,
+                    size,
+                    modified,
+                });
+            }
+            b'L' => long_name = Some(cstr(&tar[start..start + size])),
+            _ => {}
+        }
+        offset = start + size.div_ceil(TAR_BLOCK) * TAR_BLOCK;
+    }
+    Ok(entries)
+}
+
+/// Returns the text of a NUL-terminated tar header field.
+fn cstr(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// Parses a tar header number: octal digits padded with spaces or NULs.
+fn parse_octal(field: &[u8]) -> Option<usize> {
+    let digits = cstr(field);
+    usize::from_str_radix(digits.trim(), 8).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds an uncompressed tar archive holding `files`, as (name, contents) pairs.
+    fn make_tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut tar = Vec::new();
+        for (name, contents) in files {
+            let mut header = [0u8; TAR_BLOCK];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[124..135].copy_from_slice(format!("{:011o}", contents.len()).as_bytes());
+            header[136..147].copy_from_slice(format!("{:011o}", 1_700_000_000).as_bytes());
+            header[156] = b'0';
+            header[257..263].copy_from_slice(b"ustar\0");
+            tar.extend_from_slice(&header);
+            tar.extend_from_slice(contents);
+            tar.resize(tar.len().div_ceil(TAR_BLOCK) * TAR_BLOCK, 0);
+        }
+        tar.resize(tar.len() + 2 * TAR_BLOCK, 0);
+        tar
+    }
+
+    #[test]
+    fn test_tar_docx_entries() -> anyhow::Result<()> {
+        let tar = make_tar(&[
+            ("a.docx", b"first"),
+            ("notes.txt", b"skipped"),
+            ("sub/b.docx", &[7u8; 600]),
+        ]);
+        let entries = tar_docx_entries(&tar)?;
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, vec!["a.docx", "sub/b.docx"]);
+        assert_eq!(&tar[entries[0].start..][..entries[0].size], b"first");
+        assert_eq!(entries[1].size, 600);
+        assert_eq!(
+            entries[1].modified,
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+        );
+        assert!(tar_docx_entries(&tar[..TAR_BLOCK + 2]).is_err());
+        Ok(())
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn test_zst_without_feature() -> anyhow::Result<()> {
+        let surrogates = zst_surrogates("docs.tar.zst", true)?;
+        assert_eq!(surrogates.len(), 1);
+        let err = surrogates[0].read_into_buf().unwrap_err();
+        assert!(err.to_string().contains("without the `zstd` feature"));
+        Ok(())
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zst_surrogates() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let docx = std::fs::read("resources/testdoc.docx")?;
+        let single = dir.path().join("testdoc.docx.zst");
+        std::fs::write(&single, zstd::encode_all(&docx[..], 0)?)?;
+        let surrogates = zst_surrogates(single.to_str().unwrap(), false)?;
+        assert_eq!(surrogates[0].read_into_buf()?, docx);
+
+        let archive = dir.path().join("docs.tar.zst");
+        let tar = make_tar(&[("testdoc.docx", &docx), ("other.txt", b"skipped")]);
+        std::fs::write(&archive, zstd::encode_all(&tar[..], 0)?)?;
+        let surrogates = zst_surrogates(archive.to_str().unwrap(), true)?;
+        assert_eq!(surrogates.len(), 1);
+        assert_eq!(surrogates[0].read_into_buf()?, docx);
+        Ok(())
+    }
+}